@@ -0,0 +1,87 @@
+use std::mem;
+
+use crate::{point3::Point3, ray::Ray};
+
+// Axis-aligned bounding box.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Aabb {
+    pub min: Point3,
+    pub max: Point3,
+}
+
+impl Aabb {
+    pub const fn new(min: &Point3, max: &Point3) -> Self {
+        Self {
+            min: *min,
+            max: *max,
+        }
+    }
+
+    // Contains nothing. The union of an empty box with any box `b` is `b`.
+    pub const fn empty() -> Self {
+        Self::new(
+            &Point3::new(f64::INFINITY, f64::INFINITY, f64::INFINITY),
+            &Point3::new(f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY),
+        )
+    }
+
+    pub fn union(&self, other: &Self) -> Self {
+        Self::new(
+            &Point3::new(
+                self.min.x.min(other.min.x),
+                self.min.y.min(other.min.y),
+                self.min.z.min(other.min.z),
+            ),
+            &Point3::new(
+                self.max.x.max(other.max.x),
+                self.max.y.max(other.max.y),
+                self.max.z.max(other.max.z),
+            ),
+        )
+    }
+
+    pub fn union_point(&self, p: &Point3) -> Self {
+        self.union(&Self::new(p, p))
+    }
+
+    pub fn centroid(&self) -> Point3 {
+        Point3::new(
+            0.5 * (self.min.x + self.max.x),
+            0.5 * (self.min.y + self.max.y),
+            0.5 * (self.min.z + self.max.z),
+        )
+    }
+
+    pub fn surface_area(&self) -> f64 {
+        let d = self.max - self.min;
+        if d.x < 0. || d.y < 0. || d.z < 0. {
+            return 0.;
+        }
+        2. * d.x.mul_add(d.y, d.y.mul_add(d.z, d.z * d.x))
+    }
+
+    // Slab test. Returns true if the ray passes through the box somewhere in
+    // the interval [t_min, t_max]. Flat boxes (e.g. around an axis-aligned
+    // triangle) still count as hit.
+    pub fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> bool {
+        self.clip(ray, t_min, t_max).is_some()
+    }
+
+    // The part of [t_min, t_max] in which the ray is inside the box.
+    pub fn clip(&self, ray: &Ray, mut t_min: f64, mut t_max: f64) -> Option<(f64, f64)> {
+        for axis in 0..3 {
+            let inv_d = 1. / ray.direction[axis];
+            let mut t0 = (self.min[axis] - ray.origin[axis]) * inv_d;
+            let mut t1 = (self.max[axis] - ray.origin[axis]) * inv_d;
+            if inv_d < 0. {
+                mem::swap(&mut t0, &mut t1);
+            }
+            t_min = if t0 > t_min { t0 } else { t_min };
+            t_max = if t1 < t_max { t1 } else { t_max };
+            if t_max < t_min {
+                return None;
+            }
+        }
+        Some((t_min, t_max))
+    }
+}
@@ -0,0 +1,442 @@
+use std::sync::Arc;
+
+use crate::{
+    aabb::Aabb,
+    hit::{Emitters, Hittable, HittableObjects, Record},
+    point3::Point3,
+    ray::Ray,
+    sampler::Sampler,
+    vec3::Vec3,
+};
+
+// Number of buckets used when estimating the surface area heuristic.
+const SAH_BUCKETS: usize = 16;
+
+// Estimated cost of a ray-box test, relative to a ray-object test.
+const TRAVERSAL_COST: f64 = 0.125;
+
+// Leaves are allowed to hold a few objects if splitting them isn't worth it.
+const MAX_LEAF_SIZE: usize = 4;
+
+// Bounds the size of the traversal stack.
+const MAX_TREE_DEPTH: usize = 64;
+
+// A bounding volume hierarchy over a set of objects. It's built once, using
+// the surface area heuristic (SAH) to pick splits, and then answers the same
+// queries as the `HittableObjects` it was built from. Its boxes cover wherever
+// moving objects go between the two times it's built for, normally the
+// camera's shutter interval.
+pub struct Bvh {
+    tree: Tree,
+    objects: Vec<Arc<dyn Hittable>>,
+    // Objects without a bounding box can't be placed in the tree, so they're
+    // tested against every ray.
+    unbounded: Vec<Arc<dyn Hittable>>,
+    emitters: Emitters,
+}
+
+// The tree itself, over primitives known only by their boxes. Its leaves hold
+// indices into the list the boxes came from, which a `Bvh` resolves to objects
+// and a `Mesh` to faces.
+pub struct Tree {
+    nodes: Vec<Node>,
+    indices: Vec<usize>,
+}
+
+enum Node {
+    Leaf {
+        bbox: Aabb,
+        first: usize,
+        count: usize,
+    },
+    // The left child always immediately follows its parent.
+    Interior {
+        bbox: Aabb,
+        right: usize,
+        axis: usize,
+    },
+}
+
+struct Primitive {
+    index: usize,
+    bbox: Aabb,
+    centroid: Point3,
+}
+
+#[derive(Clone, Copy)]
+struct Bucket {
+    bbox: Aabb,
+    count: usize,
+}
+
+struct Split {
+    axis: usize,
+    bucket: usize,
+    cost: f64,
+}
+
+impl Bvh {
+    pub fn new(objects: HittableObjects, time0: f64, time1: f64) -> Self {
+        let mut emitters = Emitters::default();
+        let mut bounded = Vec::new();
+        let mut bboxes = Vec::new();
+        let mut unbounded = Vec::new();
+        for object in objects.into_objects() {
+            emitters.push(&object);
+            match object.bounding_box(time0, time1) {
+                Some(bbox) => {
+                    bounded.push(object);
+                    bboxes.push(bbox);
+                }
+                None => unbounded.push(object),
+            }
+        }
+
+        Self {
+            tree: Tree::new(&bboxes),
+            objects: bounded,
+            unbounded,
+            emitters,
+        }
+    }
+
+    // Calls `visit` on every object whose bounds the ray passes through
+    // between `t_min` and `t_max`, as `Tree::traverse` does.
+    fn traverse<F>(&self, ray: &Ray, t_min: f64, mut t_max: f64, mut visit: F)
+    where
+        F: FnMut(&dyn Hittable, f64) -> f64,
+    {
+        for o in &self.unbounded {
+            t_max = visit(o.as_ref(), t_max);
+        }
+        self.tree.traverse(ray, t_min, t_max, |i, t_max| {
+            visit(self.objects[i].as_ref(), t_max)
+        });
+    }
+}
+
+impl Tree {
+    pub fn new(bboxes: &[Aabb]) -> Self {
+        let mut tree = Self {
+            nodes: Vec::new(),
+            indices: Vec::with_capacity(bboxes.len()),
+        };
+        let mut prims: Vec<_> = bboxes
+            .iter()
+            .enumerate()
+            .map(|(index, bbox)| Primitive {
+                index,
+                bbox: *bbox,
+                centroid: bbox.centroid(),
+            })
+            .collect();
+        if !prims.is_empty() {
+            tree.build(&mut prims, 0);
+        }
+        tree
+    }
+
+    // The box around everything, or `None` if the tree is empty.
+    pub fn bounding_box(&self) -> Option<Aabb> {
+        self.nodes.first().map(|n| match n {
+            Node::Leaf { bbox, .. } | Node::Interior { bbox, .. } => *bbox,
+        })
+    }
+
+    // Recursively builds the subtree for `prims`, returning its node index.
+    fn build(&mut self, prims: &mut [Primitive], depth: usize) -> usize {
+        let bbox = prims.iter().fold(Aabb::empty(), |b, p| b.union(&p.bbox));
+        let index = self.nodes.len();
+
+        let mid = if prims.len() == 1 || depth + 1 >= MAX_TREE_DEPTH {
+            None
+        } else {
+            Self::partition(prims, &bbox)
+        };
+
+        match mid {
+            Some((mid, axis)) => {
+                // Reserve this slot; the real node is written once the
+                // children's indices are known.
+                self.nodes.push(Node::Leaf {
+                    bbox,
+                    first: 0,
+                    count: 0,
+                });
+                self.build(&mut prims[..mid], depth + 1);
+                let right = self.build(&mut prims[mid..], depth + 1);
+                self.nodes[index] = Node::Interior { bbox, right, axis };
+            }
+            None => {
+                let first = self.indices.len();
+                self.indices.extend(prims.iter().map(|p| p.index));
+                self.nodes.push(Node::Leaf {
+                    bbox,
+                    first,
+                    count: prims.len(),
+                });
+            }
+        }
+
+        index
+    }
+
+    // Reorders `prims` around the cheapest SAH split, returning the split
+    // point and axis. Returns `None` if a leaf would be cheaper.
+    fn partition(prims: &mut [Primitive], bbox: &Aabb) -> Option<(usize, usize)> {
+        let centroid_bounds = prims
+            .iter()
+            .fold(Aabb::empty(), |b, p| b.union_point(&p.centroid));
+
+        let mut best: Option<Split> = None;
+        for axis in 0..3 {
+            let lo = centroid_bounds.min[axis];
+            let extent = centroid_bounds.max[axis] - lo;
+            if extent <= 0. {
+                continue;
+            }
+
+            let mut buckets = [Bucket {
+                bbox: Aabb::empty(),
+                count: 0,
+            }; SAH_BUCKETS];
+            for p in prims.iter() {
+                let b = &mut buckets[bucket_index(p.centroid[axis], lo, extent)];
+                b.bbox = b.bbox.union(&p.bbox);
+                b.count += 1;
+            }
+
+            // Sweep from the right to get the cost of every right-hand side,
+            // then from the left to combine them.
+            let mut right_area = [0.; SAH_BUCKETS];
+            let mut right_count = [0; SAH_BUCKETS];
+            let mut acc = Bucket {
+                bbox: Aabb::empty(),
+                count: 0,
+            };
+            for i in (1..SAH_BUCKETS).rev() {
+                acc.bbox = acc.bbox.union(&buckets[i].bbox);
+                acc.count += buckets[i].count;
+                right_area[i] = acc.bbox.surface_area();
+                right_count[i] = acc.count;
+            }
+
+            let mut acc = Bucket {
+                bbox: Aabb::empty(),
+                count: 0,
+            };
+            for i in 0..SAH_BUCKETS - 1 {
+                acc.bbox = acc.bbox.union(&buckets[i].bbox);
+                acc.count += buckets[i].count;
+                if acc.count == 0 || right_count[i + 1] == 0 {
+                    continue;
+                }
+                #[allow(clippy::cast_precision_loss)]
+                let cost = (acc.count as f64).mul_add(
+                    acc.bbox.surface_area(),
+                    right_count[i + 1] as f64 * right_area[i + 1],
+                );
+                if best.as_ref().is_none_or(|b| cost < b.cost) {
+                    best = Some(Split {
+                        axis,
+                        bucket: i,
+                        cost,
+                    });
+                }
+            }
+        }
+
+        let best = best?;
+        let area = bbox.surface_area();
+        #[allow(clippy::cast_precision_loss)]
+        let leaf_cost = prims.len() as f64;
+        let split_cost = if area > 0. {
+            TRAVERSAL_COST + best.cost / area
+        } else {
+            TRAVERSAL_COST
+        };
+        if prims.len() <= MAX_LEAF_SIZE && split_cost >= leaf_cost {
+            return None;
+        }
+
+        let lo = centroid_bounds.min[best.axis];
+        let extent = centroid_bounds.max[best.axis] - lo;
+        let mut mid = 0;
+        for i in 0..prims.len() {
+            if bucket_index(prims[i].centroid[best.axis], lo, extent) <= best.bucket {
+                prims.swap(i, mid);
+                mid += 1;
+            }
+        }
+
+        Some((mid, best.axis))
+    }
+}
+
+fn bucket_index(c: f64, lo: f64, extent: f64) -> usize {
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    let b = (SAH_BUCKETS as f64 * (c - lo) / extent) as usize;
+    b.min(SAH_BUCKETS - 1)
+}
+
+impl Tree {
+    // Calls `visit` on the index of every primitive whose box the ray passes
+    // through between `t_min` and `t_max`, nearer subtrees first. `visit` gets
+    // the current `t_max` and returns the new one, so that a hit can cull
+    // everything behind it.
+    pub fn traverse<F>(&self, ray: &Ray, t_min: f64, mut t_max: f64, mut visit: F)
+    where
+        F: FnMut(usize, f64) -> f64,
+    {
+        if self.nodes.is_empty() {
+            return;
+        }
+
+        let mut stack = [0; MAX_TREE_DEPTH];
+        let mut len = 1;
+        while len > 0 {
+            len -= 1;
+            match self.nodes[stack[len]] {
+                Node::Leaf { bbox, first, count } => {
+                    if !bbox.hit(ray, t_min, t_max) {
+                        continue;
+                    }
+                    for &i in &self.indices[first..first + count] {
+                        t_max = visit(i, t_max);
+                    }
+                }
+                Node::Interior { bbox, right, axis } => {
+                    if !bbox.hit(ray, t_min, t_max) {
+                        continue;
+                    }
+                    // Visit the nearer child first, so that the farther one
+                    // can often be culled by `t_max`.
+                    let left = stack[len] + 1;
+                    if ray.direction[axis] < 0. {
+                        stack[len] = left;
+                        stack[len + 1] = right;
+                    } else {
+                        stack[len] = right;
+                        stack[len + 1] = left;
+                    }
+                    len += 2;
+                }
+            }
+        }
+    }
+}
+
+impl Hittable for Bvh {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64, sampler: &mut Sampler) -> Option<Record> {
+        let mut hit_record = None;
+        self.traverse(ray, t_min, t_max, |o, closest_so_far| {
+            match o.hit(ray, t_min, closest_so_far, sampler) {
+                Some(rec) => {
+                    let t = rec.t;
+                    hit_record = Some(rec);
+                    t
+                }
+                None => closest_so_far,
+            }
+        });
+        hit_record
+    }
+
+    fn hit_surface(
+        &self,
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+        sampler: &mut Sampler,
+    ) -> Option<Record> {
+        let mut hit_record = None;
+        self.traverse(ray, t_min, t_max, |o, closest_so_far| {
+            match o.hit_surface(ray, t_min, closest_so_far, sampler) {
+                Some(rec) => {
+                    let t = rec.t;
+                    hit_record = Some(rec);
+                    t
+                }
+                None => closest_so_far,
+            }
+        });
+        hit_record
+    }
+
+    fn transmittance(&self, ray: &Ray, t_min: f64, t_max: f64, sampler: &mut Sampler) -> f64 {
+        let mut transmittance = 1.;
+        self.traverse(ray, t_min, t_max, |o, t_max| {
+            transmittance *= o.transmittance(ray, t_min, t_max, sampler);
+            t_max
+        });
+        transmittance
+    }
+
+    // This is the box the tree was built with, whatever the times asked for.
+    fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<Aabb> {
+        if !self.unbounded.is_empty() {
+            return None;
+        }
+        self.tree.bounding_box()
+    }
+
+    // Like `HittableObjects`, it samples its emissive objects, not the tree.
+    fn is_emissive(&self) -> bool {
+        !self.emitters.is_empty()
+    }
+
+    fn sample_direction(&self, origin: &Point3, sampler: &mut Sampler) -> Vec3 {
+        self.emitters.sample_direction(origin, sampler)
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f64 {
+        self.emitters.pdf_value(origin, direction)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::Bvh;
+    use crate::{
+        hit::{Hittable, HittableObjects},
+        material::Blank,
+        point3::Point3,
+        ray::Ray,
+        sampler::Sampler,
+        sphere::Sphere,
+        vec3::Vec3,
+    };
+
+    #[test]
+    fn matches_linear_search() {
+        let mut objects = HittableObjects::new();
+        for i in -5..5 {
+            for j in -5..5 {
+                let center = Point3::new(f64::from(i), 0.3 * f64::from(j % 3), f64::from(j));
+                objects.add(Sphere::new(&center, 0.4, Arc::new(Blank::new())));
+            }
+        }
+        let mut linear = HittableObjects::new();
+        linear.extend(objects.objects().iter().cloned());
+        let bvh = Bvh::new(objects, 0., 0.);
+        let mut sampler = Sampler::new(0);
+
+        for i in 0..200 {
+            let a = f64::from(i) * 0.1;
+            let ray = Ray {
+                origin: Point3::new(10. * a.cos(), 3., 10. * a.sin()),
+                direction: Vec3::new(-a.cos() + 0.05 * a.sin(), -0.3, -a.sin()),
+                time: 0.,
+            };
+            let want = linear
+                .hit(&ray, 0.001, f64::INFINITY, &mut sampler)
+                .map(|r| r.t);
+            let got = bvh
+                .hit(&ray, 0.001, f64::INFINITY, &mut sampler)
+                .map(|r| r.t);
+            assert_eq!(want, got);
+        }
+    }
+}
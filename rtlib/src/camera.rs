@@ -1,4 +1,6 @@
-use crate::{point3::Point3, ray::Ray, vec3::Vec3};
+use rand::Rng;
+
+use crate::{point3::Point3, ray::Ray, sampler::Sampler, vec3::Vec3};
 
 pub struct Camera {
     pub aspect_ratio: f64,
@@ -12,6 +14,9 @@ pub struct Camera {
     u: Vec3,
     v: Vec3,
     lens_radius: f64,
+    // Rays are cast at times spread uniformly over [time0, time1].
+    time0: f64,
+    time1: f64,
 }
 
 impl Camera {
@@ -50,19 +55,80 @@ impl Camera {
             u,
             v,
             lens_radius,
+            time0: 0.,
+            time1: 0.,
         }
     }
+
+    // Opens the shutter from `time0` to `time1`, instead of for an instant
+    // at time 0.
+    pub const fn with_shutter(mut self, time0: f64, time1: f64) -> Self {
+        self.time0 = time0;
+        self.time1 = time1;
+        self
+    }
 }
 
 impl Camera {
-    pub fn get_ray(&self, s: f64, t: f64) -> Ray {
-        let rd = self.lens_radius * Vec3::rand_in_unit_disk();
+    pub fn get_ray(&self, s: f64, t: f64, sampler: &mut Sampler) -> Ray {
+        let rd = self.lens_radius * Vec3::rand_in_unit_disk(sampler);
         let offset = self.u * rd.x + self.v * rd.y;
+        // An instantaneous shutter needs no random number, so that still
+        // images come out the same as ever.
+        let time = if self.time1 > self.time0 {
+            sampler.gen_range(self.time0..self.time1)
+        } else {
+            self.time0
+        };
         Ray {
             origin: self.origin + offset,
             direction: self.lower_left_corner + s * self.horizontal + t * self.vertical
                 - self.origin
                 - offset,
+            time,
+        }
+    }
+}
+
+// Everything needed to position a camera, short of the image's aspect ratio.
+#[derive(Clone)]
+pub struct Settings {
+    pub look_from: Point3,
+    pub look_at: Point3,
+    pub v_up: Vec3,
+    pub vfov: f64, // degrees.
+    pub aperture: f64,
+    pub focus_dist: f64,
+    pub shutter_open: f64,
+    pub shutter_close: f64,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            look_from: Point3::new(13., 2., 3.),
+            look_at: Point3::new(0., 0., 0.),
+            v_up: Vec3::new(0., 1., 0.),
+            vfov: 20.,
+            aperture: 0.1,
+            focus_dist: 10.,
+            shutter_open: 0.,
+            shutter_close: 0.,
         }
     }
 }
+
+impl Settings {
+    pub fn build(&self, aspect_ratio: f64) -> Camera {
+        Camera::new(
+            &self.look_from,
+            &self.look_at,
+            &self.v_up,
+            self.vfov,
+            aspect_ratio,
+            self.aperture,
+            self.focus_dist,
+        )
+        .with_shutter(self.shutter_open, self.shutter_close)
+    }
+}
@@ -2,7 +2,9 @@ use std::ops;
 
 use rand::Rng;
 
-#[derive(Clone, Copy, Default)]
+use crate::sampler::Sampler;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
 pub struct Color {
     pub r: f64,
     pub g: f64,
@@ -15,8 +17,7 @@ impl Color {
     }
 
     // Element-wise bounded [0, 1).
-    pub fn rand() -> Self {
-        let mut rng = rand::thread_rng();
+    pub fn rand(rng: &mut Sampler) -> Self {
         Self {
             r: rng.gen(),
             g: rng.gen(),
@@ -26,8 +27,7 @@ impl Color {
 
     // Element-wise bounded [a, b). Panics if impossible.
     // TODO(clfs) Eliminate panics.
-    pub fn rand_in(lo: f64, hi: f64) -> Self {
-        let mut rng = rand::thread_rng();
+    pub fn rand_in(lo: f64, hi: f64, rng: &mut Sampler) -> Self {
         Self {
             r: rng.gen_range(lo..hi),
             g: rng.gen_range(lo..hi),
@@ -35,15 +35,26 @@ impl Color {
         }
     }
 
-    pub fn to_rgb(&self, samples_per_pixel: u32) -> [u8; 3] {
-        let scale = 1. / f64::from(samples_per_pixel);
+    // Relative luminance, for linear Rec. 709 primaries.
+    pub fn luminance(&self) -> f64 {
+        0.2126 * self.r + 0.7152 * self.g + 0.0722 * self.b
+    }
+
+    // Encodes display-linear values with the sRGB transfer function, clipping
+    // them to [0, 1].
+    pub fn to_rgb(&self) -> [u8; 3] {
         // TODO(cfiguereosupran) Eliminate these clippy allows.
-        #[allow(clippy::cast_possible_truncation, clippy::clippy::cast_sign_loss)]
-        [
-            (256. * (self.r * scale).sqrt().max(0.).min(0.999)) as u8,
-            (256. * (self.g * scale).sqrt().max(0.).min(0.999)) as u8,
-            (256. * (self.b * scale).sqrt().max(0.).min(0.999)) as u8,
-        ]
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let encode = |x: f64| (255. * srgb_oetf(x.clamp(0., 1.))).round() as u8;
+        [encode(self.r), encode(self.g), encode(self.b)]
+    }
+}
+
+fn srgb_oetf(x: f64) -> f64 {
+    if x <= 0.003_130_8 {
+        12.92 * x
+    } else {
+        1.055f64.mul_add(x.powf(1. / 2.4), -0.055)
     }
 }
 
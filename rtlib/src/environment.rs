@@ -0,0 +1,294 @@
+// What rays see once they leave the scene: light arriving from infinitely far
+// away, in every direction.
+
+use std::{
+    f64::consts::PI,
+    ffi::OsStr,
+    fs::File,
+    io::{self, BufReader},
+    path::Path,
+};
+
+use crate::{color::Color, framebuffer::Framebuffer, hdr, vec3::Vec3};
+
+pub trait Environment: Send + Sync {
+    // Radiance arriving from `direction`, a unit vector pointing away from the
+    // scene.
+    fn radiance(&self, direction: &Vec3) -> Color;
+
+    // Picks a direction to look for light in, given two numbers uniformly
+    // distributed in [0, 1), and the density per unit solid angle with which
+    // it was picked. `None` if the environment is smooth enough that sampling
+    // the BSDF finds its light well anyway.
+    fn sample(&self, _u: [f64; 2]) -> Option<(Vec3, f64)> {
+        None
+    }
+
+    // Density with which `sample` picks `direction`.
+    fn pdf(&self, _direction: &Vec3) -> f64 {
+        0.
+    }
+}
+
+// A sky that blends from `bottom`, straight down, to `top`, straight up. The
+// default is the book's white-to-blue sky.
+pub struct Gradient {
+    pub bottom: Color,
+    pub top: Color,
+}
+
+impl Gradient {
+    pub const fn new(bottom: &Color, top: &Color) -> Self {
+        Self {
+            bottom: *bottom,
+            top: *top,
+        }
+    }
+}
+
+impl Default for Gradient {
+    fn default() -> Self {
+        Self::new(&Color::new(1., 1., 1.), &Color::new(0.5, 0.7, 1.))
+    }
+}
+
+impl Environment for Gradient {
+    fn radiance(&self, direction: &Vec3) -> Color {
+        let t = 0.5 * (direction.y + 1.);
+        (1. - t) * self.bottom + t * self.top
+    }
+}
+
+// A panorama in the equirectangular (latitude-longitude) layout. The top row
+// is straight up (+y) and the bottom row straight down. Across the middle,
+// from left to right, it looks toward -x, -z (the center) and +x, and the
+// left and right edges meet behind, at +z.
+pub struct EnvironmentMap {
+    image: Framebuffer,
+    // Degrees to turn the map about the y axis, in the same sense as a
+    // `rotate_y` transform.
+    pub rotation: f64,
+    // Scales the map's radiance.
+    pub intensity: f64,
+    // For importance sampling: the running total of each pixel's weight,
+    // which is its luminance times the solid angle it covers.
+    cdf: Vec<f64>,
+}
+
+impl EnvironmentMap {
+    // Panics if the image is empty.
+    pub fn new(image: Framebuffer) -> Self {
+        assert!(
+            image.width > 0 && image.height > 0,
+            "environment maps can't be empty"
+        );
+        let (width, height) = (image.width as usize, image.height as usize);
+        let mut cdf = Vec::with_capacity(width * height + 1);
+        let mut total = 0.;
+        cdf.push(total);
+        for (y, row) in image.rows().enumerate() {
+            // Rows near the poles cover less of the sphere.
+            #[allow(clippy::cast_precision_loss)]
+            let sin_theta = (PI * (y as f64 + 0.5) / height as f64).sin();
+            for c in row {
+                total += c.luminance().max(0.) * sin_theta;
+                cdf.push(total);
+            }
+        }
+        Self {
+            image,
+            rotation: 0.,
+            intensity: 1.,
+            cdf,
+        }
+    }
+
+    // Loads a Radiance (.hdr) or OpenEXR (.exr) file, by its extension.
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let extension = path
+            .extension()
+            .and_then(OsStr::to_str)
+            .map(str::to_ascii_lowercase);
+        let open = || File::open(path).map(BufReader::new);
+        let image = match extension.as_deref() {
+            Some("hdr") => hdr::read_radiance(open()?)?,
+            Some("exr") => hdr::read_exr(&mut open()?)?,
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "environment maps must be .hdr or .exr files",
+                ))
+            }
+        };
+        if image.width == 0 || image.height == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "environment map is empty",
+            ));
+        }
+        Ok(Self::new(image))
+    }
+
+    // The pixel seen along `direction`, and the sine of its polar angle.
+    #[allow(
+        clippy::cast_precision_loss,
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss
+    )]
+    fn pixel(&self, direction: &Vec3) -> (usize, f64) {
+        let phi = direction.x.atan2(-direction.z) + self.rotation.to_radians();
+        let u = (phi / (2. * PI) + 0.5).rem_euclid(1.);
+        let theta = direction.y.clamp(-1., 1.).acos();
+        let v = theta / PI;
+
+        let (width, height) = (self.image.width as usize, self.image.height as usize);
+        let x = ((u * width as f64) as usize).min(width - 1);
+        let y = ((v * height as f64) as usize).min(height - 1);
+        (y * width + x, theta.sin())
+    }
+
+    fn total(&self) -> f64 {
+        self.cdf[self.cdf.len() - 1]
+    }
+}
+
+impl Environment for EnvironmentMap {
+    fn radiance(&self, direction: &Vec3) -> Color {
+        let (i, _) = self.pixel(direction);
+        self.intensity * self.image.pixels[i]
+    }
+
+    // Picks a pixel in proportion to its weight, and then a point in it.
+    #[allow(clippy::cast_precision_loss)]
+    fn sample(&self, u: [f64; 2]) -> Option<(Vec3, f64)> {
+        let total = self.total();
+        if total <= 0. {
+            return None;
+        }
+        let target = u[0] * total;
+        let i = self
+            .cdf
+            .partition_point(|&c| c <= target)
+            .clamp(1, self.cdf.len() - 1)
+            - 1;
+        let (lo, hi) = (self.cdf[i], self.cdf[i + 1]);
+        let within = if hi > lo {
+            (target - lo) / (hi - lo)
+        } else {
+            0.5
+        };
+
+        let width = self.image.width as usize;
+        let (x, y) = (i % width, i / width);
+        let s = (x as f64 + within) / width as f64;
+        let t = (y as f64 + u[1]) / f64::from(self.image.height);
+
+        let phi = (s - 0.5).mul_add(2. * PI, -self.rotation.to_radians());
+        let theta = t * PI;
+        let direction = Vec3::new(
+            theta.sin() * phi.sin(),
+            theta.cos(),
+            -theta.sin() * phi.cos(),
+        );
+        let pdf = self.pdf(&direction);
+        Some((direction, pdf)).filter(|_| pdf > 0.)
+    }
+
+    // Pixels are picked in proportion to their weight, and then points
+    // uniformly in (u, v), which maps onto 2π² sin θ of solid angle per unit
+    // area.
+    fn pdf(&self, direction: &Vec3) -> f64 {
+        let total = self.total();
+        let (i, sin_theta) = self.pixel(direction);
+        if total <= 0. || sin_theta <= 0. {
+            return 0.;
+        }
+        let pixels = self.cdf.len() - 1;
+        #[allow(clippy::cast_precision_loss)]
+        let density = (self.cdf[i + 1] - self.cdf[i]) / total * pixels as f64;
+        density / (2. * PI * PI * sin_theta)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::PI;
+
+    use super::{Environment, EnvironmentMap};
+    use crate::{color::Color, framebuffer::Framebuffer, vec3::Vec3};
+
+    // Dim everywhere, except one bright pixel just above the horizon,
+    // straight ahead (toward -z).
+    fn bright_spot() -> EnvironmentMap {
+        let mut image = Framebuffer::new(16, 8);
+        for c in &mut image.pixels {
+            *c = Color::new(0.1, 0.1, 0.1);
+        }
+        image.pixels[3 * 16 + 8] = Color::new(1000., 1000., 1000.);
+        EnvironmentMap::new(image)
+    }
+
+    #[test]
+    fn looks_up_directions() {
+        let mut map = bright_spot();
+        let ahead = Vec3::new(0.05, 0.2, -1.).unit();
+        assert!(map.radiance(&ahead).r > 999.);
+        assert!(map.radiance(&-ahead).r < 1.);
+
+        // Turning the map like `rotate_y(90)` takes what was ahead to -x.
+        map.rotation = 90.;
+        map.intensity = 2.;
+        let left = Vec3::new(-1., 0.2, -0.05).unit();
+        assert!((map.radiance(&left).r - 2000.).abs() < 1e-9);
+        assert!(map.radiance(&ahead).r < 1.);
+    }
+
+    #[test]
+    #[should_panic(expected = "can't be empty")]
+    fn refuses_empty_images() {
+        EnvironmentMap::new(Framebuffer::new(16, 0));
+    }
+
+    #[test]
+    fn samples_in_proportion_to_brightness() {
+        let mut map = bright_spot();
+        map.rotation = 30.;
+        let n = 200;
+        let mut bright = 0;
+        for i in 0..n {
+            for j in 0..n {
+                let u = [
+                    (f64::from(i) + 0.5) / f64::from(n),
+                    (f64::from(j) + 0.5) / f64::from(n),
+                ];
+                let (direction, pdf) = map.sample(u).unwrap();
+                assert!((direction.mag() - 1.).abs() < 1e-9);
+                assert!((map.pdf(&direction) - pdf).abs() <= 1e-9 * pdf);
+                if map.radiance(&direction).r > 1. {
+                    bright += 1;
+                }
+            }
+        }
+        // Nearly all of the light is in the bright pixel.
+        assert!(f64::from(bright) / f64::from(n * n) > 0.98);
+
+        // Weighting by the density recovers the total light arriving, the
+        // same as uniform sampling over the sphere would.
+        let mut estimate = 0.;
+        for i in 0..n {
+            for j in 0..n {
+                let u = [
+                    (f64::from(i) + 0.5) / f64::from(n),
+                    (f64::from(j) + 0.5) / f64::from(n),
+                ];
+                let (direction, pdf) = map.sample(u).unwrap();
+                estimate += map.radiance(&direction).r / pdf / f64::from(n * n);
+            }
+        }
+        // The bright pixel spans polar angles from 3π/8 to π/2, and 1/16th
+        // of the way around.
+        let bright_area = 2. * PI / 16. * ((3. * PI / 8.).cos() - (PI / 2.).cos());
+        let expected = 0.1f64.mul_add(4. * PI, (1000. - 0.1) * bright_area);
+        assert!((estimate - expected).abs() < 0.01 * expected);
+    }
+}
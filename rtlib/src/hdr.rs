@@ -0,0 +1,595 @@
+// Readers and writers for high dynamic range image formats. Unlike the 8-bit
+// formats, these keep linear radiance as-is, including values above 1.0.
+
+use std::{
+    convert::TryFrom,
+    io::{self, BufRead, Read, Write},
+};
+
+use image::codecs::hdr::HdrDecoder;
+
+use crate::{color::Color, framebuffer::Framebuffer};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Precision {
+    Half,
+    Float,
+}
+
+// OpenEXR, as a single-part, uncompressed scanline image with R, G and B
+// channels.
+pub fn write_exr<W: Write>(w: &mut W, fb: &Framebuffer, precision: Precision) -> io::Result<()> {
+    let (pixel_type, channel_size) = match precision {
+        Precision::Half => (1_i32, 2_usize),
+        Precision::Float => (2_i32, 4_usize),
+    };
+
+    let mut header = Vec::new();
+    header.extend_from_slice(&[0x76, 0x2f, 0x31, 0x01]); // Magic number.
+    header.extend_from_slice(&2_i32.to_le_bytes()); // Version 2, scanline.
+
+    // Channels must be listed alphabetically.
+    let mut chlist = Vec::new();
+    for name in &["B", "G", "R"] {
+        chlist.extend_from_slice(name.as_bytes());
+        chlist.push(0);
+        chlist.extend_from_slice(&pixel_type.to_le_bytes());
+        chlist.extend_from_slice(&[0, 0, 0, 0]); // pLinear, reserved.
+        chlist.extend_from_slice(&1_i32.to_le_bytes()); // x sampling.
+        chlist.extend_from_slice(&1_i32.to_le_bytes()); // y sampling.
+    }
+    chlist.push(0);
+    exr_attribute(&mut header, "channels", "chlist", &chlist);
+
+    exr_attribute(&mut header, "compression", "compression", &[0]);
+
+    #[allow(clippy::cast_possible_wrap)]
+    let window: Vec<u8> = [0, 0, fb.width as i32 - 1, fb.height as i32 - 1]
+        .iter()
+        .flat_map(|v| v.to_le_bytes())
+        .collect();
+    exr_attribute(&mut header, "dataWindow", "box2i", &window);
+    exr_attribute(&mut header, "displayWindow", "box2i", &window);
+
+    exr_attribute(&mut header, "lineOrder", "lineOrder", &[0]); // Increasing y.
+    exr_attribute(
+        &mut header,
+        "pixelAspectRatio",
+        "float",
+        &1_f32.to_le_bytes(),
+    );
+    exr_attribute(&mut header, "screenWindowCenter", "v2f", &[0; 8]);
+    exr_attribute(
+        &mut header,
+        "screenWindowWidth",
+        "float",
+        &1_f32.to_le_bytes(),
+    );
+    header.push(0);
+
+    // One chunk per scanline: its y coordinate, its size, then each channel's
+    // values for the whole line.
+    let line_size = fb.width as usize * 3 * channel_size;
+    let chunk_size = 8 + line_size;
+    let table_size = 8 * fb.height as usize;
+    let first_chunk = header.len() + table_size;
+
+    w.write_all(&header)?;
+    for y in 0..fb.height as usize {
+        w.write_all(&((first_chunk + y * chunk_size) as u64).to_le_bytes())?;
+    }
+
+    let mut line = Vec::with_capacity(line_size);
+    for (y, row) in fb.rows().enumerate() {
+        line.clear();
+        let channels: [fn(&Color) -> f64; 3] = [|c| c.b, |c| c.g, |c| c.r];
+        for channel in &channels {
+            for c in row {
+                #[allow(clippy::cast_possible_truncation)]
+                let v = channel(c) as f32;
+                match precision {
+                    Precision::Half => line.extend_from_slice(&f32_to_f16(v).to_le_bytes()),
+                    Precision::Float => line.extend_from_slice(&v.to_le_bytes()),
+                }
+            }
+        }
+        #[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+        {
+            w.write_all(&(y as i32).to_le_bytes())?;
+            w.write_all(&(line_size as i32).to_le_bytes())?;
+        }
+        w.write_all(&line)?;
+    }
+
+    Ok(())
+}
+
+fn exr_attribute(header: &mut Vec<u8>, name: &str, kind: &str, value: &[u8]) {
+    header.extend_from_slice(name.as_bytes());
+    header.push(0);
+    header.extend_from_slice(kind.as_bytes());
+    header.push(0);
+    #[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+    header.extend_from_slice(&(value.len() as i32).to_le_bytes());
+    header.extend_from_slice(value);
+}
+
+// Converts to IEEE 754 half precision, rounding to nearest even.
+fn f32_to_f16(value: f32) -> u16 {
+    let bits = value.to_bits();
+    #[allow(clippy::cast_possible_truncation)]
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    #[allow(clippy::cast_possible_wrap)]
+    let exp = ((bits >> 23) & 0xff) as i32;
+    let mantissa = bits & 0x7f_ffff;
+
+    if exp == 0xff {
+        // Infinity stays infinity, and NaN stays NaN.
+        return sign | 0x7c00 | if mantissa == 0 { 0 } else { 0x200 };
+    }
+
+    let e = exp - 127 + 15;
+    if e >= 0x1f {
+        return sign | 0x7c00; // Too large; round to infinity.
+    }
+
+    let (half, dropped) = if e <= 0 {
+        // Subnormal in half precision (or too small, i.e. zero).
+        if e < -10 {
+            return sign;
+        }
+        #[allow(clippy::cast_sign_loss)]
+        let shift = (14 - e) as u32;
+        let m = mantissa | 0x80_0000;
+        (m >> shift, (m & ((1 << shift) - 1), 1 << (shift - 1)))
+    } else {
+        #[allow(clippy::cast_sign_loss)]
+        let half = ((e as u32) << 10) | (mantissa >> 13);
+        (half, (mantissa & 0x1fff, 0x1000))
+    };
+
+    // A carry out of the mantissa correctly bumps the exponent.
+    let (rest, halfway) = dropped;
+    let rounded = if rest > halfway || (rest == halfway && half & 1 == 1) {
+        half + 1
+    } else {
+        half
+    };
+    #[allow(clippy::cast_possible_truncation)]
+    let rounded = rounded as u16;
+    sign | rounded
+}
+
+// Converts from IEEE 754 half precision. Every half is exactly a float.
+fn f16_to_f32(half: u16) -> f32 {
+    let sign = u32::from(half >> 15) << 31;
+    let exponent = u32::from((half >> 10) & 0x1f);
+    let mantissa = u32::from(half & 0x3ff);
+    let magnitude = match exponent {
+        // Zero, or subnormal.
+        0 => {
+            let value = f32::from(half & 0x3ff) * 2_f32.powi(-24);
+            return if sign == 0 { value } else { -value };
+        }
+        0x1f => 0xff << 23 | mantissa << 13,
+        _ => (exponent + 127 - 15) << 23 | mantissa << 13,
+    };
+    f32::from_bits(sign | magnitude)
+}
+
+// Reads the same kind of OpenEXR file as `write_exr` writes: single-part,
+// uncompressed scanlines, in any pixel type. Colors come from the R, G and B
+// channels, or from Y for grayscale images; other channels are ignored.
+pub fn read_exr<R: Read>(r: &mut R) -> io::Result<Framebuffer> {
+    let mut bytes = Vec::new();
+    r.read_to_end(&mut bytes)?;
+    let mut input = Bytes::new(&bytes);
+
+    if input.take(4)? != [0x76, 0x2f, 0x31, 0x01] {
+        return Err(invalid("not an OpenEXR file"));
+    }
+    let version = input.i32()?;
+    // Tiled, deep and multi-part files.
+    if version & 0xff != 2 || version & 0x1a00 != 0 {
+        return Err(invalid(
+            "only single-part scanline OpenEXR files are supported",
+        ));
+    }
+
+    // (name, pixel type) for each channel, in the order they're stored.
+    let mut channels = Vec::new();
+    let mut window = None;
+    loop {
+        let name = input.name()?;
+        if name.is_empty() {
+            break;
+        }
+        let _kind = input.name()?;
+        let size = usize::try_from(input.i32()?).map_err(|_| invalid("bad attribute size"))?;
+        let mut value = Bytes::new(input.take(size)?);
+        match name {
+            "channels" => loop {
+                let channel = value.name()?;
+                if channel.is_empty() {
+                    break;
+                }
+                let pixel_type = value.i32()?;
+                value.take(4)?; // pLinear, reserved.
+                if value.i32()? != 1 || value.i32()? != 1 {
+                    return Err(invalid("subsampled channels are not supported"));
+                }
+                channels.push((channel.to_string(), pixel_type));
+            },
+            "compression" if value.take(1)? != [0] => {
+                return Err(invalid("only uncompressed OpenEXR files are supported"));
+            }
+            "dataWindow" => {
+                window = Some([value.i32()?, value.i32()?, value.i32()?, value.i32()?]);
+            }
+            _ => {}
+        }
+    }
+
+    let [x_min, y_min, x_max, y_max] = window.ok_or_else(|| invalid("missing dataWindow"))?;
+    let (width, height) = match (
+        u32::try_from(i64::from(x_max) - i64::from(x_min) + 1),
+        u32::try_from(i64::from(y_max) - i64::from(y_min) + 1),
+    ) {
+        (Ok(width), Ok(height)) if width > 0 && height > 0 => (width, height),
+        _ => return Err(invalid("empty dataWindow")),
+    };
+
+    // Where each channel's values start within a scanline, in bytes, and how
+    // to decode them.
+    let mut line_size = 0;
+    let mut layout = Vec::new();
+    for (name, pixel_type) in &channels {
+        let decode: fn(&[u8]) -> f64 = match pixel_type {
+            0 => |b| f64::from(u32::from_le_bytes([b[0], b[1], b[2], b[3]])),
+            1 => |b| f64::from(f16_to_f32(u16::from_le_bytes([b[0], b[1]]))),
+            2 => |b| f64::from(f32::from_le_bytes([b[0], b[1], b[2], b[3]])),
+            _ => return Err(invalid("unknown pixel type")),
+        };
+        let size = if *pixel_type == 1 { 2 } else { 4 };
+        layout.push((name.as_str(), line_size, size, decode));
+        line_size += size * width as usize;
+    }
+    let find = |name| layout.iter().find(|(n, ..)| *n == name);
+    let rgb = match (find("R"), find("G"), find("B"), find("Y")) {
+        (Some(r), Some(g), Some(b), _) => [r, g, b],
+        (_, _, _, Some(y)) => [y, y, y],
+        _ => return Err(invalid("no R, G and B or Y channels")),
+    };
+
+    // One scanline per chunk, found through the offset table.
+    let mut fb = Framebuffer::new(width, height);
+    for _ in 0..height {
+        let offset = usize::try_from(input.u64()?).map_err(|_| invalid("bad chunk offset"))?;
+        let mut chunk = Bytes::new(
+            bytes
+                .get(offset..)
+                .ok_or_else(|| invalid("bad chunk offset"))?,
+        );
+        let y = usize::try_from(i64::from(chunk.i32()?) - i64::from(y_min))
+            .ok()
+            .filter(|&y| y < height as usize)
+            .ok_or_else(|| invalid("scanline outside the dataWindow"))?;
+        if usize::try_from(chunk.i32()?).ok() != Some(line_size) {
+            return Err(invalid("wrong scanline size"));
+        }
+        let line = chunk.take(line_size)?;
+
+        let row = &mut fb.pixels[y * width as usize..(y + 1) * width as usize];
+        for (x, pixel) in row.iter_mut().enumerate() {
+            let [r, g, b] = rgb.map(|&(_, start, size, decode)| {
+                decode(&line[start + x * size..start + (x + 1) * size])
+            });
+            *pixel = Color::new(r, g, b);
+        }
+    }
+    Ok(fb)
+}
+
+// Reads a Radiance RGBE (.hdr) image.
+pub fn read_radiance<R: BufRead>(r: R) -> io::Result<Framebuffer> {
+    let decoder = HdrDecoder::new(r).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    let meta = decoder.metadata();
+    let pixels = decoder
+        .read_image_hdr()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    Ok(Framebuffer {
+        width: meta.width,
+        height: meta.height,
+        pixels: pixels
+            .iter()
+            .map(|p| Color::new(f64::from(p.0[0]), f64::from(p.0[1]), f64::from(p.0[2])))
+            .collect(),
+    })
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+// Little-endian values, read in order from a byte slice.
+struct Bytes<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Bytes<'a> {
+    const fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+
+    fn take(&mut self, n: usize) -> io::Result<&'a [u8]> {
+        if n > self.data.len() {
+            return Err(invalid("unexpected end of file"));
+        }
+        let (head, tail) = self.data.split_at(n);
+        self.data = tail;
+        Ok(head)
+    }
+
+    fn i32(&mut self) -> io::Result<i32> {
+        let b = self.take(4)?;
+        Ok(i32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn u64(&mut self) -> io::Result<u64> {
+        let mut b = [0; 8];
+        b.copy_from_slice(self.take(8)?);
+        Ok(u64::from_le_bytes(b))
+    }
+
+    // A null-terminated string.
+    fn name(&mut self) -> io::Result<&'a str> {
+        let end = self
+            .data
+            .iter()
+            .position(|&b| b == 0)
+            .ok_or_else(|| invalid("unexpected end of file"))?;
+        let name = std::str::from_utf8(&self.data[..end]).map_err(|_| invalid("bad name"))?;
+        self.data = &self.data[end + 1..];
+        Ok(name)
+    }
+}
+
+// Portable float map: a tiny header followed by raw little-endian floats,
+// with the bottom row first.
+pub fn write_pfm<W: Write>(w: &mut W, fb: &Framebuffer) -> io::Result<()> {
+    // A negative scale means little-endian.
+    write!(w, "PF\n{} {}\n-1.0\n", fb.width, fb.height)?;
+    let mut line = Vec::with_capacity(fb.width as usize * 12);
+    for row in fb.rows().rev() {
+        line.clear();
+        for c in row {
+            for v in &[c.r, c.g, c.b] {
+                #[allow(clippy::cast_possible_truncation)]
+                line.extend_from_slice(&(*v as f32).to_le_bytes());
+            }
+        }
+        w.write_all(&line)?;
+    }
+    Ok(())
+}
+
+// Radiance RGBE (.hdr), with run-length encoded scanlines where the format
+// allows it.
+pub fn write_radiance<W: Write>(w: &mut W, fb: &Framebuffer) -> io::Result<()> {
+    write!(
+        w,
+        "#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n",
+        fb.height, fb.width
+    )?;
+
+    let width = fb.width as usize;
+    let rle = (8..0x8000).contains(&width);
+    let mut component = Vec::with_capacity(width);
+    for row in fb.rows() {
+        let rgbe: Vec<[u8; 4]> = row.iter().map(to_rgbe).collect();
+        if !rle {
+            for p in &rgbe {
+                w.write_all(p)?;
+            }
+            continue;
+        }
+
+        #[allow(clippy::cast_possible_truncation)]
+        w.write_all(&[2, 2, (width >> 8) as u8, (width & 0xff) as u8])?;
+        for i in 0..4 {
+            component.clear();
+            component.extend(rgbe.iter().map(|p| p[i]));
+            write_rle(w, &component)?;
+        }
+    }
+    Ok(())
+}
+
+// Shared-exponent encoding: a mantissa per channel, and one exponent.
+fn to_rgbe(c: &Color) -> [u8; 4] {
+    let v = c.r.max(c.g).max(c.b);
+    if v.is_nan() || v <= 1e-32 {
+        return [0; 4];
+    }
+    #[allow(clippy::cast_possible_truncation)]
+    let mut e = v.log2().floor() as i32 + 1;
+    if v / 2_f64.powi(e) >= 1. {
+        e += 1;
+    }
+    let scale = 256. / 2_f64.powi(e);
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    let m = |x: f64| (x.max(0.) * scale) as u8;
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    [m(c.r), m(c.g), m(c.b), (e + 128).clamp(0, 255) as u8]
+}
+
+// Writes one component of a scanline, as a mix of runs (a count above 128,
+// then the repeated byte) and literals (a count up to 128, then the bytes).
+fn write_rle<W: Write>(w: &mut W, data: &[u8]) -> io::Result<()> {
+    const MIN_RUN: usize = 4;
+    let mut cur = 0;
+    while cur < data.len() {
+        // Find the next run that's long enough to be worth encoding.
+        let mut run_start = cur;
+        let mut run_len = 0;
+        let mut prev_run_len = 0;
+        while run_len < MIN_RUN && run_start < data.len() {
+            run_start += run_len;
+            prev_run_len = run_len;
+            run_len = 1;
+            while run_start + run_len < data.len()
+                && run_len < 127
+                && data[run_start] == data[run_start + run_len]
+            {
+                run_len += 1;
+            }
+        }
+
+        // A short run right before the long one is still worth a run.
+        if prev_run_len > 1 && prev_run_len == run_start - cur {
+            #[allow(clippy::cast_possible_truncation)]
+            w.write_all(&[128 + prev_run_len as u8, data[cur]])?;
+            cur = run_start;
+        }
+
+        while cur < run_start {
+            let n = (run_start - cur).min(128);
+            #[allow(clippy::cast_possible_truncation)]
+            w.write_all(&[n as u8])?;
+            w.write_all(&data[cur..cur + n])?;
+            cur += n;
+        }
+
+        if run_len >= MIN_RUN {
+            #[allow(clippy::cast_possible_truncation)]
+            w.write_all(&[128 + run_len as u8, data[run_start]])?;
+            cur += run_len;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        f16_to_f32, f32_to_f16, read_exr, read_radiance, to_rgbe, write_exr, write_pfm,
+        write_radiance, Precision,
+    };
+    use crate::{color::Color, framebuffer::Framebuffer};
+
+    fn test_image() -> Framebuffer {
+        let mut fb = Framebuffer::new(3, 2);
+        fb.pixels = vec![
+            Color::new(0., 0.5, 1.),
+            Color::new(2., 4., 8.),
+            Color::new(0.25, 0.125, 0.0625),
+            Color::new(100., 0., 0.),
+            Color::new(0., 3., 0.),
+            Color::new(1., 1., 1.),
+        ];
+        fb
+    }
+
+    #[test]
+    fn converts_to_half() {
+        assert_eq!(f32_to_f16(0.), 0);
+        assert_eq!(f32_to_f16(-0.), 0x8000);
+        assert_eq!(f32_to_f16(1.), 0x3c00);
+        assert_eq!(f32_to_f16(0.5), 0x3800);
+        assert_eq!(f32_to_f16(-2.), 0xc000);
+        assert_eq!(f32_to_f16(65504.), 0x7bff);
+        assert_eq!(f32_to_f16(1e6), 0x7c00);
+        assert_eq!(f32_to_f16(2_f32.powi(-24)), 0x0001);
+        assert_eq!(f32_to_f16(2_f32.powi(-14)), 0x0400);
+        // 1 + 2^-11 is halfway between two halves; ties go to even.
+        assert_eq!(f32_to_f16(1. + 2_f32.powi(-11)), 0x3c00);
+        assert_eq!(f32_to_f16(f32::INFINITY), 0x7c00);
+        assert!(f32_to_f16(f32::NAN) & 0x3ff != 0);
+    }
+
+    #[test]
+    fn converts_from_half() {
+        for &half in &[
+            0_u16, 0x8000, 0x3c00, 0x3800, 0xc000, 0x7bff, 0x7c00, 0x0001, 0x0400,
+        ] {
+            assert_eq!(f32_to_f16(f16_to_f32(half)), half);
+        }
+        assert!(f16_to_f32(0x7e00).is_nan());
+    }
+
+    #[test]
+    fn reads_back_exr() {
+        let fb = test_image();
+        for &precision in &[Precision::Half, Precision::Float] {
+            let mut out = Vec::new();
+            write_exr(&mut out, &fb, precision).unwrap();
+            assert_eq!(read_exr(&mut out.as_slice()).unwrap(), fb);
+        }
+        assert!(read_exr(&mut &b"not an image"[..]).is_err());
+    }
+
+    #[test]
+    fn reads_back_radiance() {
+        // Every test pixel is exactly representable in RGBE.
+        let fb = test_image();
+        let mut out = Vec::new();
+        write_radiance(&mut out, &fb).unwrap();
+        assert_eq!(read_radiance(out.as_slice()).unwrap(), fb);
+    }
+
+    #[test]
+    fn reads_back_run_length_encoded_radiance() {
+        // Wide enough to be run-length encoded. Red and the exponent are the
+        // same all along. Green has a short run, a long one, another short
+        // one, and then changes every pixel; blue comes in pairs. The second
+        // row is the first backwards. Every value is exact in RGBE.
+        let width = 300;
+        let mut fb = Framebuffer::new(width, 2);
+        for (i, pixel) in fb.pixels.iter_mut().enumerate() {
+            let x = i % width as usize;
+            let x = if i < width as usize {
+                x
+            } else {
+                width as usize - 1 - x
+            };
+            let g = match x {
+                0..=2 => 5,
+                3..=149 => 10,
+                150..=152 => 20,
+                _ => x * 37 % 128,
+            };
+            let b = x / 2 % 128;
+            #[allow(clippy::cast_precision_loss)]
+            let c = |m: usize| m as f64 / 256.;
+            *pixel = Color::new(0.5, c(g), c(b));
+        }
+
+        let mut out = Vec::new();
+        write_radiance(&mut out, &fb).unwrap();
+        assert!(out.len() < fb.pixels.len() * 4);
+        assert_eq!(read_radiance(out.as_slice()).unwrap(), fb);
+    }
+
+    #[test]
+    fn converts_to_rgbe() {
+        assert_eq!(to_rgbe(&Color::new(0., 0., 0.)), [0, 0, 0, 0]);
+        assert_eq!(to_rgbe(&Color::new(1., 0.5, 0.)), [128, 64, 0, 129]);
+        assert_eq!(to_rgbe(&Color::new(0.5, 0.25, 0.)), [128, 64, 0, 128]);
+    }
+
+    #[test]
+    fn pfm_is_bottom_up() {
+        let mut fb = Framebuffer::new(1, 2);
+        fb.pixels[0] = Color::new(1., 1., 1.);
+        let mut out = Vec::new();
+        write_pfm(&mut out, &fb).unwrap();
+        let header = b"PF\n1 2\n-1.0\n";
+        assert_eq!(&out[..header.len()], header);
+        assert_eq!(out.len(), header.len() + 2 * 12);
+        assert_eq!(&out[header.len()..header.len() + 4], &[0; 4]);
+        assert_eq!(
+            &out[header.len() + 12..header.len() + 16],
+            &1_f32.to_le_bytes()
+        );
+    }
+}
@@ -1,22 +1,70 @@
-use std::rc::Rc;
+use std::sync::Arc;
+
+use rand::Rng;
 
 use crate::{
+    aabb::Aabb,
     material::{Blank, Material},
     point3::Point3,
     ray::Ray,
+    sampler::Sampler,
     vec3::Vec3,
 };
 
-pub trait Hittable {
-    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<Record>;
+pub trait Hittable: Send + Sync {
+    // `sampler` is for objects that are only hit some of the time, such as
+    // participating media. Solid surfaces ignore it.
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64, sampler: &mut Sampler) -> Option<Record>;
+
+    // Like `hit`, but passes through participating media. Shadow rays use it
+    // together with `transmittance`, to find a light without scattering.
+    fn hit_surface(
+        &self,
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+        sampler: &mut Sampler,
+    ) -> Option<Record> {
+        self.hit(ray, t_min, t_max, sampler)
+    }
+
+    // The fraction of light that gets through the object's media between
+    // `t_min` and `t_max`. It may be a random, unbiased estimate.
+    fn transmittance(&self, _ray: &Ray, _t_min: f64, _t_max: f64, _sampler: &mut Sampler) -> f64 {
+        1.
+    }
+
+    // A box around everywhere the object goes between `time0` and `time1`.
+    // Returns `None` if the object is unbounded.
+    fn bounding_box(&self, time0: f64, time1: f64) -> Option<Aabb>;
+
+    // Whether the object gives off light, and so should be sampled directly.
+    fn is_emissive(&self) -> bool {
+        false
+    }
+
+    // For light sampling: a direction from `origin` toward a random point on
+    // the object.
+    fn sample_direction(&self, _origin: &Point3, _sampler: &mut Sampler) -> Vec3 {
+        Vec3::new(1., 0., 0.)
+    }
+
+    // Density, per unit solid angle, with which `sample_direction` picks
+    // `direction`. It's zero for directions that miss the object.
+    fn pdf_value(&self, _origin: &Point3, _direction: &Vec3) -> f64 {
+        0.
+    }
 }
 
 #[derive(Clone)]
 pub struct Record {
     pub p: Point3,
     pub normal: Vec3,
-    pub mat: Rc<dyn Material>,
+    pub mat: Arc<dyn Material>,
     pub t: f64,
+    // Surface coordinates of the hit point, e.g. for texture lookups.
+    pub u: f64,
+    pub v: f64,
     pub front_face: bool,
 }
 
@@ -31,8 +79,10 @@ impl Record {
         Self {
             p: Point3::zero(),
             normal: Vec3::zero(),
-            mat: Rc::new(Blank::new()),
+            mat: Arc::new(Blank::new()),
             t: 0.,
+            u: 0.,
+            v: 0.,
             front_face: false,
         }
     }
@@ -49,7 +99,8 @@ impl Record {
 
 #[derive(Default)]
 pub struct HittableObjects {
-    pub objects: Vec<Rc<dyn Hittable>>,
+    objects: Vec<Arc<dyn Hittable>>,
+    emitters: Emitters,
 }
 
 impl HittableObjects {
@@ -59,20 +110,123 @@ impl HittableObjects {
 
     pub fn clear(&mut self) {
         self.objects.clear();
+        self.emitters = Emitters::default();
     }
 
     pub fn add<H: Hittable + 'static>(&mut self, h: H) {
-        self.objects.push(Rc::new(h));
+        self.push(Arc::new(h));
+    }
+
+    // Like `add`, for an object that may be shared.
+    pub fn push(&mut self, object: Arc<dyn Hittable>) {
+        self.emitters.push(&object);
+        self.objects.push(object);
+    }
+
+    pub fn objects(&self) -> &[Arc<dyn Hittable>] {
+        &self.objects
+    }
+
+    pub fn into_objects(self) -> Vec<Arc<dyn Hittable>> {
+        self.objects
+    }
+
+    pub fn len(&self) -> usize {
+        self.objects.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.objects.is_empty()
+    }
+
+    // The emissive objects, for sampling lights directly. Groups that glow,
+    // such as an instanced mesh, come as one object that samples its own
+    // emissive parts.
+    pub fn lights(&self) -> Self {
+        let mut lights = Self::new();
+        for object in &self.emitters.objects {
+            lights.push(Arc::clone(object));
+        }
+        lights
+    }
+}
+
+impl Extend<Arc<dyn Hittable>> for HittableObjects {
+    fn extend<I: IntoIterator<Item = Arc<dyn Hittable>>>(&mut self, objects: I) {
+        for object in objects {
+            self.push(object);
+        }
+    }
+}
+
+// The emissive members of a group, gathered once as the group's built, so
+// that sampling them doesn't have to pass over the rest every time. Each is
+// picked equally often.
+#[derive(Clone, Default)]
+pub struct Emitters {
+    objects: Vec<Arc<dyn Hittable>>,
+}
+
+impl Emitters {
+    // Keeps `object` if it glows.
+    pub fn push(&mut self, object: &Arc<dyn Hittable>) {
+        if object.is_emissive() {
+            self.objects.push(Arc::clone(object));
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.objects.is_empty()
+    }
+
+    pub fn sample_direction(&self, origin: &Point3, sampler: &mut Sampler) -> Vec3 {
+        if self.objects.is_empty() {
+            return Vec3::new(1., 0., 0.);
+        }
+        let i = sampler.gen_range(0..self.objects.len());
+        self.objects[i].sample_direction(origin, sampler)
+    }
+
+    pub fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f64 {
+        if self.objects.is_empty() {
+            return 0.;
+        }
+        #[allow(clippy::cast_precision_loss)]
+        let weight = 1. / self.objects.len() as f64;
+        self.objects
+            .iter()
+            .map(|o| weight * o.pdf_value(origin, direction))
+            .sum()
     }
 }
 
 impl Hittable for HittableObjects {
-    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<Record> {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64, sampler: &mut Sampler) -> Option<Record> {
+        let mut hit_record = None;
+        let mut closest_so_far = t_max;
+
+        for o in &self.objects {
+            if let Some(rec) = o.as_ref().hit(ray, t_min, closest_so_far, sampler) {
+                closest_so_far = rec.t;
+                hit_record = Some(rec);
+            }
+        }
+
+        hit_record
+    }
+
+    fn hit_surface(
+        &self,
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+        sampler: &mut Sampler,
+    ) -> Option<Record> {
         let mut hit_record = None;
         let mut closest_so_far = t_max;
 
         for o in &self.objects {
-            if let Some(rec) = o.as_ref().hit(ray, t_min, closest_so_far) {
+            if let Some(rec) = o.hit_surface(ray, t_min, closest_so_far, sampler) {
                 closest_so_far = rec.t;
                 hit_record = Some(rec);
             }
@@ -80,4 +234,128 @@ impl Hittable for HittableObjects {
 
         hit_record
     }
+
+    fn transmittance(&self, ray: &Ray, t_min: f64, t_max: f64, sampler: &mut Sampler) -> f64 {
+        self.objects
+            .iter()
+            .map(|o| o.transmittance(ray, t_min, t_max, sampler))
+            .product()
+    }
+
+    fn bounding_box(&self, time0: f64, time1: f64) -> Option<Aabb> {
+        let mut boxes = self.objects.iter().map(|o| o.bounding_box(time0, time1));
+        let first = boxes.next()??;
+        boxes.try_fold(first, |acc, b| Some(acc.union(&b?)))
+    }
+
+    fn is_emissive(&self) -> bool {
+        !self.emitters.is_empty()
+    }
+
+    // Picks one of the emissive objects uniformly, then samples it. Objects
+    // that don't glow can't be sampled, so they're never picked.
+    fn sample_direction(&self, origin: &Point3, sampler: &mut Sampler) -> Vec3 {
+        self.emitters.sample_direction(origin, sampler)
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f64 {
+        self.emitters.pdf_value(origin, direction)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{f64::consts::PI, sync::Arc};
+
+    use super::{Hittable, HittableObjects};
+    use crate::{
+        color::Color,
+        material::{DiffuseLight, Lambertian, Material},
+        point3::Point3,
+        sampler::Sampler,
+        sphere::Sphere,
+        triangle::{Mesh, Triangle, TriangleMesh},
+        vec3::Vec3,
+    };
+
+    // Checks that `light`'s density integrates to 1 over the sphere of
+    // directions, and that it's the density `sample_direction` draws from:
+    // weighting its samples by 1 / pdf recovers the solid angle the light
+    // covers, which is measured separately by uniform sampling.
+    fn check_density(light: &dyn Hittable, origin: &Point3) {
+        let n = 200_000;
+        let mut sampler = Sampler::new(1);
+        let mut integral = 0.;
+        let mut covered = 0.;
+        for _ in 0..n {
+            let direction = Vec3::rand_unit(&mut sampler);
+            let pdf = light.pdf_value(origin, &direction);
+            integral += 4. * PI * pdf / f64::from(n);
+            if pdf > 0. {
+                covered += 4. * PI / f64::from(n);
+            }
+        }
+        assert!((integral - 1.).abs() < 0.02, "integrates to {}", integral);
+
+        let mut estimate = 0.;
+        for _ in 0..n {
+            let direction = light.sample_direction(origin, &mut sampler);
+            let pdf = light.pdf_value(origin, &direction);
+            assert!(pdf > 0.);
+            estimate += 1. / pdf / f64::from(n);
+        }
+        assert!(
+            (estimate - covered).abs() < 0.02 * covered,
+            "{} vs {}",
+            estimate,
+            covered
+        );
+    }
+
+    #[test]
+    fn light_densities_match_their_samples() {
+        let glow: Arc<dyn Material> = Arc::new(DiffuseLight::new(&Color::new(1., 1., 1.)));
+        let sphere = Sphere::new(&Point3::new(0., 2., 0.), 1., Arc::clone(&glow));
+        check_density(&sphere, &Point3::zero());
+
+        let triangle = Triangle::new(
+            &Point3::new(-1., 1., -1.),
+            &Point3::new(2., 1., -1.),
+            &Point3::new(-1., 1., 2.),
+            Arc::clone(&glow),
+        );
+        check_density(&triangle, &Point3::zero());
+
+        // A mesh whose faces differ in size and overlap as seen from the
+        // origin, so some directions pass through both.
+        let mesh = Mesh::new(TriangleMesh::new(
+            vec![
+                Point3::new(-1., 1., -1.),
+                Point3::new(2., 1., -1.),
+                Point3::new(-1., 1., 2.),
+                Point3::new(0., 2., 0.),
+                Point3::new(1., 2., 0.),
+                Point3::new(0., 2., 1.),
+            ],
+            vec![[0, 1, 2], [3, 4, 5]],
+            glow,
+        ));
+        check_density(&mesh, &Point3::zero());
+
+        // Objects that don't glow are left out of a group's sampling.
+        let mut group = HittableObjects::new();
+        group.add(sphere);
+        group.add(Sphere::new(
+            &Point3::new(0., -2., 0.),
+            1.,
+            Arc::new(Lambertian::new(&Color::new(0.5, 0.5, 0.5))),
+        ));
+        group.add(triangle);
+        let mut sampler = Sampler::new(2);
+        for _ in 0..1000 {
+            let direction = group.sample_direction(&Point3::zero(), &mut sampler);
+            assert!(direction.y > 0., "{:?}", direction);
+        }
+        check_density(&group, &Point3::zero());
+    }
 }
@@ -0,0 +1,228 @@
+// IES photometric profiles (ANSI/IESNA LM-63): how a real light fixture's
+// luminous intensity varies with direction, as published by manufacturers.
+//
+// Only type C photometry is supported, which is what nearly every
+// architectural fixture uses. Vertical angles run from 0 (straight down the
+// fixture's axis) to 180, and horizontal angles around the axis.
+
+use std::{fs, io, path::Path};
+
+pub struct IesProfile {
+    // Angles in degrees, in increasing order.
+    vertical: Vec<f64>,
+    horizontal: Vec<f64>,
+    // Candelas, one row of `vertical.len()` values per horizontal angle.
+    candela: Vec<f64>,
+    max: f64,
+}
+
+impl IesProfile {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn parse(text: &str) -> io::Result<Self> {
+        // Everything after the keywords, up to and including the TILT line,
+        // is whitespace or comma separated numbers.
+        let mut lines = text.lines();
+        let tilt = lines
+            .by_ref()
+            .find(|line| line.trim_start().starts_with("TILT="))
+            .ok_or_else(|| invalid("missing TILT line"))?;
+        let rest: Vec<&str> = lines.collect();
+        let mut numbers = rest
+            .iter()
+            .flat_map(|line| line.split(|c: char| c.is_whitespace() || c == ','))
+            .filter(|word| !word.is_empty())
+            .map(|word| {
+                word.parse::<f64>()
+                    .map_err(|_| invalid(&format!("expected a number, found {:?}", word)))
+            });
+        let mut next = || {
+            numbers
+                .next()
+                .unwrap_or_else(|| Err(invalid("unexpected end of file")))
+        };
+
+        match tilt.trim() {
+            "TILT=NONE" => {}
+            // Lamp-to-luminaire geometry, then angle and factor pairs, which
+            // only matter for lamps whose output changes as they're tilted.
+            "TILT=INCLUDE" => {
+                next()?;
+                let pairs = count(next()?)?;
+                for _ in 0..2 * pairs {
+                    next()?;
+                }
+            }
+            _ => return Err(invalid("tilt data in separate files is not supported")),
+        }
+
+        let _lamps = next()?;
+        let _lumens = next()?;
+        let multiplier = next()?;
+        let vertical_count = count(next()?)?;
+        let horizontal_count = count(next()?)?;
+        if next()? != 1. {
+            return Err(invalid("only type C photometry is supported"));
+        }
+        for _ in 0..4 {
+            next()?; // Units, and the luminous opening's width, length and height.
+        }
+        let ballast_factor = next()?;
+        for _ in 0..2 {
+            next()?; // Ballast-lamp factor, and input watts.
+        }
+
+        let mut read = |n| (0..n).map(|_| next()).collect::<io::Result<Vec<f64>>>();
+        let vertical = read(vertical_count)?;
+        let horizontal = read(horizontal_count)?;
+        let candela: Vec<f64> = read(vertical_count * horizontal_count)?
+            .into_iter()
+            .map(|c| c * multiplier * ballast_factor)
+            .collect();
+
+        let increasing = |angles: &[f64]| angles.windows(2).all(|w| w[0] < w[1]);
+        if vertical.is_empty() || horizontal.is_empty() {
+            return Err(invalid("no angles"));
+        }
+        if !increasing(&vertical) || !increasing(&horizontal) {
+            return Err(invalid("angles must be in increasing order"));
+        }
+        if candela.iter().any(|c| !c.is_finite() || *c < 0.) {
+            return Err(invalid("candelas must be finite and non-negative"));
+        }
+        let max = candela.iter().fold(0., |m: f64, &c| m.max(c));
+        Ok(Self {
+            vertical,
+            horizontal,
+            candela,
+            max,
+        })
+    }
+
+    // The brightest intensity in any direction.
+    pub const fn max(&self) -> f64 {
+        self.max
+    }
+
+    // Intensity, in candelas, at `vertical` degrees from the fixture's axis
+    // and `horizontal` degrees around it, interpolated between the measured
+    // angles. The profile's symmetry fills in the horizontal angles it
+    // leaves out, but it's dark past the vertical angles it measures, e.g.
+    // above a downlight that only gives 0 to 90.
+    pub fn candela(&self, vertical: f64, horizontal: f64) -> f64 {
+        let (first, last) = (self.vertical[0], self.vertical[self.vertical.len() - 1]);
+        if vertical < first || vertical > last {
+            return 0.;
+        }
+
+        let last = self.horizontal[self.horizontal.len() - 1];
+        let h = horizontal.rem_euclid(360.);
+        let h = if last <= 0. {
+            0.
+        } else if last <= 90. {
+            // Quadrant symmetric.
+            let h = h % 180.;
+            if h > 90. {
+                180. - h
+            } else {
+                h
+            }
+        } else if last <= 180. {
+            // Bilaterally symmetric about the 0-180 degree plane.
+            if h > 180. {
+                360. - h
+            } else {
+                h
+            }
+        } else {
+            h
+        };
+
+        let (h0, h1, th) = bracket(&self.horizontal, h);
+        let (v0, v1, tv) = bracket(&self.vertical, vertical);
+        let n = self.vertical.len();
+        let at = |h: usize, v: usize| self.candela[h * n + v];
+        let lerp = |a: f64, b: f64, t: f64| t.mul_add(b - a, a);
+        lerp(
+            lerp(at(h0, v0), at(h0, v1), tv),
+            lerp(at(h1, v0), at(h1, v1), tv),
+            th,
+        )
+    }
+}
+
+// The two entries of `angles` around `x`, and how far `x` is from the first
+// to the second. Outside the range, the end values are held.
+fn bracket(angles: &[f64], x: f64) -> (usize, usize, f64) {
+    let i = angles.partition_point(|&a| a <= x);
+    if i == 0 {
+        (0, 0, 0.)
+    } else if i == angles.len() {
+        (i - 1, i - 1, 0.)
+    } else {
+        let (a, b) = (angles[i - 1], angles[i]);
+        (i - 1, i, (x - a) / (b - a))
+    }
+}
+
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+fn count(x: f64) -> io::Result<usize> {
+    if x >= 1. && x.fract() == 0. && x < 1e6 {
+        Ok(x as usize)
+    } else {
+        Err(invalid("counts must be positive integers"))
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::IesProfile;
+
+    // A made-up downlight: a 60 degree beam, brighter toward 90 degrees
+    // around than toward 0, bilaterally symmetric.
+    const DOWNLIGHT: &str = "IESNA:LM-63-2002
+[TEST] made up
+[MANUFAC] nobody
+TILT=NONE
+1 1000 2 4 3 1 2 0.1 0.1 0
+1.0 1.0 20
+0 30 60 90
+0, 90, 180
+100 80 10 0
+300 240 30 0
+100 80 10 0
+";
+
+    #[test]
+    fn parses_and_interpolates() {
+        let ies = IesProfile::parse(DOWNLIGHT).unwrap();
+        // The multiplier, 2, scales every value.
+        assert!((ies.max() - 600.).abs() < 1e-12);
+        assert!((ies.candela(0., 0.) - 200.).abs() < 1e-12);
+        assert!((ies.candela(0., 90.) - 600.).abs() < 1e-12);
+        assert!((ies.candela(15., 0.) - 180.).abs() < 1e-12);
+        assert!((ies.candela(0., 45.) - 400.).abs() < 1e-12);
+        // Mirrored about the 0-180 plane, and dark past the last angle.
+        assert!((ies.candela(30., 270.) - 480.).abs() < 1e-12);
+        assert!(ies.candela(120., 90.).abs() < 1e-12);
+
+        // Light that's still measured at the last vertical angle stops there,
+        // rather than carrying on round the back of the fixture.
+        let wide =
+            IesProfile::parse("TILT=NONE\n1 1000 1 3 1 1 2 0 0 0\n1 1 10\n0 45 90\n0\n50 100 20\n")
+                .unwrap();
+        assert!((wide.candela(90., 0.) - 20.).abs() < 1e-12);
+        assert!(wide.candela(120., 0.).abs() < 1e-12);
+        assert!(wide.candela(180., 0.).abs() < 1e-12);
+
+        assert!(IesProfile::parse("TILT=NONE\n1 1000 1 2").is_err());
+        let type_b = DOWNLIGHT.replace("4 3 1 2", "4 3 2 2");
+        assert!(IesProfile::parse(&type_b).is_err());
+    }
+}
@@ -0,0 +1,215 @@
+use std::sync::Arc;
+
+use crate::{
+    aabb::Aabb,
+    hit::{Hittable, Record},
+    point3::Point3,
+    ray::Ray,
+    sampler::Sampler,
+    transform::{AnimatedTransform, Transform},
+    vec3::Vec3,
+};
+
+// Places a shared object in the world with an object-to-world transform. Any
+// number of instances can share one object (e.g. a whole mesh's BVH), so each
+// copy costs only a pointer and a couple of matrices.
+pub struct Instance {
+    object: Arc<dyn Hittable>,
+    to_world: Transform,
+    // Set for moving instances, whose `to_world` is then where they start.
+    motion: Option<AnimatedTransform>,
+}
+
+impl Instance {
+    pub fn new(object: Arc<dyn Hittable>, to_world: &Transform) -> Self {
+        Self {
+            object,
+            to_world: *to_world,
+            motion: None,
+        }
+    }
+
+    // An instance that moves over time.
+    pub fn moving(object: Arc<dyn Hittable>, motion: &AnimatedTransform) -> Self {
+        Self {
+            object,
+            to_world: motion.at(f64::NEG_INFINITY),
+            motion: Some(*motion),
+        }
+    }
+
+    fn to_world_at(&self, time: f64) -> Transform {
+        match &self.motion {
+            Some(motion) => motion.at(time),
+            None => self.to_world,
+        }
+    }
+
+    // `to_world` maps `record` back out of object space.
+    fn to_world_record(to_world: &Transform, mut record: Record) -> Record {
+        record.p = to_world.point(&record.p);
+        // The transformed normal stays on the same side of the ray, so
+        // `front_face` is still right.
+        record.normal = to_world.normal(&record.normal).unit();
+        record
+    }
+}
+
+impl Hittable for Instance {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64, sampler: &mut Sampler) -> Option<Record> {
+        // The object-space ray's direction isn't normalized, so `t` carries
+        // over unchanged.
+        let to_world = self.to_world_at(ray.time);
+        self.object
+            .hit(&to_world.inverse().ray(ray), t_min, t_max, sampler)
+            .map(|record| Self::to_world_record(&to_world, record))
+    }
+
+    fn hit_surface(
+        &self,
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+        sampler: &mut Sampler,
+    ) -> Option<Record> {
+        let to_world = self.to_world_at(ray.time);
+        self.object
+            .hit_surface(&to_world.inverse().ray(ray), t_min, t_max, sampler)
+            .map(|record| Self::to_world_record(&to_world, record))
+    }
+
+    fn transmittance(&self, ray: &Ray, t_min: f64, t_max: f64, sampler: &mut Sampler) -> f64 {
+        let to_object = self.to_world_at(ray.time).inverse();
+        self.object
+            .transmittance(&to_object.ray(ray), t_min, t_max, sampler)
+    }
+
+    fn bounding_box(&self, time0: f64, time1: f64) -> Option<Aabb> {
+        let bbox = self.object.bounding_box(time0, time1)?;
+        Some(match &self.motion {
+            Some(motion) => motion.bounding_box(&bbox, time0, time1),
+            None => self.to_world.bounding_box(&bbox),
+        })
+    }
+
+    // Moving instances aren't sampled as lights, even if they glow, since
+    // where they are depends on the time.
+    fn is_emissive(&self) -> bool {
+        self.motion.is_none() && self.object.is_emissive()
+    }
+
+    fn sample_direction(&self, origin: &Point3, sampler: &mut Sampler) -> Vec3 {
+        let to_object = self.to_world.inverse();
+        let local = self
+            .object
+            .sample_direction(&to_object.point(origin), sampler);
+        self.to_world.vector(&local)
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f64 {
+        // The object's density, per unit solid angle in object space, times
+        // the Jacobian of the map from world to object space directions.
+        let to_object = self.to_world.inverse();
+        let local = to_object.vector(&direction.unit());
+        let pdf = self.object.pdf_value(&to_object.point(origin), &local);
+        let scale = local.mag();
+        pdf * to_object.determinant().abs() / (scale * scale * scale)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::Instance;
+    use crate::{
+        hit::Hittable,
+        material::Blank,
+        point3::Point3,
+        quad::Quad,
+        ray::Ray,
+        sampler::Sampler,
+        sphere::Sphere,
+        transform::{AnimatedTransform, Transform},
+        vec3::Vec3,
+    };
+
+    #[test]
+    fn hits_transformed_object() {
+        let sphere = Arc::new(Sphere::new(&Point3::zero(), 1., Arc::new(Blank::new())));
+        let to_world =
+            Transform::scale(2., 1., 1.).then(&Transform::translate(&Vec3::new(0., 0., -5.)));
+        let instance = Instance::new(sphere, &to_world);
+
+        let ray = Ray {
+            origin: Point3::new(-10., 0., -5.),
+            direction: Vec3::new(1., 0., 0.),
+            time: 0.,
+        };
+        let rec = instance
+            .hit(&ray, 0.001, f64::INFINITY, &mut Sampler::new(0))
+            .expect("should hit");
+        assert!((rec.t - 8.).abs() < 1e-9);
+        assert!((rec.p - Point3::new(-2., 0., -5.)).mag() < 1e-9);
+        assert!((rec.normal - Vec3::new(-1., 0., 0.)).mag() < 1e-9);
+        assert!(rec.front_face);
+
+        let bbox = instance.bounding_box(0., 0.).unwrap();
+        assert!((bbox.min - Point3::new(-2., -1., -6.)).mag() < 1e-9);
+        assert!((bbox.max - Point3::new(2., 1., -4.)).mag() < 1e-9);
+    }
+
+    #[test]
+    fn light_pdf_accounts_for_scaling() {
+        // A unit square stretched to 2x3, against the same rectangle built
+        // directly.
+        let unit = Arc::new(Quad::xy_rect(0., 1., 0., 1., 0., Arc::new(Blank::new())));
+        let to_world =
+            Transform::scale(2., 3., 1.).then(&Transform::translate(&Vec3::new(1., 0., 0.)));
+        let instance = Instance::new(unit, &to_world);
+        let direct = Quad::xy_rect(1., 3., 0., 3., 0., Arc::new(Blank::new()));
+
+        let origin = Point3::new(0.5, 1., 4.);
+        for direction in &[Vec3::new(1., 0.5, -4.), Vec3::new(1., -0.25, -2.)] {
+            let expected = direct.pdf_value(&origin, direction);
+            assert!(expected > 0.);
+            assert!((instance.pdf_value(&origin, direction) - expected).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn moving_instance_follows_its_motion() {
+        // A unit sphere moving from +x to -z while turning a quarter turn.
+        // Its center goes in a straight line.
+        let sphere = Arc::new(Sphere::new(&Point3::zero(), 1., Arc::new(Blank::new())));
+        let start = Transform::translate(&Vec3::new(5., 0., 0.));
+        let end = start.then(&Transform::rotate_y(90.));
+        let instance = Instance::moving(sphere, &AnimatedTransform::new(&start, &end, 0., 1.));
+        let mut sampler = Sampler::new(0);
+
+        let down = |x, z, time| Ray {
+            origin: Point3::new(x, 5., z),
+            direction: Vec3::new(0., -1., 0.),
+            time,
+        };
+        assert!(instance
+            .hit(&down(5., 0., 0.), 0.001, f64::INFINITY, &mut sampler)
+            .is_some());
+        assert!(instance
+            .hit(&down(5., 0., 1.), 0.001, f64::INFINITY, &mut sampler)
+            .is_none());
+        assert!(instance
+            .hit(&down(0., -5., 1.), 0.001, f64::INFINITY, &mut sampler)
+            .is_some());
+
+        assert!(instance
+            .hit(&down(2.5, -2.5, 0.5), 0.001, f64::INFINITY, &mut sampler)
+            .is_some());
+
+        let bbox = instance.bounding_box(0., 1.).unwrap();
+        assert!(bbox.max.x >= 6. - 1e-9 && bbox.min.z <= -6. + 1e-9);
+        let bbox = instance.bounding_box(0., 0.).unwrap();
+        assert!((bbox.min - Point3::new(4., -1., -1.)).mag() < 1e-9);
+        assert!(!instance.is_emissive());
+    }
+}
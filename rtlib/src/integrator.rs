@@ -0,0 +1,518 @@
+// Rendering algorithms: ways of turning a camera ray into a color. The path
+// tracer is the real one. The others trade accuracy for speed, or show some
+// property of the surfaces instead of light, for checking scenes and
+// materials.
+
+use std::sync::Arc;
+
+use rand::Rng;
+
+use crate::{
+    color::Color,
+    environment::Environment,
+    hit::{self, Hittable, HittableObjects},
+    light::Light,
+    material::cosine_direction,
+    onb::Onb,
+    ray::Ray,
+    sampler::Sampler,
+};
+
+pub trait Integrator: Send + Sync {
+    // What the camera sees along `ray`.
+    fn radiance(&self, ray: &Ray, context: &Context, sampler: &mut Sampler) -> Color;
+}
+
+// A scene, ready for tracing rays through.
+pub struct Context<'a> {
+    // Everything rays can hit, normally in a BVH.
+    pub world: &'a dyn Hittable,
+    // The emissive objects in `world`, for aiming shadow rays at.
+    pub lights: &'a HittableObjects,
+    // Lights with no surface.
+    pub delta_lights: &'a [Arc<dyn Light>],
+    pub environment: &'a dyn Environment,
+}
+
+pub const NAMES: [&str; 7] = ["path", "direct", "ao", "normals", "depth", "albedo", "uv"];
+
+// Looks up an integrator by its name in `NAMES`. `max_depth` limits the
+// bounces of the ones that follow light around the scene, and `ao_distance`
+// is how far the ambient occlusion integrator looks for occluders.
+pub fn by_name(name: &str, max_depth: u32, ao_distance: f64) -> Option<Box<dyn Integrator>> {
+    Some(match name {
+        "path" => Box::new(PathTracer { max_depth }),
+        "direct" => Box::new(DirectLighting { max_depth }),
+        "ao" => Box::new(AmbientOcclusion {
+            distance: ao_distance,
+        }),
+        "normals" => Box::new(Normals),
+        "depth" => Box::new(Depth),
+        "albedo" => Box::new(Albedo),
+        "uv" => Box::new(Uv),
+        _ => return None,
+    })
+}
+
+// Follows light along paths of up to `max_depth` bounces. At every diffuse
+// bounce, it also sends shadow rays toward a randomly chosen light and into
+// the environment (next-event estimation), and combines the two ways of
+// finding each with multiple importance sampling. Delta lights, which nothing
+// else can find, get a shadow ray each.
+pub struct PathTracer {
+    pub max_depth: u32,
+}
+
+impl Integrator for PathTracer {
+    fn radiance(&self, ray: &Ray, context: &Context, sampler: &mut Sampler) -> Color {
+        trace(ray, context, self.max_depth, false, sampler)
+    }
+}
+
+// Only light that reaches the camera after a single diffuse bounce, which is
+// quicker and less noisy, but leaves shadows black. Mirrors and glass are
+// still followed, up to `max_depth` times, to the first diffuse surface.
+pub struct DirectLighting {
+    pub max_depth: u32,
+}
+
+impl Integrator for DirectLighting {
+    fn radiance(&self, ray: &Ray, context: &Context, sampler: &mut Sampler) -> Color {
+        trace(ray, context, self.max_depth, true, sampler)
+    }
+}
+
+fn trace(
+    ray: &Ray,
+    context: &Context,
+    max_depth: u32,
+    direct_only: bool,
+    sampler: &mut Sampler,
+) -> Color {
+    let Context {
+        world,
+        lights,
+        delta_lights,
+        environment,
+    } = *context;
+    let mut color = Color::default();
+    let mut throughput = Color::new(1., 1., 1.);
+    let mut ray = *ray;
+    // Density with which the last bounce picked `ray`, if it was diffuse.
+    let mut bsdf_pdf: Option<f64> = None;
+
+    for _ in 0..max_depth {
+        let h_rec = match world.hit(&ray, 0.001, f64::INFINITY, sampler) {
+            Some(h_rec) => h_rec,
+            None => {
+                let direction = ray.direction.unit();
+                let weight =
+                    bsdf_pdf.map_or(1., |pdf| power_heuristic(pdf, environment.pdf(&direction)));
+                color += weight * (throughput * environment.radiance(&direction));
+                break;
+            }
+        };
+
+        // Light that next-event estimation could also have found is weighted
+        // down, so that it isn't counted twice.
+        let emitted = h_rec.mat.emitted(h_rec.u, h_rec.v, &h_rec.p);
+        let weight = match bsdf_pdf {
+            Some(pdf) if !lights.is_empty() => {
+                power_heuristic(pdf, lights.pdf_value(&ray.origin, &ray.direction))
+            }
+            _ => 1.,
+        };
+        color += weight * (throughput * emitted);
+
+        // Past a diffuse bounce, only the light it found directly counts.
+        if direct_only && bsdf_pdf.is_some() {
+            break;
+        }
+
+        let s_rec = match h_rec.mat.scatter(&ray, &h_rec, sampler) {
+            Some(s_rec) => s_rec,
+            None => break,
+        };
+
+        if s_rec.pdf.is_some() {
+            if !lights.is_empty() {
+                color += throughput * sample_light(&ray, &h_rec, world, lights, sampler);
+            }
+            color += throughput * sample_environment(&ray, &h_rec, world, environment, sampler);
+            for light in delta_lights {
+                color +=
+                    throughput * sample_delta_light(&ray, &h_rec, world, light.as_ref(), sampler);
+            }
+        }
+
+        throughput = throughput * s_rec.attenuation;
+        bsdf_pdf = s_rec.pdf;
+        ray = s_rec.scattered;
+    }
+
+    color
+}
+
+// Direct light arriving at `h_rec` from a randomly chosen light, weighted for
+// combination with BSDF sampling.
+fn sample_light(
+    ray_in: &Ray,
+    h_rec: &hit::Record,
+    world: &dyn Hittable,
+    lights: &HittableObjects,
+    sampler: &mut Sampler,
+) -> Color {
+    let direction = lights.sample_direction(&h_rec.p, sampler);
+    let light_pdf = lights.pdf_value(&h_rec.p, &direction);
+    if light_pdf <= 0. {
+        return Color::default();
+    }
+
+    let shadow_ray = Ray {
+        origin: h_rec.p,
+        direction,
+        time: ray_in.time,
+    };
+    // Look through any media for the light, and then dim it by however much
+    // of them lay in between.
+    let l_rec = match world.hit_surface(&shadow_ray, 0.001, f64::INFINITY, sampler) {
+        Some(l_rec) => l_rec,
+        None => return Color::default(),
+    };
+    let transmittance = world.transmittance(&shadow_ray, 0.001, l_rec.t, sampler);
+    if transmittance <= 0. {
+        return Color::default();
+    }
+
+    let emitted = l_rec.mat.emitted(l_rec.u, l_rec.v, &l_rec.p);
+    let f = h_rec.mat.eval(ray_in, h_rec, &direction);
+    let bsdf_pdf = h_rec.mat.scattering_pdf(ray_in, h_rec, &direction);
+    (transmittance * power_heuristic(light_pdf, bsdf_pdf) / light_pdf) * (f * emitted)
+}
+
+// Light arriving at `h_rec` from the environment, in a direction picked by
+// the environment, weighted for combination with BSDF sampling.
+fn sample_environment(
+    ray_in: &Ray,
+    h_rec: &hit::Record,
+    world: &dyn Hittable,
+    environment: &dyn Environment,
+    sampler: &mut Sampler,
+) -> Color {
+    let (direction, env_pdf) = match environment.sample([sampler.gen(), sampler.gen()]) {
+        Some(sample) => sample,
+        None => return Color::default(),
+    };
+
+    let shadow_ray = Ray {
+        origin: h_rec.p,
+        direction,
+        time: ray_in.time,
+    };
+    if world
+        .hit_surface(&shadow_ray, 0.001, f64::INFINITY, sampler)
+        .is_some()
+    {
+        return Color::default();
+    }
+    let transmittance = world.transmittance(&shadow_ray, 0.001, f64::INFINITY, sampler);
+    if transmittance <= 0. {
+        return Color::default();
+    }
+
+    let f = h_rec.mat.eval(ray_in, h_rec, &direction);
+    let bsdf_pdf = h_rec.mat.scattering_pdf(ray_in, h_rec, &direction);
+    (transmittance * power_heuristic(env_pdf, bsdf_pdf) / env_pdf)
+        * (f * environment.radiance(&direction))
+}
+
+// Light arriving at `h_rec` from a point, spot or directional light. There's
+// only one direction it can come from, so there's nothing to weigh it against.
+fn sample_delta_light(
+    ray_in: &Ray,
+    h_rec: &hit::Record,
+    world: &dyn Hittable,
+    light: &dyn Light,
+    sampler: &mut Sampler,
+) -> Color {
+    let incident = match light.illuminate(&h_rec.p) {
+        Some(incident) => incident,
+        None => return Color::default(),
+    };
+
+    let shadow_ray = Ray {
+        origin: h_rec.p,
+        direction: incident.direction,
+        time: ray_in.time,
+    };
+    if world
+        .hit_surface(&shadow_ray, 0.001, incident.distance, sampler)
+        .is_some()
+    {
+        return Color::default();
+    }
+    let transmittance = world.transmittance(&shadow_ray, 0.001, incident.distance, sampler);
+    if transmittance <= 0. {
+        return Color::default();
+    }
+
+    let f = h_rec.mat.eval(ray_in, h_rec, &incident.direction);
+    transmittance * (f * incident.irradiance)
+}
+
+fn power_heuristic(pdf: f64, other_pdf: f64) -> f64 {
+    let (a, b) = (pdf * pdf, other_pdf * other_pdf);
+    if a + b > 0. {
+        a / (a + b)
+    } else {
+        0.
+    }
+}
+
+// White where the surface is open to the sky, and darker in creases and
+// corners: the fraction of the hemisphere above each point, weighted by
+// cosine, that's clear of other surfaces for `distance`. Rays that miss
+// everything are white.
+pub struct AmbientOcclusion {
+    pub distance: f64,
+}
+
+impl Integrator for AmbientOcclusion {
+    fn radiance(&self, ray: &Ray, context: &Context, sampler: &mut Sampler) -> Color {
+        let h_rec = match context.world.hit(ray, 0.001, f64::INFINITY, sampler) {
+            Some(h_rec) => h_rec,
+            None => return Color::new(1., 1., 1.),
+        };
+        let frame = Onb::from_w(&h_rec.normal);
+        let occlusion_ray = Ray {
+            origin: h_rec.p,
+            direction: frame.local(&cosine_direction(sampler.gen(), sampler.gen())),
+            time: ray.time,
+        };
+        match context
+            .world
+            .hit_surface(&occlusion_ray, 0.001, self.distance, sampler)
+        {
+            Some(_) => Color::default(),
+            None => Color::new(1., 1., 1.),
+        }
+    }
+}
+
+// The surface normal seen first along each ray, pointing out of the object,
+// with each axis mapped from [-1, 1] onto [0, 1]. Misses are black.
+pub struct Normals;
+
+impl Integrator for Normals {
+    fn radiance(&self, ray: &Ray, context: &Context, sampler: &mut Sampler) -> Color {
+        context
+            .world
+            .hit(ray, 0.001, f64::INFINITY, sampler)
+            .map_or_else(Color::default, |h_rec| {
+                let n = if h_rec.front_face {
+                    h_rec.normal
+                } else {
+                    -h_rec.normal
+                };
+                Color::new(0.5 * (n.x + 1.), 0.5 * (n.y + 1.), 0.5 * (n.z + 1.))
+            })
+    }
+}
+
+// Distance from the camera to the first surface, in scene units, which is
+// best written to an HDR format or scaled down with `--exposure`. Misses are
+// black.
+pub struct Depth;
+
+impl Integrator for Depth {
+    fn radiance(&self, ray: &Ray, context: &Context, sampler: &mut Sampler) -> Color {
+        context
+            .world
+            .hit(ray, 0.001, f64::INFINITY, sampler)
+            .map_or_else(Color::default, |h_rec| {
+                let d = h_rec.t * ray.direction.mag();
+                Color::new(d, d, d)
+            })
+    }
+}
+
+// The fraction of light the first surface scatters back the way the camera
+// looks at it (its directional albedo), estimated by sampling its BSDF.
+// Lights, and misses, are black.
+pub struct Albedo;
+
+impl Integrator for Albedo {
+    fn radiance(&self, ray: &Ray, context: &Context, sampler: &mut Sampler) -> Color {
+        context
+            .world
+            .hit(ray, 0.001, f64::INFINITY, sampler)
+            .and_then(|h_rec| h_rec.mat.scatter(ray, &h_rec, sampler))
+            .map_or_else(Color::default, |s_rec| s_rec.attenuation)
+    }
+}
+
+// The first surface's texture coordinates, u in red and v in green. Misses
+// are black.
+pub struct Uv;
+
+impl Integrator for Uv {
+    fn radiance(&self, ray: &Ray, context: &Context, sampler: &mut Sampler) -> Color {
+        context
+            .world
+            .hit(ray, 0.001, f64::INFINITY, sampler)
+            .map_or_else(Color::default, |h_rec| Color::new(h_rec.u, h_rec.v, 0.))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::{by_name, Context, NAMES};
+    use crate::{
+        color::Color,
+        environment::Gradient,
+        hit::HittableObjects,
+        material::{DiffuseLight, Lambertian},
+        point3::Point3,
+        ray::Ray,
+        sampler::Sampler,
+        sphere::Sphere,
+        vec3::Vec3,
+    };
+
+    // A gray sphere under a uniformly white sky. Light that a convex object
+    // reflects never comes back to it, so every way of lighting the sphere
+    // should see exactly its albedo.
+    #[test]
+    fn integrators_agree_on_a_furnace() {
+        let mut world = HittableObjects::new();
+        let gray = Arc::new(Lambertian::new(&Color::new(0.5, 0.5, 0.5)));
+        world.add(Sphere::new(&Point3::zero(), 1., gray));
+        let white = Color::new(1., 1., 1.);
+        let context = Context {
+            world: &world,
+            lights: &HittableObjects::new(),
+            delta_lights: &[],
+            environment: &Gradient::new(&white, &white),
+        };
+        let ray = |x: f64| Ray {
+            origin: Point3::new(x, 0., 5.),
+            direction: Vec3::new(0., 0., -1.),
+            time: 0.,
+        };
+
+        let mut sampler = Sampler::new(0);
+        let see = |name: &str, x: f64, sampler: &mut Sampler| {
+            let integrator = by_name(name, 10, f64::INFINITY).unwrap();
+            integrator.radiance(&ray(x), &context, sampler).g
+        };
+        for name in &["path", "direct", "albedo"] {
+            for _ in 0..20 {
+                assert!(
+                    (see(name, 0.3, &mut sampler) - 0.5).abs() < 1e-12,
+                    "{}",
+                    name
+                );
+            }
+        }
+        assert!((see("ao", 0.3, &mut sampler) - 1.).abs() < 1e-12);
+
+        // Straight on, the normal faces the camera and the surface is 4 away.
+        assert!((see("normals", 0., &mut sampler) - 0.5).abs() < 1e-12);
+        assert!((see("depth", 0., &mut sampler) - 4.).abs() < 1e-12);
+        for name in &NAMES[..] {
+            assert!(see(name, 2., &mut sampler) >= 0., "{}", name);
+        }
+        assert!(by_name("nope", 10, 1.).is_none());
+    }
+
+    // Looking straight at a light, with nothing else around, shows exactly
+    // its color, and only the glowing sphere is sampled as a light.
+    #[test]
+    fn lights_are_seen_directly() {
+        let emit = Color::new(4., 2., 1.);
+        let mut world = HittableObjects::new();
+        world.add(Sphere::new(
+            &Point3::zero(),
+            1.,
+            Arc::new(DiffuseLight::new(&emit)),
+        ));
+        world.add(Sphere::new(
+            &Point3::new(5., 0., 0.),
+            1.,
+            Arc::new(Lambertian::new(&Color::new(0.5, 0.5, 0.5))),
+        ));
+        assert!(world.objects()[0].is_emissive());
+        assert!(!world.objects()[1].is_emissive());
+        let lights = world.lights();
+        assert_eq!(lights.len(), 1);
+
+        let black = Color::default();
+        let context = Context {
+            world: &world,
+            lights: &lights,
+            delta_lights: &[],
+            environment: &Gradient::new(&black, &black),
+        };
+        let ray = Ray {
+            origin: Point3::new(0., 0., 5.),
+            direction: Vec3::new(0., 0., -1.),
+            time: 0.,
+        };
+        let mut sampler = Sampler::new(0);
+        for name in &["path", "direct"] {
+            let integrator = by_name(name, 10, f64::INFINITY).unwrap();
+            assert_eq!(integrator.radiance(&ray, &context, &mut sampler), emit);
+        }
+    }
+
+    // A spherical light of radiance 9 and radius 1, centered 3 above a gray
+    // floor. The floor just below it gets an irradiance of π·9·(1/3)², and
+    // so reflects 0.5·9/9 = 0.5. Path tracing should find that whether it
+    // aims at the light or only follows the BSDF, which it does when it
+    // isn't told about any lights.
+    #[test]
+    fn light_sampling_agrees_with_bsdf_sampling() {
+        let mut world = HittableObjects::new();
+        world.add(Sphere::new(
+            &Point3::new(0., -1000., 0.),
+            1000.,
+            Arc::new(Lambertian::new(&Color::new(0.5, 0.5, 0.5))),
+        ));
+        world.add(Sphere::new(
+            &Point3::new(0., 3., 0.),
+            1.,
+            Arc::new(DiffuseLight::new(&Color::new(9., 9., 9.))),
+        ));
+        let lights = world.lights();
+        let black = Color::default();
+        let environment = Gradient::new(&black, &black);
+        let ray = Ray {
+            origin: Point3::new(2., 0.5, 0.),
+            direction: Vec3::new(-2., -0.5, 0.),
+            time: 0.,
+        };
+
+        let estimate = |lights: &HittableObjects| {
+            let context = Context {
+                world: &world,
+                lights,
+                delta_lights: &[],
+                environment: &environment,
+            };
+            let integrator = by_name("path", 5, f64::INFINITY).unwrap();
+            let mut sampler = Sampler::new(3);
+            let n = 100_000;
+            let mut sum = 0.;
+            for _ in 0..n {
+                sum += integrator.radiance(&ray, &context, &mut sampler).g;
+            }
+            sum / f64::from(n)
+        };
+        let with_lights = estimate(&lights);
+        let without = estimate(&HittableObjects::new());
+        assert!((with_lights - 0.5).abs() < 0.01, "{}", with_lights);
+        assert!((without - 0.5).abs() < 0.03, "{}", without);
+    }
+}
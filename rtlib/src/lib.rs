@@ -1,12 +1,37 @@
+pub mod aabb;
+pub mod bvh;
 pub mod camera;
 pub mod color;
+pub mod environment;
+pub mod framebuffer;
+pub mod hdr;
 pub mod hit;
+pub mod ies;
+pub mod instance;
+pub mod integrator;
+pub mod light;
 pub mod material;
+pub mod medium;
+pub mod microfacet;
+pub mod obj;
+pub mod onb;
+pub mod perlin;
 pub mod point3;
+pub mod principled;
+pub mod quad;
 pub mod ray;
+pub mod render;
+pub mod sampler;
 pub mod scene;
+pub mod scene_file;
+pub mod sky;
 pub mod sphere;
+pub mod texture;
+pub mod tonemap;
+pub mod transform;
+pub mod triangle;
 pub mod vec3;
+pub mod volume;
 
 #[cfg(test)]
 mod tests {
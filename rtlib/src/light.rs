@@ -0,0 +1,260 @@
+// Lights with no surface: points, spots and distant suns. Rays can never hit
+// them, so they only contribute when the integrator aims shadow rays at them.
+
+use std::sync::Arc;
+
+use crate::{color::Color, ies::IesProfile, onb::Onb, point3::Point3, vec3::Vec3};
+
+pub trait Light: Send + Sync {
+    // Light from here arriving at `p`, if any does.
+    fn illuminate(&self, p: &Point3) -> Option<Incident>;
+}
+
+pub struct Incident {
+    // Unit vector from `p` toward the light.
+    pub direction: Vec3,
+    // How far away the light is, along `direction`.
+    pub distance: f64,
+    // Irradiance on a surface facing the light. A BSDF's `eval`, which
+    // includes the cosine term, times this is the light reflected.
+    pub irradiance: Color,
+}
+
+// Shines equally in every direction. `intensity` is the irradiance it gives
+// at a distance of 1.
+pub struct PointLight {
+    pub position: Point3,
+    pub intensity: Color,
+}
+
+impl PointLight {
+    pub const fn new(position: &Point3, intensity: &Color) -> Self {
+        Self {
+            position: *position,
+            intensity: *intensity,
+        }
+    }
+}
+
+impl Light for PointLight {
+    fn illuminate(&self, p: &Point3) -> Option<Incident> {
+        let to_light = self.position - *p;
+        let distance = to_light.mag();
+        Some(Incident {
+            direction: to_light / distance,
+            distance,
+            irradiance: (1. / (distance * distance)) * self.intensity,
+        })
+    }
+}
+
+// A point light that only shines within `cone_angle` degrees of `direction`,
+// fading out over the last `cone_delta` degrees. `intensity` is as for
+// `PointLight`, down the middle of the cone.
+//
+// With an IES profile, the profile shapes the beam instead of the cone, and
+// `intensity` is its brightest direction. The profile's axis (its vertical
+// angle 0) points along `direction`, and its horizontal angle 0 leans toward
+// +y, or toward -z if the light points straight up or down; `orient` turns it
+// another way.
+pub struct SpotLight {
+    pub position: Point3,
+    frame: Onb,
+    pub intensity: Color,
+    cos_outer: f64,
+    cos_inner: f64,
+    pub profile: Option<Arc<IesProfile>>,
+}
+
+impl SpotLight {
+    pub fn new(
+        position: &Point3,
+        direction: &Vec3,
+        intensity: &Color,
+        cone_angle: f64,
+        cone_delta: f64,
+    ) -> Self {
+        let mut spot = Self {
+            position: *position,
+            frame: Onb::from_w(direction),
+            intensity: *intensity,
+            cos_outer: cone_angle.to_radians().cos(),
+            cos_inner: (cone_angle - cone_delta).max(0.).to_radians().cos(),
+            profile: None,
+        };
+        let up = Vec3::new(0., 1., 0.);
+        if spot.frame.w.cross(up).mag() > 1e-9 {
+            spot.orient(&up);
+        } else {
+            spot.orient(&Vec3::new(0., 0., -1.));
+        }
+        spot
+    }
+
+    // Turns the light about its axis, so that its profile's horizontal angle
+    // 0 leans toward `up`, and 90 is a quarter turn counterclockwise from
+    // there, looking back at the light.
+    //
+    // Panics if `up` is zero or along the light's axis.
+    pub fn orient(&mut self, up: &Vec3) {
+        let w = self.frame.w;
+        let u = *up - up.dot(w) * w;
+        assert!(
+            u.mag() > 1e-9 * up.mag(),
+            "up must not be zero, or along the light's axis"
+        );
+        let u = u.unit();
+        self.frame = Onb {
+            u,
+            v: w.cross(u),
+            w,
+        };
+    }
+
+    // How much of `intensity` goes along `w`, a unit vector from the light.
+    fn falloff(&self, w: &Vec3) -> f64 {
+        let local = self.frame.to_local(w);
+        if let Some(profile) = &self.profile {
+            if profile.max() <= 0. {
+                return 0.;
+            }
+            let vertical = local.z.clamp(-1., 1.).acos().to_degrees();
+            let horizontal = local.y.atan2(local.x).to_degrees();
+            return profile.candela(vertical, horizontal) / profile.max();
+        }
+
+        let cos = local.z;
+        if cos >= self.cos_inner {
+            1.
+        } else if cos <= self.cos_outer {
+            0.
+        } else {
+            let t = (cos - self.cos_outer) / (self.cos_inner - self.cos_outer);
+            t * t * 2f64.mul_add(-t, 3.)
+        }
+    }
+}
+
+impl Light for SpotLight {
+    fn illuminate(&self, p: &Point3) -> Option<Incident> {
+        let to_light = self.position - *p;
+        let distance = to_light.mag();
+        let direction = to_light / distance;
+        let falloff = self.falloff(&-direction);
+        if falloff <= 0. {
+            return None;
+        }
+        Some(Incident {
+            direction,
+            distance,
+            irradiance: (falloff / (distance * distance)) * self.intensity,
+        })
+    }
+}
+
+// Light from infinitely far away, all travelling along `direction`, such as
+// sunlight. `irradiance` is what it gives a surface facing it.
+pub struct DirectionalLight {
+    direction: Vec3,
+    pub irradiance: Color,
+}
+
+impl DirectionalLight {
+    pub fn new(direction: &Vec3, irradiance: &Color) -> Self {
+        Self {
+            direction: direction.unit(),
+            irradiance: *irradiance,
+        }
+    }
+}
+
+impl Light for DirectionalLight {
+    fn illuminate(&self, _p: &Point3) -> Option<Incident> {
+        Some(Incident {
+            direction: -self.direction,
+            distance: f64::INFINITY,
+            irradiance: self.irradiance,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::{DirectionalLight, Light, PointLight, SpotLight};
+    use crate::{color::Color, ies::IesProfile, point3::Point3, vec3::Vec3};
+
+    #[test]
+    fn lights_fall_off() {
+        let white = Color::new(1., 1., 1.);
+        let point = PointLight::new(&Point3::new(0., 4., 0.), &white);
+        let incident = point.illuminate(&Point3::new(0., 2., 0.)).unwrap();
+        assert!((incident.distance - 2.).abs() < 1e-12);
+        assert!((incident.direction - Vec3::new(0., 1., 0.)).mag() < 1e-12);
+        assert!((incident.irradiance.r - 0.25).abs() < 1e-12);
+
+        // Full inside 20 degrees, about half way through the fade at 25, and
+        // dark past 30.
+        let spot = SpotLight::new(
+            &Point3::new(0., 1., 0.),
+            &Vec3::new(0., -1., 0.),
+            &white,
+            30.,
+            10.,
+        );
+        let at = |degrees: f64| {
+            let t = degrees.to_radians();
+            spot.illuminate(&Point3::new(t.sin(), 1. - t.cos(), 0.))
+                .map_or(0., |i| i.irradiance.g)
+        };
+        assert!((at(10.) - 1.).abs() < 1e-12);
+        assert!((at(25.) - 0.5).abs() < 0.1);
+        assert!(at(35.) == 0.);
+
+        let sun = DirectionalLight::new(&Vec3::new(0., -2., 0.), &white);
+        let incident = sun.illuminate(&Point3::zero()).unwrap();
+        assert!(incident.distance.is_infinite());
+        assert!((incident.direction - Vec3::new(0., 1., 0.)).mag() < 1e-12);
+    }
+
+    #[test]
+    fn profiles_shape_spots() {
+        let text = "TILT=NONE\n1 1000 1 3 1 1 2 0 0 0\n1 1 10\n0 45 90\n0\n50 100 0\n";
+        let mut spot = SpotLight::new(
+            &Point3::zero(),
+            &Vec3::new(0., 0., -1.),
+            &Color::new(2., 2., 2.),
+            10.,
+            0.,
+        );
+        spot.profile = Some(Arc::new(IesProfile::parse(text).unwrap()));
+
+        // The profile is brightest at 45 degrees off the axis, and wider than
+        // the cone, which it replaces.
+        let below = spot.illuminate(&Point3::new(0., 0., -1.)).unwrap();
+        assert!((below.irradiance.r - 1.).abs() < 1e-12);
+        let off_axis = spot.illuminate(&Point3::new(1., 0., -1.)).unwrap();
+        assert!((off_axis.irradiance.r - 1.).abs() < 1e-12);
+
+        // A downlight that's narrower at horizontal angle 90 than at 0. That
+        // plane faces -z unless it's turned.
+        let text =
+            "TILT=NONE\n1 1000 1 3 2 1 2 0 0 0\n1 1 10\n0 45 90\n0 90\n100 100 0\n100 50 0\n";
+        let mut spot = SpotLight::new(
+            &Point3::zero(),
+            &Vec3::new(0., -1., 0.),
+            &Color::new(1., 1., 1.),
+            10.,
+            0.,
+        );
+        spot.profile = Some(Arc::new(IesProfile::parse(text).unwrap()));
+        let at = |spot: &SpotLight, p| spot.illuminate(&p).map_or(0., |i| i.irradiance.r);
+        let (north, east) = (Point3::new(0., -1., -1.), Point3::new(1., -1., 0.));
+        assert!((at(&spot, north) - 0.5).abs() < 1e-12);
+        assert!((at(&spot, east) - 0.25).abs() < 1e-12);
+        spot.orient(&Vec3::new(1., 0.5, 0.));
+        assert!((at(&spot, north) - 0.25).abs() < 1e-12);
+        assert!((at(&spot, east) - 0.5).abs() < 1e-12);
+    }
+}
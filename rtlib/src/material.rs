@@ -1,14 +1,126 @@
+use std::{f64::consts::PI, sync::Arc};
+
 use rand::Rng;
 
-use crate::{color::Color, hit, ray::Ray, vec3::Vec3};
+use crate::{
+    color::Color,
+    hit,
+    microfacet::{self, fresnel_conductor, fresnel_dielectric, Ggx},
+    onb::Onb,
+    point3::Point3,
+    ray::Ray,
+    sampler::Sampler,
+    texture::{SolidColor, Texture},
+    vec3::Vec3,
+};
 
 pub struct Record {
     pub attenuation: Color,
     pub scattered: Ray,
+    // Density, per unit solid angle, with which `scattered` was picked. It's
+    // `None` for specular (mirror-like) scattering, which only ever picks one
+    // direction, and so can't be aimed at a light.
+    pub pdf: Option<f64>,
+}
+
+// How light scatters at one point, in its shading frame: an orthonormal basis
+// with the shading normal along +z. `wo` points back along the incoming ray,
+// and `wi` along the scattered one. Both are unit vectors.
+pub trait Bsdf {
+    // The BSDF times the cosine term, |wi.z|. Zero for specular scattering.
+    fn eval(&self, wo: &Vec3, wi: &Vec3) -> Color;
+
+    // Picks `wi`, given three numbers uniformly distributed in [0, 1).
+    fn sample(&self, wo: &Vec3, u: [f64; 3]) -> Option<Sample>;
+
+    // Density, per unit solid angle, with which `sample` picks `wi`. Zero for
+    // specular scattering.
+    fn pdf(&self, wo: &Vec3, wi: &Vec3) -> f64;
+}
+
+pub struct Sample {
+    pub wi: Vec3,
+    // `eval / pdf`, or for specular scattering, the fraction of light that
+    // goes along `wi`.
+    pub weight: Color,
+    // As for `Record`.
+    pub pdf: Option<f64>,
+}
+
+pub trait Material: Send + Sync {
+    // How light scatters at `h_rec`, or `None` if it doesn't.
+    fn bsdf(&self, h_rec: &hit::Record) -> Option<Box<dyn Bsdf>>;
+
+    // Light given off by the surface itself. Most materials don't glow.
+    fn emitted(&self, _u: f64, _v: f64, _p: &Point3) -> Color {
+        Color::default()
+    }
+
+    fn is_emissive(&self) -> bool {
+        false
+    }
+
+    // The rest are `bsdf`, in world space.
+
+    fn scatter(&self, ray_in: &Ray, h_rec: &hit::Record, sampler: &mut Sampler) -> Option<Record> {
+        let bsdf = self.bsdf(h_rec)?;
+        let (frame, wo) = shading_frame(ray_in, h_rec);
+        let sample = bsdf.sample(&wo, [sampler.gen(), sampler.gen(), sampler.gen()])?;
+        Some(Record {
+            attenuation: sample.weight,
+            scattered: Ray {
+                origin: h_rec.p,
+                direction: frame.local(&sample.wi),
+                time: ray_in.time,
+            },
+            pdf: sample.pdf,
+        })
+    }
+
+    // The BSDF times the cosine term, for scattering `ray_in` into
+    // `direction`.
+    fn eval(&self, ray_in: &Ray, h_rec: &hit::Record, direction: &Vec3) -> Color {
+        self.bsdf(h_rec).map_or_else(Color::default, |bsdf| {
+            let (frame, wo) = shading_frame(ray_in, h_rec);
+            bsdf.eval(&wo, &frame.to_local(&direction.unit()))
+        })
+    }
+
+    // Density with which `scatter` would pick `direction`.
+    fn scattering_pdf(&self, ray_in: &Ray, h_rec: &hit::Record, direction: &Vec3) -> f64 {
+        self.bsdf(h_rec).map_or(0., |bsdf| {
+            let (frame, wo) = shading_frame(ray_in, h_rec);
+            bsdf.pdf(&wo, &frame.to_local(&direction.unit()))
+        })
+    }
+}
+
+// A frame around the shading normal, and the direction back along `ray_in`
+// in it.
+fn shading_frame(ray_in: &Ray, h_rec: &hit::Record) -> (Onb, Vec3) {
+    let frame = Onb::from_w(&h_rec.normal);
+    let wo = frame.to_local(&-ray_in.direction.unit());
+    (frame, wo)
+}
+
+// A cosine-weighted direction about +z, from two uniform numbers.
+pub fn cosine_direction(u1: f64, u2: f64) -> Vec3 {
+    let r = u1.sqrt();
+    let phi = 2. * PI * u2;
+    Vec3::new(r * phi.cos(), r * phi.sin(), (1. - u1).sqrt())
+}
+
+// A uniformly distributed unit vector, from two uniform numbers.
+fn sphere_direction(u1: f64, u2: f64) -> Vec3 {
+    let z = 2f64.mul_add(-u1, 1.);
+    let r = z.mul_add(-z, 1.).max(0.).sqrt();
+    let phi = 2. * PI * u2;
+    Vec3::new(r * phi.cos(), r * phi.sin(), z)
 }
 
-pub trait Material {
-    fn scatter(&self, ray_in: &Ray, h_rec: &hit::Record) -> Option<Record>;
+// The mirror image of `wo` about the normal.
+fn mirror(wo: &Vec3) -> Vec3 {
+    Vec3::new(-wo.x, -wo.y, wo.z)
 }
 
 #[derive(Default)]
@@ -21,71 +133,111 @@ impl Blank {
 }
 
 impl Material for Blank {
-    fn scatter(&self, _ray_in: &Ray, _h_rec: &hit::Record) -> Option<Record> {
+    fn bsdf(&self, _h_rec: &hit::Record) -> Option<Box<dyn Bsdf>> {
         None
     }
 }
 
-#[derive(Default)]
 pub struct Lambertian {
-    pub albedo: Color,
+    pub albedo: Arc<dyn Texture>,
 }
 
 impl Lambertian {
-    pub const fn new(albedo: &Color) -> Self {
-        Self { albedo: *albedo }
+    pub fn new(albedo: &Color) -> Self {
+        Self::textured(Arc::new(SolidColor::new(albedo)))
+    }
+
+    pub fn textured(albedo: Arc<dyn Texture>) -> Self {
+        Self { albedo }
     }
 }
 
 impl Material for Lambertian {
-    fn scatter(&self, _ray_in: &Ray, h_rec: &hit::Record) -> Option<Record> {
-        let mut scatter_direction = h_rec.normal + Vec3::rand_unit();
+    fn bsdf(&self, h_rec: &hit::Record) -> Option<Box<dyn Bsdf>> {
+        Some(Box::new(LambertianBsdf {
+            albedo: self.albedo.value(h_rec.u, h_rec.v, &h_rec.p),
+        }))
+    }
+}
 
-        // Catch degenerate scatter direction.
-        if scatter_direction.is_near_zero() {
-            scatter_direction = h_rec.normal;
-        };
+struct LambertianBsdf {
+    albedo: Color,
+}
 
-        Some(Record {
-            scattered: Ray {
-                origin: h_rec.p,
-                direction: scatter_direction,
-            },
-            attenuation: self.albedo,
+impl Bsdf for LambertianBsdf {
+    fn eval(&self, wo: &Vec3, wi: &Vec3) -> Color {
+        self.pdf(wo, wi) * self.albedo
+    }
+
+    fn sample(&self, wo: &Vec3, u: [f64; 3]) -> Option<Sample> {
+        let wi = cosine_direction(u[0], u[1]);
+        Some(Sample {
+            wi,
+            weight: self.albedo,
+            pdf: Some(self.pdf(wo, &wi)),
         })
     }
+
+    // Cosine-weighted, as is `sample`.
+    fn pdf(&self, _wo: &Vec3, wi: &Vec3) -> f64 {
+        wi.z.max(0.) / PI
+    }
 }
 
-#[derive(Default)]
 pub struct Metal {
-    pub albedo: Color,
-    pub fuzz: f64,
+    pub albedo: Arc<dyn Texture>,
+    pub fuzz: Arc<dyn Texture>,
 }
 
 impl Metal {
-    pub const fn new(albedo: &Color, fuzz: f64) -> Self {
-        Self {
-            albedo: *albedo,
-            fuzz,
-        }
+    pub fn new(albedo: &Color, fuzz: f64) -> Self {
+        Self::textured(
+            Arc::new(SolidColor::new(albedo)),
+            Arc::new(SolidColor::gray(fuzz)),
+        )
+    }
+
+    pub fn textured(albedo: Arc<dyn Texture>, fuzz: Arc<dyn Texture>) -> Self {
+        Self { albedo, fuzz }
     }
 }
 
 impl Material for Metal {
-    fn scatter(&self, ray_in: &Ray, h_rec: &hit::Record) -> Option<Record> {
-        let reflected = ray_in.direction.unit().reflect(&h_rec.normal);
-        let scattered = Ray {
-            origin: h_rec.p,
-            direction: reflected + self.fuzz * Vec3::rand_in_unit_sphere(),
-        };
-        if scattered.direction.dot(h_rec.normal) > 0. {
-            Some(Record {
-                attenuation: self.albedo,
-                scattered,
-            })
-        } else {
-            None
+    fn bsdf(&self, h_rec: &hit::Record) -> Option<Box<dyn Bsdf>> {
+        Some(Box::new(MetalBsdf {
+            albedo: self.albedo.value(h_rec.u, h_rec.v, &h_rec.p),
+            fuzz: self.fuzz.scalar(h_rec.u, h_rec.v, &h_rec.p),
+        }))
+    }
+}
+
+// The mirror direction, nudged by a random point in a ball of radius `fuzz`.
+// There's no density to go with that, so it's treated as specular.
+struct MetalBsdf {
+    albedo: Color,
+    fuzz: f64,
+}
+
+impl Bsdf for MetalBsdf {
+    fn eval(&self, _wo: &Vec3, _wi: &Vec3) -> Color {
+        Color::default()
+    }
+
+    fn sample(&self, wo: &Vec3, u: [f64; 3]) -> Option<Sample> {
+        let nudge = (self.fuzz * u[0].cbrt()) * sphere_direction(u[1], u[2]);
+        let wi = mirror(wo) + nudge;
+        if wi.z <= 0. {
+            return None;
         }
+        Some(Sample {
+            wi: wi.unit(),
+            weight: self.albedo,
+            pdf: None,
+        })
+    }
+
+    fn pdf(&self, _wo: &Vec3, _wi: &Vec3) -> f64 {
+        0.
     }
 }
 
@@ -106,34 +258,572 @@ impl Dielectric {
 }
 
 impl Material for Dielectric {
-    fn scatter(&self, ray_in: &Ray, h_rec: &hit::Record) -> Option<Record> {
-        let attenuation = Color::new(1., 1., 1.);
-        let refraction_ratio = if h_rec.front_face {
-            1. / self.ir
-        } else {
-            self.ir
-        };
+    fn bsdf(&self, h_rec: &hit::Record) -> Option<Box<dyn Bsdf>> {
+        Some(Box::new(DielectricBsdf {
+            refraction_ratio: if h_rec.front_face {
+                1. / self.ir
+            } else {
+                self.ir
+            },
+        }))
+    }
+}
+
+// A smooth boundary, which either mirrors or refracts.
+struct DielectricBsdf {
+    // Index of refraction before the surface over the one past it.
+    refraction_ratio: f64,
+}
 
-        let unit_direction = ray_in.direction.unit();
-        let cos_theta = (-unit_direction).dot(h_rec.normal).min(1.);
+impl Bsdf for DielectricBsdf {
+    fn eval(&self, _wo: &Vec3, _wi: &Vec3) -> Color {
+        Color::default()
+    }
+
+    // Reflects with the Fresnel reflectance's probability, and otherwise
+    // refracts, so that either way every bit of light carries on.
+    fn sample(&self, wo: &Vec3, u: [f64; 3]) -> Option<Sample> {
+        let cos_theta = wo.z.min(1.);
         let sin_theta = (1. - cos_theta * cos_theta).sqrt();
 
-        let cannot_refract = refraction_ratio * sin_theta > 1.;
-        let mut rng = rand::thread_rng();
-        let direction = if cannot_refract
-            || Self::reflectance(cos_theta, refraction_ratio) > rng.gen::<f64>()
-        {
-            unit_direction.reflect(&h_rec.normal)
+        let cannot_refract = self.refraction_ratio * sin_theta > 1.;
+        let wi =
+            if cannot_refract || Dielectric::reflectance(cos_theta, self.refraction_ratio) > u[0] {
+                mirror(wo)
+            } else {
+                (-*wo).refract(&Vec3::new(0., 0., 1.), self.refraction_ratio)
+            };
+
+        Some(Sample {
+            wi,
+            weight: Color::new(1., 1., 1.),
+            pdf: None,
+        })
+    }
+
+    fn pdf(&self, _wo: &Vec3, _wi: &Vec3) -> f64 {
+        0.
+    }
+}
+
+// Metals with measured optical constants.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ConductorKind {
+    Gold,
+    Copper,
+    Aluminium,
+    Silver,
+}
+
+impl ConductorKind {
+    // The complex index of refraction, `eta + i k`, at roughly the red, green
+    // and blue wavelengths.
+    pub const fn ior(self) -> (Color, Color) {
+        match self {
+            Self::Gold => (
+                Color::new(0.143, 0.374, 1.442),
+                Color::new(3.983, 2.386, 1.603),
+            ),
+            Self::Copper => (
+                Color::new(0.200, 0.924, 1.102),
+                Color::new(3.912, 2.452, 2.142),
+            ),
+            Self::Aluminium => (
+                Color::new(1.657, 0.880, 0.521),
+                Color::new(9.224, 6.270, 4.837),
+            ),
+            Self::Silver => (
+                Color::new(0.155, 0.117, 0.138),
+                Color::new(4.828, 3.122, 2.147),
+            ),
+        }
+    }
+}
+
+// A rough metal: GGX microfacets, each reflecting like a perfect mirror with
+// the Fresnel reflectance of the complex index of refraction `eta + i k`.
+// `roughness` is perceptual, in [0, 1].
+pub struct Conductor {
+    pub eta: Color,
+    pub k: Color,
+    pub roughness: Arc<dyn Texture>,
+}
+
+impl Conductor {
+    pub fn new(eta: &Color, k: &Color, roughness: f64) -> Self {
+        Self::textured(eta, k, Arc::new(SolidColor::gray(roughness)))
+    }
+
+    pub fn textured(eta: &Color, k: &Color, roughness: Arc<dyn Texture>) -> Self {
+        Self {
+            eta: *eta,
+            k: *k,
+            roughness,
+        }
+    }
+
+    pub fn preset(kind: ConductorKind, roughness: Arc<dyn Texture>) -> Self {
+        let (eta, k) = kind.ior();
+        Self::textured(&eta, &k, roughness)
+    }
+}
+
+impl Material for Conductor {
+    fn bsdf(&self, h_rec: &hit::Record) -> Option<Box<dyn Bsdf>> {
+        Some(Box::new(ConductorBsdf {
+            ggx: Ggx::isotropic(self.roughness.scalar(h_rec.u, h_rec.v, &h_rec.p)),
+            eta: self.eta,
+            k: self.k,
+        }))
+    }
+}
+
+struct ConductorBsdf {
+    ggx: Ggx,
+    eta: Color,
+    k: Color,
+}
+
+impl Bsdf for ConductorBsdf {
+    fn eval(&self, wo: &Vec3, wi: &Vec3) -> Color {
+        if wo.z <= 0. || wi.z <= 0. {
+            return Color::default();
+        }
+        let h = (*wo + *wi).unit();
+        let fresnel = fresnel_conductor(wo.dot(h), &self.eta, &self.k);
+        (self.ggx.d(&h) * self.ggx.g(wo, wi) / (4. * wo.z)) * fresnel
+    }
+
+    fn sample(&self, wo: &Vec3, u: [f64; 3]) -> Option<Sample> {
+        if wo.z <= 0. {
+            return None;
+        }
+        let h = self.ggx.sample_visible(wo, u[0], u[1]);
+        let wi = microfacet::reflect(wo, &h);
+        if wi.z <= 0. {
+            return None;
+        }
+
+        // Sampling visible normals leaves only the Fresnel term and the
+        // shadowing of `wi`.
+        let fresnel = fresnel_conductor(wo.dot(h), &self.eta, &self.k);
+        Some(Sample {
+            wi,
+            weight: (self.ggx.g(wo, &wi) / self.ggx.g1(wo)) * fresnel,
+            pdf: Some(self.ggx.visible_pdf(wo, &h) / (4. * wo.dot(h))),
+        })
+    }
+
+    fn pdf(&self, wo: &Vec3, wi: &Vec3) -> f64 {
+        if wo.z <= 0. || wi.z <= 0. {
+            return 0.;
+        }
+        let h = (*wo + *wi).unit();
+        self.ggx.visible_pdf(wo, &h) / (4. * wo.dot(h))
+    }
+}
+
+// Frosted glass: GGX microfacets, each reflecting or refracting like a
+// `Dielectric`. `roughness` is perceptual, in [0, 1].
+//
+// Like `Dielectric`, it doesn't rescale radiance by the change in index of
+// refraction; for closed objects, that cancels out on the way back out.
+pub struct RoughDielectric {
+    pub ir: f64, // Index of refraction.
+    pub roughness: Arc<dyn Texture>,
+}
+
+impl RoughDielectric {
+    pub fn new(ir: f64, roughness: f64) -> Self {
+        Self::textured(ir, Arc::new(SolidColor::gray(roughness)))
+    }
+
+    pub fn textured(ir: f64, roughness: Arc<dyn Texture>) -> Self {
+        Self { ir, roughness }
+    }
+}
+
+impl Material for RoughDielectric {
+    fn bsdf(&self, h_rec: &hit::Record) -> Option<Box<dyn Bsdf>> {
+        Some(Box::new(RoughDielectricBsdf {
+            ggx: Ggx::isotropic(self.roughness.scalar(h_rec.u, h_rec.v, &h_rec.p)),
+            eta: if h_rec.front_face {
+                self.ir
+            } else {
+                1. / self.ir
+            },
+        }))
+    }
+}
+
+struct RoughDielectricBsdf {
+    ggx: Ggx,
+    // Ratio of the index of refraction past the surface to the one before it.
+    eta: f64,
+}
+
+impl RoughDielectricBsdf {
+    // The microfacet normal that would scatter `wo` into `wi`, on the same
+    // side as `wo`. `None` if no microfacet could.
+    fn half_vector(&self, wo: &Vec3, wi: &Vec3) -> Option<Vec3> {
+        let reflected = wi.z > 0.;
+        let h = if reflected {
+            *wo + *wi
         } else {
-            unit_direction.refract(&h_rec.normal, refraction_ratio)
+            *wo + self.eta * *wi
         };
+        if h.is_near_zero() {
+            return None;
+        }
+        let h = if h.z < 0. { -h.unit() } else { h.unit() };
+        let valid = wo.dot(h) > 0. && (wi.dot(h) > 0.) == reflected;
+        Some(h).filter(|_| valid)
+    }
 
-        Some(Record {
-            attenuation,
-            scattered: Ray {
-                origin: h_rec.p,
-                direction,
-            },
+    // The BSDF times the cosine term, and the density with which `sample`
+    // picks `wi`.
+    fn eval_pdf(&self, wo: &Vec3, wi: &Vec3) -> (f64, f64) {
+        let h = match self.half_vector(wo, wi) {
+            Some(h) if wo.z > 0. && wi.z != 0. => h,
+            _ => return (0., 0.),
+        };
+        let (ggx, eta) = (&self.ggx, self.eta);
+        let fresnel = fresnel_dielectric(wo.dot(h), eta);
+        let visible = ggx.visible_pdf(wo, &h);
+        let masking = ggx.d(&h) * ggx.g(wo, wi);
+
+        if wi.z > 0. {
+            (
+                fresnel * masking / (4. * wo.z),
+                fresnel * visible / (4. * wo.dot(h)),
+            )
+        } else {
+            // The change of variables from `h` to `wi` through refraction.
+            let denom = eta.mul_add(wi.dot(h), wo.dot(h));
+            let jacobian = eta * eta * wi.dot(h).abs() / (denom * denom);
+            (
+                (1. - fresnel) * masking * wo.dot(h) * jacobian / wo.z,
+                (1. - fresnel) * visible * jacobian,
+            )
+        }
+    }
+}
+
+impl Bsdf for RoughDielectricBsdf {
+    fn eval(&self, wo: &Vec3, wi: &Vec3) -> Color {
+        let (f, _) = self.eval_pdf(wo, wi);
+        Color::new(f, f, f)
+    }
+
+    fn sample(&self, wo: &Vec3, u: [f64; 3]) -> Option<Sample> {
+        if wo.z <= 0. {
+            return None;
+        }
+        let h = self.ggx.sample_visible(wo, u[0], u[1]);
+
+        // Reflect or refract in proportion to the Fresnel reflectance, which
+        // then cancels out of the weight.
+        let fresnel = fresnel_dielectric(wo.dot(h), self.eta);
+        let wi = if u[2] < fresnel {
+            Some(microfacet::reflect(wo, &h)).filter(|wi| wi.z > 0.)
+        } else {
+            microfacet::refract(wo, &h, self.eta).filter(|wi| wi.z < 0.)
+        }?;
+
+        let (_, pdf) = self.eval_pdf(wo, &wi);
+        if pdf <= 0. {
+            return None;
+        }
+        let weight = self.ggx.g(wo, &wi) / self.ggx.g1(wo);
+        Some(Sample {
+            wi,
+            weight: Color::new(weight, weight, weight),
+            pdf: Some(pdf),
+        })
+    }
+
+    fn pdf(&self, wo: &Vec3, wi: &Vec3) -> f64 {
+        self.eval_pdf(wo, wi).1
+    }
+}
+
+// A light source. It absorbs everything that hits it.
+pub struct DiffuseLight {
+    pub emit: Arc<dyn Texture>,
+}
+
+impl DiffuseLight {
+    pub fn new(emit: &Color) -> Self {
+        Self::textured(Arc::new(SolidColor::new(emit)))
+    }
+
+    pub fn textured(emit: Arc<dyn Texture>) -> Self {
+        Self { emit }
+    }
+}
+
+impl Material for DiffuseLight {
+    fn bsdf(&self, _h_rec: &hit::Record) -> Option<Box<dyn Bsdf>> {
+        None
+    }
+
+    fn emitted(&self, u: f64, v: f64, p: &Point3) -> Color {
+        self.emit.value(u, v, p)
+    }
+
+    fn is_emissive(&self) -> bool {
+        true
+    }
+}
+
+// The phase function of a participating medium that scatters equally in every
+// direction.
+pub struct Isotropic {
+    pub albedo: Arc<dyn Texture>,
+}
+
+impl Isotropic {
+    pub fn new(albedo: &Color) -> Self {
+        Self::textured(Arc::new(SolidColor::new(albedo)))
+    }
+
+    pub fn textured(albedo: Arc<dyn Texture>) -> Self {
+        Self { albedo }
+    }
+}
+
+impl Material for Isotropic {
+    fn bsdf(&self, h_rec: &hit::Record) -> Option<Box<dyn Bsdf>> {
+        Some(Box::new(IsotropicPhase {
+            albedo: self.albedo.value(h_rec.u, h_rec.v, &h_rec.p),
+        }))
+    }
+}
+
+struct IsotropicPhase {
+    albedo: Color,
+}
+
+impl Bsdf for IsotropicPhase {
+    // There's no cosine term inside a medium, so this is just the albedo
+    // times the phase function.
+    fn eval(&self, wo: &Vec3, wi: &Vec3) -> Color {
+        self.pdf(wo, wi) * self.albedo
+    }
+
+    fn sample(&self, wo: &Vec3, u: [f64; 3]) -> Option<Sample> {
+        let wi = sphere_direction(u[0], u[1]);
+        Some(Sample {
+            wi,
+            weight: self.albedo,
+            pdf: Some(self.pdf(wo, &wi)),
+        })
+    }
+
+    fn pdf(&self, _wo: &Vec3, _wi: &Vec3) -> f64 {
+        1. / (4. * PI)
+    }
+}
+
+// The Henyey–Greenstein phase function, which favors scattering forward when
+// `g` is positive (e.g. clouds) and backward when it's negative. `g` is the
+// mean cosine of the scattering angle, in (-1, 1); zero is isotropic.
+pub struct HenyeyGreenstein {
+    pub albedo: Arc<dyn Texture>,
+    pub g: f64,
+}
+
+impl HenyeyGreenstein {
+    pub fn new(albedo: &Color, g: f64) -> Self {
+        Self::textured(Arc::new(SolidColor::new(albedo)), g)
+    }
+
+    // Panics unless `g` is in (-1, 1).
+    pub fn textured(albedo: Arc<dyn Texture>, g: f64) -> Self {
+        assert!(g.abs() < 1., "g must be in (-1, 1)");
+        Self { albedo, g }
+    }
+}
+
+impl Material for HenyeyGreenstein {
+    fn bsdf(&self, h_rec: &hit::Record) -> Option<Box<dyn Bsdf>> {
+        Some(Box::new(HenyeyGreensteinPhase {
+            albedo: self.albedo.value(h_rec.u, h_rec.v, &h_rec.p),
+            g: self.g,
+        }))
+    }
+}
+
+struct HenyeyGreensteinPhase {
+    albedo: Color,
+    g: f64,
+}
+
+impl HenyeyGreensteinPhase {
+    // Density over the sphere for a scattering angle with cosine `cos_theta`,
+    // measured from the incoming direction of travel.
+    fn phase(&self, cos_theta: f64) -> f64 {
+        let g = self.g;
+        let denom = (2. * g).mul_add(-cos_theta, g.mul_add(g, 1.));
+        (1. - g * g) / (4. * PI * denom * denom.sqrt())
+    }
+}
+
+impl Bsdf for HenyeyGreensteinPhase {
+    fn eval(&self, wo: &Vec3, wi: &Vec3) -> Color {
+        self.pdf(wo, wi) * self.albedo
+    }
+
+    fn sample(&self, wo: &Vec3, u: [f64; 3]) -> Option<Sample> {
+        // Invert the CDF of the scattering angle's cosine.
+        let g = self.g;
+        let cos_theta = if g.abs() < 1e-3 {
+            2f64.mul_add(-u[0], 1.)
+        } else {
+            let s = (1. - g * g) / (2. * g).mul_add(u[0], 1. - g);
+            (g.mul_add(g, 1.) - s * s) / (2. * g)
+        }
+        .clamp(-1., 1.);
+        let sin_theta = cos_theta.mul_add(-cos_theta, 1.).max(0.).sqrt();
+        let phi = 2. * PI * u[1];
+
+        // Around the direction of travel, which is `-wo`.
+        let frame = Onb::from_w(&-*wo);
+        let wi = frame.local(&Vec3::new(
+            sin_theta * phi.cos(),
+            sin_theta * phi.sin(),
+            cos_theta,
+        ));
+        Some(Sample {
+            wi,
+            weight: self.albedo,
+            pdf: Some(self.phase(cos_theta)),
         })
     }
+
+    // Sampling is exact, so this is the phase function itself.
+    fn pdf(&self, wo: &Vec3, wi: &Vec3) -> f64 {
+        self.phase(-wo.dot(*wi))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::PI;
+
+    use rand::Rng;
+
+    use super::{sphere_direction, Dielectric, DiffuseLight, Lambertian, Material, Metal};
+    use crate::{color::Color, hit, point3::Point3, sampler::Sampler, vec3::Vec3};
+
+    fn hit_record(front_face: bool) -> hit::Record {
+        let mut h_rec = hit::Record::new();
+        h_rec.normal = Vec3::new(0., 0., 1.);
+        h_rec.front_face = front_face;
+        h_rec
+    }
+
+    #[test]
+    fn lambertian_samples_what_it_evaluates() {
+        let albedo = Color::new(0.5, 0.25, 1.);
+        let bsdf = Lambertian::new(&albedo).bsdf(&hit_record(true)).unwrap();
+        let wo = Vec3::new(0.6, 0., 0.8);
+
+        let mut sampler = Sampler::new(1);
+        for _ in 0..1000 {
+            let sample = bsdf.sample(&wo, sampler.gen()).unwrap();
+            let pdf = bsdf.pdf(&wo, &sample.wi);
+            assert!((sample.pdf.unwrap() - pdf).abs() < 1e-12);
+            let f = bsdf.eval(&wo, &sample.wi);
+            assert!((sample.weight.g * pdf - f.g).abs() < 1e-12);
+            assert!((sample.wi.mag() - 1.).abs() < 1e-12);
+        }
+
+        // The density integrates to one over the sphere, and nothing goes
+        // through the surface.
+        let n = 100_000;
+        let mut total = 0.;
+        for _ in 0..n {
+            let wi = sphere_direction(sampler.gen(), sampler.gen());
+            total += 4. * PI * bsdf.pdf(&wo, &wi) / f64::from(n);
+        }
+        assert!((total - 1.).abs() < 0.02, "{}", total);
+        let below = Vec3::new(0., 0.6, -0.8);
+        assert!(bsdf.pdf(&wo, &below) == 0. && bsdf.eval(&wo, &below).r == 0.);
+    }
+
+    #[test]
+    fn metal_mirrors_and_fuzzes() {
+        let albedo = Color::new(0.9, 0.6, 0.2);
+        let wo = Vec3::new(0.6, 0., 0.8);
+        let mut sampler = Sampler::new(2);
+
+        let smooth = Metal::new(&albedo, 0.).bsdf(&hit_record(true)).unwrap();
+        let sample = smooth.sample(&wo, sampler.gen()).unwrap();
+        assert!((sample.wi - Vec3::new(-0.6, 0., 0.8)).mag() < 1e-12);
+        assert!(sample.pdf.is_none() && (sample.weight.b - 0.2).abs() < 1e-12);
+        assert!(smooth.eval(&wo, &sample.wi).r == 0. && smooth.pdf(&wo, &sample.wi) == 0.);
+
+        // Fuzz spreads reflections out, by up to asin(fuzz) from the mirror
+        // direction, but never below the surface.
+        let fuzzy = Metal::new(&albedo, 0.5).bsdf(&hit_record(true)).unwrap();
+        let mut widest: f64 = 1.;
+        for _ in 0..1000 {
+            if let Some(sample) = fuzzy.sample(&wo, sampler.gen()) {
+                assert!(sample.wi.z > 0. && (sample.wi.mag() - 1.).abs() < 1e-12);
+                widest = widest.min(sample.wi.dot(Vec3::new(-0.6, 0., 0.8)));
+            }
+        }
+        assert!(widest < 0.95 && widest >= 0.75_f64.sqrt() - 1e-9);
+    }
+
+    #[test]
+    fn dielectric_follows_snell_and_fresnel() {
+        let glass = Dielectric::new(1.5);
+
+        // Straight on, about 4% reflects and the rest goes straight through.
+        let bsdf = glass.bsdf(&hit_record(true)).unwrap();
+        let wo = Vec3::new(0., 0., 1.);
+        let n = 1000;
+        let mut reflected = 0;
+        for i in 0..n {
+            let u = (f64::from(i) + 0.5) / f64::from(n);
+            let sample = bsdf.sample(&wo, [u, 0., 0.]).unwrap();
+            assert!(sample.pdf.is_none() && sample.weight.r == 1.);
+            if sample.wi.z > 0. {
+                reflected += 1;
+            } else {
+                assert!((sample.wi - Vec3::new(0., 0., -1.)).mag() < 1e-12);
+            }
+        }
+        assert_eq!(reflected, 40);
+
+        // Going in at an angle, the ray bends toward the normal.
+        let wo = Vec3::new(0.6, 0., 0.8);
+        let refracted = bsdf.sample(&wo, [0.99, 0., 0.]).unwrap().wi;
+        assert!(refracted.z < 0.);
+        assert!((refracted.x + 0.6 / 1.5).abs() < 1e-12);
+
+        // Coming out past the critical angle, it's totally internally
+        // reflected.
+        let bsdf = glass.bsdf(&hit_record(false)).unwrap();
+        let wo = Vec3::new(0.8, 0., 0.6);
+        for &u in &[0., 0.5, 0.99] {
+            let wi = bsdf.sample(&wo, [u, 0., 0.]).unwrap().wi;
+            assert!((wi - Vec3::new(-0.8, 0., 0.6)).mag() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn diffuse_lights_emit_and_absorb() {
+        let emit = Color::new(4., 2., 1.);
+        let light = DiffuseLight::new(&emit);
+        assert_eq!(light.emitted(0.3, 0.7, &Point3::new(1., 2., 3.)), emit);
+        assert!(light.bsdf(&hit_record(true)).is_none());
+        assert!(light.is_emissive());
+
+        let gray = Lambertian::new(&Color::new(0.5, 0.5, 0.5));
+        assert_eq!(gray.emitted(0.3, 0.7, &Point3::zero()), Color::default());
+        assert!(!gray.is_emissive());
+    }
 }
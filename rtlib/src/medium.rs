@@ -0,0 +1,157 @@
+use std::sync::Arc;
+
+use rand::Rng;
+
+use crate::{
+    aabb::Aabb,
+    color::Color,
+    hit::{Hittable, Record},
+    material::{Isotropic, Material},
+    ray::Ray,
+    sampler::Sampler,
+    texture::Texture,
+    vec3::Vec3,
+};
+
+// Smoke, fog or mist of uniform density, filling a closed, convex `boundary`.
+// Rays passing through are scattered at an exponentially distributed
+// distance, or leave without ever touching it.
+pub struct ConstantMedium {
+    boundary: Arc<dyn Hittable>,
+    // Negative reciprocal of the density, for sampling free-flight distances.
+    neg_inv_density: f64,
+    phase_function: Arc<dyn Material>,
+}
+
+impl ConstantMedium {
+    pub fn new(boundary: Arc<dyn Hittable>, density: f64, albedo: &Color) -> Self {
+        Self::with_phase_function(boundary, density, Arc::new(Isotropic::new(albedo)))
+    }
+
+    pub fn textured(boundary: Arc<dyn Hittable>, density: f64, albedo: Arc<dyn Texture>) -> Self {
+        Self::with_phase_function(boundary, density, Arc::new(Isotropic::textured(albedo)))
+    }
+
+    pub fn with_phase_function(
+        boundary: Arc<dyn Hittable>,
+        density: f64,
+        phase_function: Arc<dyn Material>,
+    ) -> Self {
+        Self {
+            boundary,
+            neg_inv_density: -1. / density,
+            phase_function,
+        }
+    }
+
+    // The part of the ray between `t_min` and `t_max` that's inside the
+    // boundary, if any.
+    fn interval(
+        &self,
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+        sampler: &mut Sampler,
+    ) -> Option<(f64, f64)> {
+        // Find where the ray enters and leaves the boundary, counting the
+        // whole ray so that origins inside the medium work too.
+        let enter = self
+            .boundary
+            .hit(ray, f64::NEG_INFINITY, f64::INFINITY, sampler)?;
+        let exit = self
+            .boundary
+            .hit(ray, enter.t + 0.0001, f64::INFINITY, sampler)?;
+
+        let t_enter = enter.t.max(t_min).max(0.);
+        let t_exit = exit.t.min(t_max);
+        if t_enter >= t_exit {
+            return None;
+        }
+        Some((t_enter, t_exit))
+    }
+}
+
+impl Hittable for ConstantMedium {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64, sampler: &mut Sampler) -> Option<Record> {
+        let (t_enter, t_exit) = self.interval(ray, t_min, t_max, sampler)?;
+
+        let speed = ray.direction.mag();
+        let distance_inside = (t_exit - t_enter) * speed;
+        let hit_distance = self.neg_inv_density * (1. - sampler.gen::<f64>()).ln();
+        if hit_distance > distance_inside {
+            return None;
+        }
+
+        let mut record = Record::new();
+        record.t = t_enter + hit_distance / speed;
+        record.p = ray.at(record.t);
+        // Neither is meaningful inside a volume.
+        record.normal = Vec3::new(1., 0., 0.);
+        record.front_face = true;
+        record.mat = Arc::clone(&self.phase_function);
+        Some(record)
+    }
+
+    fn hit_surface(
+        &self,
+        _ray: &Ray,
+        _t_min: f64,
+        _t_max: f64,
+        _sampler: &mut Sampler,
+    ) -> Option<Record> {
+        None
+    }
+
+    // Beer–Lambert, exactly.
+    fn transmittance(&self, ray: &Ray, t_min: f64, t_max: f64, sampler: &mut Sampler) -> f64 {
+        match self.interval(ray, t_min, t_max, sampler) {
+            Some((t_enter, t_exit)) => {
+                let distance_inside = (t_exit - t_enter) * ray.direction.mag();
+                (distance_inside / self.neg_inv_density).exp()
+            }
+            None => 1.,
+        }
+    }
+
+    fn bounding_box(&self, time0: f64, time1: f64) -> Option<Aabb> {
+        self.boundary.bounding_box(time0, time1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::ConstantMedium;
+    use crate::{
+        color::Color, hit::Hittable, material::Blank, point3::Point3, ray::Ray, sampler::Sampler,
+        sphere::Sphere, vec3::Vec3,
+    };
+
+    #[test]
+    fn transmittance_follows_beer_lambert() {
+        // A ray through the middle of a unit sphere travels 2 units inside.
+        let boundary = Arc::new(Sphere::new(&Point3::zero(), 1., Arc::new(Blank::new())));
+        let density = 0.5;
+        let medium = ConstantMedium::new(boundary, density, &Color::new(1., 1., 1.));
+        let ray = Ray {
+            origin: Point3::new(0., 0., 5.),
+            direction: Vec3::new(0., 0., -1.),
+            time: 0.,
+        };
+
+        let mut sampler = Sampler::new(7);
+        let n = 20_000;
+        let mut passed = 0;
+        for _ in 0..n {
+            match medium.hit(&ray, 0.001, f64::INFINITY, &mut sampler) {
+                Some(rec) => assert!((4. ..=6.).contains(&rec.t)),
+                None => passed += 1,
+            }
+        }
+        let expected = (-density * 2_f64).exp();
+        assert!((f64::from(passed) / f64::from(n) - expected).abs() < 0.02);
+        let exact = medium.transmittance(&ray, 0.001, f64::INFINITY, &mut sampler);
+        assert!((exact - expected).abs() < 1e-9);
+    }
+}
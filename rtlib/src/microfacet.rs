@@ -0,0 +1,223 @@
+// Building blocks for microfacet BSDFs: the GGX (Trowbridge–Reitz)
+// distribution of microfacet normals, sampling of the normals visible from a
+// given direction, and Fresnel reflectance.
+//
+// Everything works in a local shading frame, where the surface normal is +z.
+
+use std::f64::consts::PI;
+
+use crate::{color::Color, vec3::Vec3};
+
+// Roughness below this is clamped, since a perfectly smooth GGX surface is a
+// delta distribution, which can't be evaluated.
+const MIN_ALPHA: f64 = 1e-3;
+
+// The GGX distribution, with separate roughnesses along the tangent (x) and
+// bitangent (y) for anisotropic surfaces.
+#[derive(Clone, Copy, Debug)]
+pub struct Ggx {
+    pub alpha_x: f64,
+    pub alpha_y: f64,
+}
+
+impl Ggx {
+    pub fn new(alpha_x: f64, alpha_y: f64) -> Self {
+        Self {
+            alpha_x: alpha_x.max(MIN_ALPHA),
+            alpha_y: alpha_y.max(MIN_ALPHA),
+        }
+    }
+
+    // Takes a perceptual roughness in [0, 1], as artists usually set it, and
+    // squares it to get alpha.
+    pub fn isotropic(roughness: f64) -> Self {
+        let alpha = roughness * roughness;
+        Self::new(alpha, alpha)
+    }
+
+    // Density of microfacet normals `h`, per unit solid angle and projected
+    // onto the macrosurface, so it integrates to 1 against `h.z`.
+    pub fn d(&self, h: &Vec3) -> f64 {
+        if h.z <= 0. {
+            return 0.;
+        }
+        let (ax, ay) = (self.alpha_x, self.alpha_y);
+        let e = (h.x / ax).powi(2) + (h.y / ay).powi(2) + h.z * h.z;
+        1. / (PI * ax * ay * e * e)
+    }
+
+    // Smith's auxiliary function, for masking and shadowing.
+    fn lambda(&self, w: &Vec3) -> f64 {
+        if w.z == 0. {
+            return f64::INFINITY;
+        }
+        let a2_tan2 = ((self.alpha_x * w.x).powi(2) + (self.alpha_y * w.y).powi(2)) / (w.z * w.z);
+        0.5 * ((1. + a2_tan2).sqrt() - 1.)
+    }
+
+    // The fraction of microfacets visible from `w`.
+    pub fn g1(&self, w: &Vec3) -> f64 {
+        1. / (1. + self.lambda(w))
+    }
+
+    // The fraction visible from both `wo` and `wi` (height-correlated).
+    pub fn g(&self, wo: &Vec3, wi: &Vec3) -> f64 {
+        1. / (1. + self.lambda(wo) + self.lambda(wi))
+    }
+
+    // Picks a microfacet normal in proportion to how much of it `wo` sees,
+    // from two uniform numbers in [0, 1). Heitz, "Sampling the GGX
+    // Distribution of Visible Normals" (2018).
+    pub fn sample_visible(&self, wo: &Vec3, u1: f64, u2: f64) -> Vec3 {
+        // Stretch to a hemisphere configuration.
+        let (ax, ay) = (self.alpha_x, self.alpha_y);
+        let vh = Vec3::new(ax * wo.x, ay * wo.y, wo.z).unit();
+
+        let len_squared = vh.x * vh.x + vh.y * vh.y;
+        let t1 = if len_squared > 0. {
+            Vec3::new(-vh.y, vh.x, 0.) / len_squared.sqrt()
+        } else {
+            Vec3::new(1., 0., 0.)
+        };
+        let t2 = vh.cross(t1);
+
+        // A point on the projected disk, squashed toward the visible half.
+        let r = u1.sqrt();
+        let phi = 2. * PI * u2;
+        let p1 = r * phi.cos();
+        let s = 0.5 * (1. + vh.z);
+        let p2 = (1. - s).mul_add((1. - p1 * p1).sqrt(), s * r * phi.sin());
+        let nh = p1 * t1 + p2 * t2 + (1. - p1 * p1 - p2 * p2).max(0.).sqrt() * vh;
+
+        // Unstretch.
+        Vec3::new(ax * nh.x, ay * nh.y, nh.z.max(0.)).unit()
+    }
+
+    // Density with which `sample_visible` picks `h`.
+    pub fn visible_pdf(&self, wo: &Vec3, h: &Vec3) -> f64 {
+        if wo.z <= 0. {
+            return 0.;
+        }
+        self.g1(wo) * wo.dot(*h).max(0.) * self.d(h) / wo.z
+    }
+}
+
+// Mirrors `w` about `h`.
+pub fn reflect(w: &Vec3, h: &Vec3) -> Vec3 {
+    2. * w.dot(*h) * *h - *w
+}
+
+// Bends `w` through a surface with microfacet normal `h` (on the same side as
+// `w`), where `eta` is the ratio of the indices of refraction on the far side
+// to the near side. Returns `None` on total internal reflection.
+pub fn refract(w: &Vec3, h: &Vec3, eta: f64) -> Option<Vec3> {
+    let cos_i = w.dot(*h);
+    let sin2_t = (1. - cos_i * cos_i).max(0.) / (eta * eta);
+    if sin2_t >= 1. {
+        return None;
+    }
+    let cos_t = (1. - sin2_t).sqrt();
+    Some(-*w / eta + (cos_i / eta - cos_t) * *h)
+}
+
+// Unpolarized reflectance of a dielectric boundary, at an angle whose cosine
+// is `cos_i`. `eta` is as for `refract`.
+pub fn fresnel_dielectric(cos_i: f64, eta: f64) -> f64 {
+    let cos_i = cos_i.clamp(0., 1.);
+    let sin2_t = (1. - cos_i * cos_i) / (eta * eta);
+    if sin2_t >= 1. {
+        return 1.;
+    }
+    let cos_t = (1. - sin2_t).sqrt();
+    let rs = eta.mul_add(-cos_t, cos_i) / eta.mul_add(cos_t, cos_i);
+    let rp = eta.mul_add(cos_i, -cos_t) / eta.mul_add(cos_i, cos_t);
+    0.5 * rs.mul_add(rs, rp * rp)
+}
+
+// Unpolarized reflectance of a conductor with complex index of refraction
+// `eta + i k`, per color channel.
+pub fn fresnel_conductor(cos_i: f64, eta: &Color, k: &Color) -> Color {
+    let cos_i = cos_i.clamp(0., 1.);
+    let channel = |eta: f64, k: f64| {
+        let cos2 = cos_i * cos_i;
+        let sin2 = 1. - cos2;
+        let (eta2, k2) = (eta * eta, k * k);
+
+        let t0 = eta2 - k2 - sin2;
+        let a2_plus_b2 = t0.mul_add(t0, 4. * eta2 * k2).sqrt();
+        let t1 = a2_plus_b2 + cos2;
+        let a = (0.5 * (a2_plus_b2 + t0)).max(0.).sqrt();
+        let t2 = 2. * cos_i * a;
+        let rs = (t1 - t2) / (t1 + t2);
+
+        let t3 = cos2.mul_add(a2_plus_b2, sin2 * sin2);
+        let t4 = t2 * sin2;
+        let rp = rs * (t3 - t4) / (t3 + t4);
+        0.5 * (rp + rs)
+    };
+    Color::new(
+        channel(eta.r, k.r),
+        channel(eta.g, k.g),
+        channel(eta.b, k.b),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::PI;
+
+    use rand::Rng;
+
+    use super::{fresnel_conductor, fresnel_dielectric, Ggx};
+    use crate::{color::Color, sampler::Sampler, vec3::Vec3};
+
+    #[test]
+    fn visible_normal_pdf_matches_sampling() {
+        let mut sampler = Sampler::new(1);
+        let n = 200_000;
+        for &(ax, ay) in &[(0.3, 0.3), (0.2, 0.6), (0.8, 0.8)] {
+            let ggx = Ggx::new(ax, ay);
+            for wo in &[Vec3::new(0., 0., 1.), Vec3::new(0.6, 0.3, 0.5).unit()] {
+                // Integrate the pdf, and its first moments, by sampling the
+                // hemisphere uniformly...
+                let (mut total, mut mean) = (0., Vec3::zero());
+                for _ in 0..n {
+                    let z: f64 = sampler.gen();
+                    let phi = 2. * PI * sampler.gen::<f64>();
+                    let r = (1. - z * z).sqrt();
+                    let h = Vec3::new(r * phi.cos(), r * phi.sin(), z);
+                    let weight = ggx.visible_pdf(wo, &h) * 2. * PI / f64::from(n);
+                    total += weight;
+                    mean = mean + weight * h;
+                }
+                assert!((total - 1.).abs() < 0.02, "{} for {:?}", total, wo);
+
+                // ...and check that samples land with the same moments.
+                let mut sampled = Vec3::zero();
+                for _ in 0..n {
+                    let h = ggx.sample_visible(wo, sampler.gen(), sampler.gen());
+                    sampled = sampled + h / f64::from(n);
+                }
+                assert!((sampled - mean).mag() < 0.02, "{:?} != {:?}", sampled, mean);
+            }
+        }
+    }
+
+    #[test]
+    fn fresnel_limits() {
+        // At normal incidence, both reduce to closed forms.
+        let f = fresnel_dielectric(1., 1.5);
+        assert!((f - 0.04).abs() < 1e-12);
+        let (n, k) = (0.2, 3.9);
+        let expected = ((n - 1.) * (n - 1.) + k * k) / ((n + 1.) * (n + 1.) + k * k);
+        let f = fresnel_conductor(1., &Color::new(n, n, n), &Color::new(k, k, k));
+        assert!((f.r - expected).abs() < 1e-9);
+
+        // Going from glass to air past the critical angle reflects everything,
+        // and everything reflects at grazing angles.
+        assert!((fresnel_dielectric(0.3, 1. / 1.5) - 1.).abs() < 1e-12);
+        assert!((fresnel_dielectric(0., 1.5) - 1.).abs() < 1e-12);
+        let f = fresnel_conductor(0., &Color::new(n, n, n), &Color::new(k, k, k));
+        assert!((f.g - 1.).abs() < 1e-9);
+    }
+}
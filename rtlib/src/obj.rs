@@ -0,0 +1,459 @@
+// Wavefront OBJ and MTL importer.
+//
+// Supports `v`, `vt`, `vn`, `f` (triangles and convex polygons, with any of
+// the `v`, `v/vt`, `v//vn` and `v/vt/vn` forms and negative indices),
+// `mtllib` and `usemtl`. Other statements (groups, smoothing, lines, ...) are
+// ignored. Faces are grouped into one `Mesh` per material.
+
+use std::{
+    collections::HashMap,
+    error, fmt,
+    fs::File,
+    io::{self, BufRead, BufReader},
+    path::{Path, PathBuf},
+    str::SplitWhitespace,
+    sync::Arc,
+};
+
+use crate::{
+    color::Color,
+    hit::HittableObjects,
+    material::{Dielectric, DiffuseLight, Lambertian, Material, Metal},
+    point3::Point3,
+    triangle::{Mesh, TriangleMesh},
+    vec3::Vec3,
+};
+
+#[derive(Debug)]
+pub enum Error {
+    Io {
+        path: PathBuf,
+        source: io::Error,
+    },
+    Parse {
+        path: PathBuf,
+        line: usize,
+        message: String,
+    },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io { path, source } => write!(f, "{}: {}", path.display(), source),
+            Self::Parse {
+                path,
+                line,
+                message,
+            } => write!(f, "{}:{}: {}", path.display(), line, message),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Self::Io { source, .. } => Some(source),
+            Self::Parse { .. } => None,
+        }
+    }
+}
+
+// A material as described by an MTL file.
+#[derive(Clone)]
+pub struct Mtl {
+    pub diffuse: Color,     // Kd
+    pub specular: Color,    // Ks
+    pub emission: Color,    // Ke
+    pub shininess: f64,     // Ns
+    pub ior: f64,           // Ni
+    pub dissolve: f64,      // d, or 1 - Tr
+    pub illum: Option<u32>, // illum
+}
+
+impl Default for Mtl {
+    fn default() -> Self {
+        Self {
+            diffuse: Color::new(0.8, 0.8, 0.8),
+            specular: Color::default(),
+            emission: Color::default(),
+            shininess: 0.,
+            ior: 1.5,
+            dissolve: 1.,
+            illum: None,
+        }
+    }
+}
+
+impl Mtl {
+    // Picks the closest of the available materials. Emissive materials become
+    // lights, transparent materials become glass, materials with a dominant
+    // specular color become metal, and everything else is diffuse.
+    pub fn to_material(&self) -> Arc<dyn Material> {
+        let max = |c: &Color| c.r.max(c.g).max(c.b);
+        if max(&self.emission) > 0. {
+            return Arc::new(DiffuseLight::new(&self.emission));
+        }
+
+        let transparent = self.dissolve < 1. || matches!(self.illum, Some(4 | 6 | 7 | 9));
+        if transparent {
+            return Arc::new(Dielectric::new(self.ior));
+        }
+
+        if max(&self.specular) > max(&self.diffuse) {
+            // A common mapping from Phong exponents to roughness.
+            let fuzz = (2. / (self.shininess + 2.)).sqrt().min(1.);
+            return Arc::new(Metal::new(&self.specular, fuzz));
+        }
+
+        Arc::new(Lambertian::new(&self.diffuse))
+    }
+}
+
+// Loads every face in an OBJ file, along with any materials it references.
+pub fn load(path: &Path) -> Result<HittableObjects, Error> {
+    let file = File::open(path).map_err(|source| Error::Io {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    parse_obj(BufReader::new(file), path)
+}
+
+// Loads every material in an MTL file, keyed by name.
+pub fn load_mtl(path: &Path) -> Result<HashMap<String, Mtl>, Error> {
+    let file = File::open(path).map_err(|source| Error::Io {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    parse_mtl(BufReader::new(file), path)
+}
+
+// Key for deduplicating vertices: position, UV and normal indices.
+type VertexKey = (usize, Option<usize>, Option<usize>);
+
+#[derive(Default)]
+struct Group {
+    vertices: HashMap<VertexKey, usize>,
+    keys: Vec<VertexKey>,
+    indices: Vec<[usize; 3]>,
+}
+
+impl Group {
+    fn vertex(&mut self, key: VertexKey) -> usize {
+        let keys = &mut self.keys;
+        *self.vertices.entry(key).or_insert_with(|| {
+            keys.push(key);
+            keys.len() - 1
+        })
+    }
+}
+
+fn parse_obj<R: BufRead>(reader: R, path: &Path) -> Result<HittableObjects, Error> {
+    let dir = path.parent().unwrap_or_else(|| Path::new(""));
+    let mut ctx = Context { path, line: 0 };
+
+    let mut positions: Vec<Point3> = Vec::new();
+    let mut uvs: Vec<(f64, f64)> = Vec::new();
+    let mut normals: Vec<Vec3> = Vec::new();
+    let mut materials: HashMap<String, Mtl> = HashMap::new();
+
+    // Groups are keyed by material name, in order of first use.
+    let mut groups: Vec<(String, Group)> = Vec::new();
+    let mut current = String::new();
+
+    for line in reader.lines() {
+        ctx.line += 1;
+        let line = line.map_err(|source| Error::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        let mut tokens = line.split_whitespace();
+        match tokens.next() {
+            Some("v") => {
+                let [x, y, z] = ctx.floats(&mut tokens)?;
+                positions.push(Point3::new(x, y, z));
+            }
+            Some("vt") => {
+                let u = ctx.float(tokens.next(), "u")?;
+                let v = tokens.next().map_or(Ok(0.), |t| ctx.float(Some(t), "v"))?;
+                uvs.push((u, v));
+            }
+            Some("vn") => {
+                let [x, y, z] = ctx.floats(&mut tokens)?;
+                normals.push(Vec3::new(x, y, z));
+            }
+            Some("f") => {
+                let mut face = Vec::new();
+                for token in tokens {
+                    face.push(ctx.face_vertex(token, positions.len(), uvs.len(), normals.len())?);
+                }
+                if face.len() < 3 {
+                    return Err(ctx.error("face needs at least 3 vertices"));
+                }
+
+                let group = match groups.iter_mut().position(|(name, _)| *name == current) {
+                    Some(i) => &mut groups[i].1,
+                    None => {
+                        groups.push((current.clone(), Group::default()));
+                        &mut groups.last_mut().expect("just pushed").1
+                    }
+                };
+                let first = group.vertex(face[0]);
+                for pair in face[1..].windows(2) {
+                    let b = group.vertex(pair[0]);
+                    let c = group.vertex(pair[1]);
+                    group.indices.push([first, b, c]);
+                }
+            }
+            Some("mtllib") => {
+                for name in tokens {
+                    materials.extend(load_mtl(&dir.join(name))?);
+                }
+            }
+            Some("usemtl") => {
+                let name = tokens
+                    .next()
+                    .ok_or_else(|| ctx.error("usemtl needs a material name"))?;
+                if !materials.contains_key(name) {
+                    return Err(ctx.error(&format!("undefined material {:?}", name)));
+                }
+                current = name.to_string();
+            }
+            _ => {}
+        }
+    }
+
+    let mut world = HittableObjects::new();
+    for (name, group) in groups {
+        // Faces before the first `usemtl` get the default material.
+        let mtl = materials.get(&name).cloned().unwrap_or_default();
+
+        let mut mesh = TriangleMesh::new(
+            group.keys.iter().map(|k| positions[k.0]).collect(),
+            group.indices,
+            mtl.to_material(),
+        );
+        // Per-vertex attributes are all-or-nothing within a mesh.
+        if group.keys.iter().all(|k| k.1.is_some()) {
+            mesh.uvs = group
+                .keys
+                .iter()
+                .filter_map(|k| k.1.map(|i| uvs[i]))
+                .collect();
+        }
+        if group.keys.iter().all(|k| k.2.is_some()) {
+            mesh.normals = group
+                .keys
+                .iter()
+                .filter_map(|k| k.2.map(|i| normals[i]))
+                .collect();
+        }
+
+        world.add(Mesh::new(mesh));
+    }
+
+    Ok(world)
+}
+
+fn parse_mtl<R: BufRead>(reader: R, path: &Path) -> Result<HashMap<String, Mtl>, Error> {
+    let mut ctx = Context { path, line: 0 };
+    let mut materials = HashMap::new();
+    let mut current: Option<(String, Mtl)> = None;
+
+    for line in reader.lines() {
+        ctx.line += 1;
+        let line = line.map_err(|source| Error::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        let mut tokens = line.split_whitespace();
+        let keyword = match tokens.next() {
+            Some(k) if !k.starts_with('#') => k,
+            _ => continue,
+        };
+
+        if keyword == "newmtl" {
+            let name = tokens
+                .next()
+                .ok_or_else(|| ctx.error("newmtl needs a material name"))?;
+            if let Some((name, mtl)) = current.replace((name.to_string(), Mtl::default())) {
+                materials.insert(name, mtl);
+            }
+            continue;
+        }
+
+        let mtl = match &mut current {
+            Some((_, mtl)) => mtl,
+            None => return Err(ctx.error(&format!("{} before newmtl", keyword))),
+        };
+        match keyword {
+            "Kd" => mtl.diffuse = ctx.color(&mut tokens)?,
+            "Ks" => mtl.specular = ctx.color(&mut tokens)?,
+            "Ke" => mtl.emission = ctx.color(&mut tokens)?,
+            "Ns" => mtl.shininess = ctx.float(tokens.next(), "Ns")?,
+            "Ni" => mtl.ior = ctx.float(tokens.next(), "Ni")?,
+            "d" => mtl.dissolve = ctx.float(tokens.next(), "d")?,
+            "Tr" => mtl.dissolve = 1. - ctx.float(tokens.next(), "Tr")?,
+            "illum" => {
+                let token = tokens.next().ok_or_else(|| ctx.error("missing illum"))?;
+                let illum = token
+                    .parse()
+                    .map_err(|_| ctx.error(&format!("invalid illum {:?}", token)))?;
+                mtl.illum = Some(illum);
+            }
+            _ => {}
+        }
+    }
+
+    if let Some((name, mtl)) = current {
+        materials.insert(name, mtl);
+    }
+    Ok(materials)
+}
+
+// Tracks the position in the file being parsed, for error messages.
+struct Context<'a> {
+    path: &'a Path,
+    line: usize,
+}
+
+impl Context<'_> {
+    fn error(&self, message: &str) -> Error {
+        Error::Parse {
+            path: self.path.to_path_buf(),
+            line: self.line,
+            message: message.to_string(),
+        }
+    }
+
+    fn float(&self, token: Option<&str>, what: &str) -> Result<f64, Error> {
+        let token = token.ok_or_else(|| self.error(&format!("missing {}", what)))?;
+        token
+            .parse()
+            .map_err(|_| self.error(&format!("invalid {} {:?}", what, token)))
+    }
+
+    fn floats(&self, tokens: &mut SplitWhitespace) -> Result<[f64; 3], Error> {
+        Ok([
+            self.float(tokens.next(), "x")?,
+            self.float(tokens.next(), "y")?,
+            self.float(tokens.next(), "z")?,
+        ])
+    }
+
+    fn color(&self, tokens: &mut SplitWhitespace) -> Result<Color, Error> {
+        let r = self.float(tokens.next(), "red component")?;
+        // A single value means grey.
+        let g = tokens
+            .next()
+            .map_or(Ok(r), |t| self.float(Some(t), "green component"))?;
+        let b = tokens
+            .next()
+            .map_or(Ok(r), |t| self.float(Some(t), "blue component"))?;
+        Ok(Color::new(r, g, b))
+    }
+
+    // Resolves one `v/vt/vn` reference into zero-based indices.
+    fn face_vertex(
+        &self,
+        token: &str,
+        n_positions: usize,
+        n_uvs: usize,
+        n_normals: usize,
+    ) -> Result<VertexKey, Error> {
+        let mut parts = token.split('/');
+        let v = parts.next().unwrap_or("");
+        let vt = parts.next().filter(|s| !s.is_empty());
+        let vn = parts.next().filter(|s| !s.is_empty());
+        if parts.next().is_some() {
+            return Err(self.error(&format!("invalid face vertex {:?}", token)));
+        }
+
+        Ok((
+            self.index(v, n_positions, "vertex")?,
+            vt.map(|s| self.index(s, n_uvs, "texture coordinate"))
+                .transpose()?,
+            vn.map(|s| self.index(s, n_normals, "normal")).transpose()?,
+        ))
+    }
+
+    // OBJ indices are one-based, or relative to the end if negative.
+    fn index(&self, token: &str, len: usize, what: &str) -> Result<usize, Error> {
+        let i: i64 = token
+            .parse()
+            .map_err(|_| self.error(&format!("invalid {} index {:?}", what, token)))?;
+        #[allow(clippy::cast_possible_wrap, clippy::cast_possible_truncation)]
+        let resolved = if i > 0 { i - 1 } else { len as i64 + i };
+        if i == 0 || resolved < 0 || resolved >= len as i64 {
+            return Err(self.error(&format!("{} index {} out of range", what, i)));
+        }
+        #[allow(clippy::cast_sign_loss, clippy::cast_possible_truncation)]
+        Ok(resolved as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::Cursor, path::Path};
+
+    use super::{parse_mtl, parse_obj};
+    use crate::{hit::Hittable, point3::Point3, ray::Ray, sampler::Sampler, vec3::Vec3};
+
+    #[test]
+    fn triangulates_polygons() {
+        let obj = "\
+# A unit square, as one quad.
+v 0 0 0
+v 1 0 0
+v 1 1 0
+v 0 1 0
+vn 0 0 1
+f 1//1 2//1 3//1 -1//1
+";
+        let world = parse_obj(Cursor::new(obj), Path::new("square.obj")).unwrap();
+        assert_eq!(world.len(), 1);
+        // Both halves of the square are there.
+        for (x, y) in &[(0.75, 0.25), (0.25, 0.75)] {
+            let ray = Ray {
+                origin: Point3::new(*x, *y, 1.),
+                direction: Vec3::new(0., 0., -1.),
+                time: 0.,
+            };
+            assert!(world
+                .hit(&ray, 0.001, f64::INFINITY, &mut Sampler::new(0))
+                .is_some());
+        }
+    }
+
+    #[test]
+    fn reports_line_numbers() {
+        let obj = "v 0 0 0\nv 1 0 0\nv 1 1 oops\n";
+        let err = parse_obj(Cursor::new(obj), Path::new("bad.obj"))
+            .err()
+            .unwrap();
+        assert_eq!(err.to_string(), "bad.obj:3: invalid z \"oops\"");
+
+        let obj = "v 0 0 0\nv 1 0 0\nf 1 2 3\n";
+        let err = parse_obj(Cursor::new(obj), Path::new("bad.obj"))
+            .err()
+            .unwrap();
+        assert_eq!(err.to_string(), "bad.obj:3: vertex index 3 out of range");
+    }
+
+    #[test]
+    fn parses_mtl() {
+        let mtl = "\
+newmtl glass
+Ni 1.45
+d 0.2
+newmtl gold
+Kd 0.1 0.1 0.1
+Ks 1.0 0.8 0.3
+Ns 250
+";
+        let materials = parse_mtl(Cursor::new(mtl), Path::new("x.mtl")).unwrap();
+        assert!((materials["glass"].ior - 1.45).abs() < 1e-12);
+        assert!((materials["glass"].dissolve - 0.2).abs() < 1e-12);
+        assert!((materials["gold"].specular.g - 0.8).abs() < 1e-12);
+    }
+}
@@ -0,0 +1,60 @@
+use crate::vec3::Vec3;
+
+// Orthonormal basis. `w` is the "up" axis, e.g. a surface normal.
+#[derive(Clone, Copy, Debug)]
+pub struct Onb {
+    pub u: Vec3,
+    pub v: Vec3,
+    pub w: Vec3,
+}
+
+impl Onb {
+    pub fn from_w(n: &Vec3) -> Self {
+        let w = n.unit();
+        let a = if w.x.abs() > 0.9 {
+            Vec3::new(0., 1., 0.)
+        } else {
+            Vec3::new(1., 0., 0.)
+        };
+        // Right-handed, so that u × v = w.
+        let v = w.cross(a).unit();
+        let u = v.cross(w);
+        Self { u, v, w }
+    }
+
+    // Converts from this basis' coordinates to world coordinates.
+    pub fn local(&self, a: &Vec3) -> Vec3 {
+        a.x * self.u + a.y * self.v + a.z * self.w
+    }
+
+    // The inverse of `local`: world coordinates to this basis' coordinates.
+    pub fn to_local(&self, a: &Vec3) -> Vec3 {
+        Vec3::new(a.dot(self.u), a.dot(self.v), a.dot(self.w))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Onb;
+    use crate::vec3::Vec3;
+
+    #[test]
+    fn is_orthonormal_and_right_handed() {
+        for n in &[
+            Vec3::new(0., 0., 1.),
+            Vec3::new(1., 0., 0.),
+            Vec3::new(-0.3, 2., 0.5),
+            Vec3::new(0.95, -0.1, -0.2),
+        ] {
+            let frame = Onb::from_w(n);
+            assert!((frame.w - n.unit()).mag() < 1e-12);
+            assert!((frame.u.mag() - 1.).abs() < 1e-12);
+            assert!((frame.v.mag() - 1.).abs() < 1e-12);
+            assert!(frame.u.dot(frame.v).abs() < 1e-12);
+            assert!((frame.u.cross(frame.v) - frame.w).mag() < 1e-12);
+
+            let a = Vec3::new(0.2, -0.7, 0.4);
+            assert!((frame.to_local(&frame.local(&a)) - a).mag() < 1e-12);
+        }
+    }
+}
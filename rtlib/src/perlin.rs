@@ -0,0 +1,114 @@
+// Perlin gradient noise, and the fractal sums built from it.
+
+use rand::seq::SliceRandom;
+
+use crate::{point3::Point3, sampler::Sampler, vec3::Vec3};
+
+const SIZE: usize = 256;
+
+pub struct Perlin {
+    gradients: Vec<Vec3>,
+    // A shuffled 0..SIZE, twice over, so lookups can skip the wrap-around.
+    perm: Vec<usize>,
+}
+
+impl Perlin {
+    // Different seeds give unrelated noise.
+    pub fn new(seed: u64) -> Self {
+        let mut sampler = Sampler::new(seed);
+        let gradients = (0..SIZE).map(|_| Vec3::rand_unit(&mut sampler)).collect();
+        let mut perm: Vec<usize> = (0..SIZE).collect();
+        perm.shuffle(&mut sampler);
+        perm.extend_from_within(..);
+        Self { gradients, perm }
+    }
+
+    // Smooth noise in about [-1, 1], and zero at every integer lattice point.
+    pub fn noise(&self, p: &Point3) -> f64 {
+        let (fx, fy, fz) = (p.x.floor(), p.y.floor(), p.z.floor());
+        let (x, y, z) = (p.x - fx, p.y - fy, p.z - fz);
+        let cell = |f: f64| {
+            #[allow(clippy::cast_possible_truncation)]
+            let i = f as i64;
+            #[allow(clippy::cast_possible_wrap, clippy::cast_sign_loss)]
+            let i = i.rem_euclid(SIZE as i64) as usize;
+            i
+        };
+        let (i, j, k) = (cell(fx), cell(fy), cell(fz));
+
+        let mut corners = [[[0.; 2]; 2]; 2];
+        for (di, plane) in corners.iter_mut().enumerate() {
+            for (dj, row) in plane.iter_mut().enumerate() {
+                for (dk, corner) in row.iter_mut().enumerate() {
+                    let hash = self.perm[self.perm[self.perm[i + di] + j + dj] + k + dk];
+                    #[allow(clippy::cast_precision_loss)]
+                    let offset = Vec3::new(x - di as f64, y - dj as f64, z - dk as f64);
+                    *corner = self.gradients[hash].dot(offset);
+                }
+            }
+        }
+
+        let (u, v, w) = (fade(x), fade(y), fade(z));
+        let lerp = |t: f64, a: f64, b: f64| t.mul_add(b - a, a);
+        let plane =
+            |c: &[[f64; 2]; 2]| lerp(v, lerp(w, c[0][0], c[0][1]), lerp(w, c[1][0], c[1][1]));
+        lerp(u, plane(&corners[0]), plane(&corners[1]))
+    }
+
+    // Fractal Brownian motion: `octaves` layers of noise, each at twice the
+    // frequency and half the amplitude of the last. Signed, like `noise`.
+    pub fn fbm(&self, p: &Point3, octaves: u32) -> f64 {
+        self.octaves(p, octaves, |n| n)
+    }
+
+    // Like `fbm`, but summing the absolute value of each layer, which gives
+    // creases where the noise crosses zero. Always non-negative.
+    pub fn turbulence(&self, p: &Point3, octaves: u32) -> f64 {
+        self.octaves(p, octaves, f64::abs)
+    }
+
+    fn octaves<F: Fn(f64) -> f64>(&self, p: &Point3, octaves: u32, f: F) -> f64 {
+        let mut sum = 0.;
+        let mut amplitude = 1.;
+        let mut frequency = 1.;
+        for _ in 0..octaves {
+            let q = Point3::new(frequency * p.x, frequency * p.y, frequency * p.z);
+            sum += amplitude * f(self.noise(&q));
+            amplitude *= 0.5;
+            frequency *= 2.;
+        }
+        sum
+    }
+}
+
+// Perlin's quintic fade curve, which has zero first and second derivatives
+// at 0 and 1.
+fn fade(t: f64) -> f64 {
+    t * t * t * t.mul_add(t.mul_add(6., -15.), 10.)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Perlin;
+    use crate::point3::Point3;
+
+    #[test]
+    fn noise_is_seeded_and_bounded() {
+        let a = Perlin::new(1);
+        let b = Perlin::new(1);
+        let c = Perlin::new(2);
+
+        let mut differs = false;
+        for i in 0..1000 {
+            let t = f64::from(i);
+            let p = Point3::new(0.37 * t, -0.11 * t, 0.73 * t);
+            let n = a.noise(&p);
+            assert!((-1.5..=1.5).contains(&n));
+            assert!((n - b.noise(&p)).abs() < f64::EPSILON);
+            differs |= (n - c.noise(&p)).abs() > 1e-6;
+            assert!(a.turbulence(&p, 4) >= 0.);
+        }
+        assert!(differs);
+        assert!(a.noise(&Point3::new(3., -7., 12.)).abs() < 1e-12);
+    }
+}
@@ -2,7 +2,7 @@ use std::ops;
 
 use crate::vec3::Vec3;
 
-#[derive(Clone, Copy, Default)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
 pub struct Point3 {
     pub x: f64,
     pub y: f64,
@@ -43,6 +43,19 @@ impl ops::Sub<Vec3> for Point3 {
     }
 }
 
+impl ops::Index<usize> for Point3 {
+    type Output = f64;
+
+    fn index(&self, axis: usize) -> &Self::Output {
+        match axis {
+            0 => &self.x,
+            1 => &self.y,
+            2 => &self.z,
+            _ => panic!("axis out of range: {}", axis),
+        }
+    }
+}
+
 impl ops::Div<f64> for Point3 {
     type Output = Self;
 
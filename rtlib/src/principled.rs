@@ -0,0 +1,361 @@
+// A "principled" uber-material, after Burley's Disney BSDF (2012, 2015). A
+// handful of intuitive parameters, each in [0, 1], blend between diffuse,
+// metallic, glassy and coated looks:
+//
+//     base_color       albedo, or the color of metal or glass
+//     metallic         dielectric (0) to metal (1)
+//     roughness        polished (0) to rough (1)
+//     specular         reflectance of dielectrics; 0.5 is an index of 1.5
+//     sheen            extra grazing reflection, for cloth
+//     sheen_tint       how much sheen takes on the base color
+//     clearcoat        a second, clear specular layer on top
+//     clearcoat_gloss  from a satin (0) to a glossy (1) clearcoat
+//     transmission     opaque (0) to transparent (1)
+//     anisotropic      stretches highlights along the tangent
+//
+// Surfaces don't carry tangents, so anisotropic highlights follow the tangent
+// of the shading frame, which is fixed for any one normal but arbitrary.
+
+use std::{f64::consts::PI, sync::Arc};
+
+use crate::{
+    color::Color,
+    hit,
+    material::{cosine_direction, Bsdf, Material, Sample},
+    microfacet::{self, fresnel_dielectric, Ggx},
+    texture::{SolidColor, Texture},
+    vec3::Vec3,
+};
+
+pub struct Principled {
+    pub base_color: Arc<dyn Texture>,
+    pub metallic: Arc<dyn Texture>,
+    pub roughness: Arc<dyn Texture>,
+    pub specular: Arc<dyn Texture>,
+    pub sheen: Arc<dyn Texture>,
+    pub sheen_tint: Arc<dyn Texture>,
+    pub clearcoat: Arc<dyn Texture>,
+    pub clearcoat_gloss: Arc<dyn Texture>,
+    pub transmission: Arc<dyn Texture>,
+    pub anisotropic: Arc<dyn Texture>,
+}
+
+impl Principled {
+    pub fn new(base_color: &Color) -> Self {
+        Self::textured(Arc::new(SolidColor::new(base_color)))
+    }
+
+    // A rough dielectric of the given color. Set the other fields for
+    // anything else.
+    pub fn textured(base_color: Arc<dyn Texture>) -> Self {
+        let gray = |v| Arc::new(SolidColor::gray(v)) as Arc<dyn Texture>;
+        Self {
+            base_color,
+            metallic: gray(0.),
+            roughness: gray(0.5),
+            specular: gray(0.5),
+            sheen: gray(0.),
+            sheen_tint: gray(0.5),
+            clearcoat: gray(0.),
+            clearcoat_gloss: gray(1.),
+            transmission: gray(0.),
+            anisotropic: gray(0.),
+        }
+    }
+
+    // Looks up every parameter at the hit point.
+    fn lobes(&self, h_rec: &hit::Record) -> Lobes {
+        let (u, v, p) = (h_rec.u, h_rec.v, &h_rec.p);
+        let scalar = |t: &Arc<dyn Texture>| t.scalar(u, v, p).clamp(0., 1.);
+
+        let base = self.base_color.value(u, v, p);
+        let metallic = scalar(&self.metallic);
+        let roughness = scalar(&self.roughness);
+        let specular = scalar(&self.specular);
+        let transmission = scalar(&self.transmission);
+
+        let luminance = base.luminance();
+        let tint = if luminance > 0. {
+            (1. / luminance) * base
+        } else {
+            Color::new(1., 1., 1.)
+        };
+        let sheen_tint = scalar(&self.sheen_tint);
+        let sheen = scalar(&self.sheen) * (lerp_color(&Color::new(1., 1., 1.), &tint, sheen_tint));
+
+        // Specular 0.5 means F0 = 0.04, as for an index of refraction of 1.5.
+        let f0 = 0.08 * specular;
+        let ior = (1. + f0.sqrt()) / (1. - f0.sqrt()).max(1e-3);
+
+        let aspect = 0.9f64.mul_add(-scalar(&self.anisotropic), 1.).sqrt();
+        let alpha = roughness * roughness;
+        let clearcoat_gloss = scalar(&self.clearcoat_gloss);
+
+        let diffuse = (1. - metallic) * (1. - transmission);
+        let glass = (1. - metallic) * transmission;
+        Lobes {
+            base,
+            roughness,
+            diffuse,
+            sheen,
+            f0: lerp_color(&Color::new(f0, f0, f0), &base, metallic),
+            glass,
+            eta: if h_rec.front_face { ior } else { 1. / ior },
+            ggx: Ggx::new(alpha / aspect, alpha * aspect),
+            clearcoat: 0.25 * scalar(&self.clearcoat),
+            clearcoat_alpha: 0.1f64.mul_add(1. - clearcoat_gloss, 0.001 * clearcoat_gloss),
+            weights: [
+                diffuse,
+                0.3f64.mul_add(1. - metallic, metallic),
+                glass,
+                0.25 * scalar(&self.clearcoat),
+            ],
+        }
+    }
+}
+
+// Everything needed to scatter at one point.
+struct Lobes {
+    base: Color,
+    roughness: f64,
+    // Weight of the diffuse and sheen lobes.
+    diffuse: f64,
+    sheen: Color,
+    // Schlick's reflectance at normal incidence, for the opaque part.
+    f0: Color,
+    // Weight of the transparent part.
+    glass: f64,
+    // Ratio of the index of refraction past the surface to the one before.
+    eta: f64,
+    ggx: Ggx,
+    clearcoat: f64,
+    clearcoat_alpha: f64,
+    // How often to sample each of the diffuse, specular, transmission and
+    // clearcoat lobes, unnormalized.
+    weights: [f64; 4],
+}
+
+impl Lobes {
+    // Picks a direction from one lobe.
+    fn sample_direction(&self, wo: &Vec3, u: [f64; 3]) -> Option<Vec3> {
+        let total: f64 = self.weights.iter().sum();
+        let mut pick = u[0] * total;
+        let mut lobe = 0;
+        while lobe < 3 && pick >= self.weights[lobe] {
+            pick -= self.weights[lobe];
+            lobe += 1;
+        }
+
+        let (u1, u2) = (u[1], u[2]);
+        let wi = match lobe {
+            0 => cosine_direction(u1, u2),
+            1 => microfacet::reflect(wo, &self.ggx.sample_visible(wo, u1, u2)),
+            2 => microfacet::refract(wo, &self.ggx.sample_visible(wo, u1, u2), self.eta)?,
+            _ => microfacet::reflect(wo, &sample_gtr1(self.clearcoat_alpha, u1, u2)),
+        };
+        Some(wi)
+    }
+
+    // The BSDF times the cosine term for scattering `wo` into `wi`, and the
+    // density with which `sample` picks `wi`, combined over all lobes.
+    fn eval_pdf(&self, wo: &Vec3, wi: &Vec3) -> (Color, f64) {
+        if wo.z <= 0. || wi.z == 0. {
+            return (Color::default(), 0.);
+        }
+        let total: f64 = self.weights.iter().sum();
+        let [p_diffuse, p_specular, p_glass, p_clearcoat] = self.weights.map(|w| w / total);
+
+        if wi.z < 0. {
+            return self.eval_transmission(wo, wi, p_glass);
+        }
+
+        let h = (*wo + *wi).unit();
+        let cos_d = wi.dot(h);
+        let mut f = Color::default();
+        let mut pdf = 0.;
+
+        // Burley's diffuse, with its retro-reflection at grazing angles, and
+        // sheen.
+        if self.diffuse > 0. {
+            let fd90 = (2. * self.roughness).mul_add(cos_d * cos_d, 0.5);
+            let retro = |cos: f64| (fd90 - 1.).mul_add(schlick_weight(cos), 1.);
+            let diffuse = retro(wo.z) * retro(wi.z) / PI;
+            f += (self.diffuse * wi.z * diffuse) * self.base;
+            f += (self.diffuse * wi.z * schlick_weight(cos_d)) * self.sheen;
+        }
+        pdf += p_diffuse * wi.z / PI;
+
+        // Specular reflection. The transparent part reflects as a dielectric,
+        // so that light inside glass can be totally internally reflected.
+        let schlick = lerp_color(&self.f0, &Color::new(1., 1., 1.), schlick_weight(cos_d));
+        let dielectric = fresnel_dielectric(wo.dot(h), self.eta);
+        let fresnel = lerp_color(
+            &schlick,
+            &Color::new(dielectric, dielectric, dielectric),
+            self.glass,
+        );
+        let specular = self.ggx.d(&h) * self.ggx.g(wo, wi) / (4. * wo.z);
+        f += specular * fresnel;
+        pdf += p_specular * self.ggx.visible_pdf(wo, &h) / (4. * wo.dot(h));
+
+        // The clearcoat is a fixed index of refraction of 1.5 (F0 = 0.04),
+        // over a GTR1 distribution, with fixed Smith shadowing.
+        if self.clearcoat > 0. {
+            let fresnel = 0.96f64.mul_add(schlick_weight(cos_d), 0.04);
+            let coat = Ggx::new(0.25, 0.25);
+            let d = gtr1(h.z, self.clearcoat_alpha);
+            let coat = self.clearcoat * fresnel * d * coat.g(wo, wi) / (4. * wo.z);
+            f += Color::new(coat, coat, coat);
+        }
+        pdf += p_clearcoat * gtr1(h.z, self.clearcoat_alpha) * h.z / (4. * wo.dot(h));
+
+        (f, pdf)
+    }
+
+    // As for `RoughDielectric`, tinted by the base color.
+    fn eval_transmission(&self, wo: &Vec3, wi: &Vec3, p_glass: f64) -> (Color, f64) {
+        if self.glass <= 0. {
+            return (Color::default(), 0.);
+        }
+        let h = (*wo + self.eta * *wi).unit();
+        let h = if h.z < 0. { -h } else { h };
+        if wo.dot(h) <= 0. || wi.dot(h) >= 0. {
+            return (Color::default(), 0.);
+        }
+
+        let fresnel = fresnel_dielectric(wo.dot(h), self.eta);
+        let denom = self.eta.mul_add(wi.dot(h), wo.dot(h));
+        let jacobian = self.eta * self.eta * wi.dot(h).abs() / (denom * denom);
+        let f = self.glass
+            * (1. - fresnel)
+            * self.ggx.d(&h)
+            * self.ggx.g(wo, wi)
+            * wo.dot(h)
+            * jacobian
+            / wo.z;
+        let pdf = p_glass * self.ggx.visible_pdf(wo, &h) * jacobian;
+        (f * self.base, pdf)
+    }
+}
+
+impl Bsdf for Lobes {
+    fn eval(&self, wo: &Vec3, wi: &Vec3) -> Color {
+        self.eval_pdf(wo, wi).0
+    }
+
+    fn sample(&self, wo: &Vec3, u: [f64; 3]) -> Option<Sample> {
+        let wi = self.sample_direction(wo, u)?;
+        let (f, pdf) = self.eval_pdf(wo, &wi);
+        if pdf <= 0. {
+            return None;
+        }
+        Some(Sample {
+            wi,
+            weight: (1. / pdf) * f,
+            pdf: Some(pdf),
+        })
+    }
+
+    fn pdf(&self, wo: &Vec3, wi: &Vec3) -> f64 {
+        self.eval_pdf(wo, wi).1
+    }
+}
+
+impl Material for Principled {
+    fn bsdf(&self, h_rec: &hit::Record) -> Option<Box<dyn Bsdf>> {
+        Some(Box::new(self.lobes(h_rec)))
+    }
+}
+
+// (1 - cos)^5, for Schlick's approximation.
+fn schlick_weight(cos: f64) -> f64 {
+    (1. - cos).clamp(0., 1.).powi(5)
+}
+
+fn lerp_color(a: &Color, b: &Color, t: f64) -> Color {
+    (1. - t) * *a + t * *b
+}
+
+// The "generalized Trowbridge–Reitz" distribution with exponent 1, which has
+// a longer tail than GGX.
+fn gtr1(cos_h: f64, alpha: f64) -> f64 {
+    if cos_h <= 0. {
+        return 0.;
+    }
+    let a2 = alpha * alpha;
+    let t = (a2 - 1.).mul_add(cos_h * cos_h, 1.);
+    (a2 - 1.) / (PI * a2.ln() * t)
+}
+
+// Samples a GTR1 microfacet normal in proportion to `gtr1(h.z) * h.z`.
+fn sample_gtr1(alpha: f64, u1: f64, u2: f64) -> Vec3 {
+    let a2 = alpha * alpha;
+    let cos2 = ((1. - a2.powf(1. - u1)) / (1. - a2)).clamp(0., 1.);
+    let (cos, sin) = (cos2.sqrt(), (1. - cos2).sqrt());
+    let phi = 2. * PI * u2;
+    Vec3::new(sin * phi.cos(), sin * phi.sin(), cos)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::Principled;
+    use crate::{
+        color::Color, hit, material::Material, point3::Point3, ray::Ray, sampler::Sampler,
+        texture::SolidColor, vec3::Vec3,
+    };
+
+    #[test]
+    fn sampling_agrees_with_eval_and_pdf() {
+        let mut material = Principled::new(&Color::new(0.8, 0.5, 0.3));
+        material.metallic = Arc::new(SolidColor::gray(0.3));
+        material.roughness = Arc::new(SolidColor::gray(0.4));
+        material.sheen = Arc::new(SolidColor::gray(0.5));
+        material.clearcoat = Arc::new(SolidColor::gray(0.7));
+        material.transmission = Arc::new(SolidColor::gray(0.4));
+        material.anisotropic = Arc::new(SolidColor::gray(0.5));
+
+        let mut h_rec = hit::Record::new();
+        h_rec.p = Point3::zero();
+        h_rec.normal = Vec3::new(0., 0., 1.);
+        h_rec.front_face = true;
+        let ray_in = Ray {
+            origin: Point3::new(1., 0.5, 1.),
+            direction: Vec3::new(-1., -0.5, -1.),
+            time: 0.,
+        };
+
+        // Each sample's weight is exactly eval / pdf.
+        let mut sampler = Sampler::new(2);
+        let mut sampled = 0;
+        for _ in 0..1000 {
+            let s_rec = match material.scatter(&ray_in, &h_rec, &mut sampler) {
+                Some(s_rec) => s_rec,
+                None => continue,
+            };
+            sampled += 1;
+            let direction = s_rec.scattered.direction;
+            let pdf = material.scattering_pdf(&ray_in, &h_rec, &direction);
+            assert!((s_rec.pdf.unwrap() - pdf).abs() <= 1e-9 * pdf);
+            let f = material.eval(&ray_in, &h_rec, &direction);
+            let weight = s_rec.attenuation;
+            assert!((weight.r * pdf - f.r).abs() <= 1e-9 * f.r.max(1.));
+        }
+        assert!(sampled > 900);
+
+        // No more energy comes out than goes in.
+        let n = 50_000;
+        let mut total = Color::default();
+        for _ in 0..n {
+            if let Some(s_rec) = material.scatter(&ray_in, &h_rec, &mut sampler) {
+                total += (1. / f64::from(n)) * s_rec.attenuation;
+            }
+        }
+        assert!(
+            total.r < 1.02 && total.g < 1.02 && total.b < 1.02,
+            "{:?}",
+            total
+        );
+    }
+}
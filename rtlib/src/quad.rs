@@ -0,0 +1,285 @@
+use std::sync::Arc;
+
+use rand::Rng;
+
+use crate::{
+    aabb::Aabb,
+    hit::{Hittable, HittableObjects, Record},
+    material::Material,
+    point3::Point3,
+    ray::Ray,
+    sampler::Sampler,
+    vec3::Vec3,
+};
+
+// A parallelogram with corner `q` and edges `u` and `v`. Its UVs run from
+// (0, 0) at `q` to (1, 1) at the opposite corner, and its front face is the
+// one that `u × v` points out of.
+pub struct Quad {
+    q: Point3,
+    u: Vec3,
+    v: Vec3,
+    mat: Arc<dyn Material>,
+    normal: Vec3,
+    // Scaled so that `w · (u × v) = 1`, for finding a hit's UVs.
+    w: Vec3,
+    area: f64,
+}
+
+impl Quad {
+    pub fn new(q: &Point3, u: &Vec3, v: &Vec3, mat: Arc<dyn Material>) -> Self {
+        let n = u.cross(*v);
+        Self {
+            q: *q,
+            u: *u,
+            v: *v,
+            mat,
+            normal: n.unit(),
+            w: n / n.mag_squared(),
+            area: n.mag(),
+        }
+    }
+
+    // Axis-aligned rectangles, at `k` along the remaining axis. Their front
+    // faces point along that axis' positive direction.
+    pub fn xy_rect(x0: f64, x1: f64, y0: f64, y1: f64, k: f64, mat: Arc<dyn Material>) -> Self {
+        Self::new(
+            &Point3::new(x0, y0, k),
+            &Vec3::new(x1 - x0, 0., 0.),
+            &Vec3::new(0., y1 - y0, 0.),
+            mat,
+        )
+    }
+
+    pub fn xz_rect(x0: f64, x1: f64, z0: f64, z1: f64, k: f64, mat: Arc<dyn Material>) -> Self {
+        Self::new(
+            &Point3::new(x0, k, z0),
+            &Vec3::new(0., 0., z1 - z0),
+            &Vec3::new(x1 - x0, 0., 0.),
+            mat,
+        )
+    }
+
+    pub fn yz_rect(y0: f64, y1: f64, z0: f64, z1: f64, k: f64, mat: Arc<dyn Material>) -> Self {
+        Self::new(
+            &Point3::new(k, y0, z0),
+            &Vec3::new(0., y1 - y0, 0.),
+            &Vec3::new(0., 0., z1 - z0),
+            mat,
+        )
+    }
+
+    fn intersect(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<Record> {
+        let denom = self.normal.dot(ray.direction);
+        if denom.abs() < 1e-8 {
+            return None; // Parallel to the quad's plane.
+        }
+        let t = self.normal.dot(self.q - ray.origin) / denom;
+        if t <= t_min || t_max <= t {
+            return None;
+        }
+
+        // Express the hit point in terms of `u` and `v`.
+        let p = ray.at(t);
+        let offset = p - self.q;
+        let alpha = self.w.dot(offset.cross(self.v));
+        let beta = self.w.dot(self.u.cross(offset));
+        if !(0. ..=1.).contains(&alpha) || !(0. ..=1.).contains(&beta) {
+            return None;
+        }
+
+        let mut record = Record::new();
+        record.t = t;
+        record.p = p;
+        record.u = alpha;
+        record.v = beta;
+        record.set_face_normal(ray, &self.normal);
+        record.mat = Arc::clone(&self.mat);
+        Some(record)
+    }
+}
+
+impl Hittable for Quad {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64, _sampler: &mut Sampler) -> Option<Record> {
+        self.intersect(ray, t_min, t_max)
+    }
+
+    fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<Aabb> {
+        let bbox = Aabb::new(&self.q, &self.q)
+            .union_point(&(self.q + self.u))
+            .union_point(&(self.q + self.v))
+            .union_point(&(self.q + self.u + self.v));
+
+        // Give axis-aligned quads some thickness, so rays in their plane
+        // don't fall into gaps in the slab test.
+        let pad = |lo: f64, hi: f64| {
+            if hi - lo < 1e-4 {
+                (lo - 5e-5, hi + 5e-5)
+            } else {
+                (lo, hi)
+            }
+        };
+        let (x0, x1) = pad(bbox.min.x, bbox.max.x);
+        let (y0, y1) = pad(bbox.min.y, bbox.max.y);
+        let (z0, z1) = pad(bbox.min.z, bbox.max.z);
+        Some(Aabb::new(
+            &Point3::new(x0, y0, z0),
+            &Point3::new(x1, y1, z1),
+        ))
+    }
+
+    fn is_emissive(&self) -> bool {
+        self.mat.is_emissive()
+    }
+
+    // Samples a point uniformly by area.
+    fn sample_direction(&self, origin: &Point3, sampler: &mut Sampler) -> Vec3 {
+        let p = self.q + sampler.gen::<f64>() * self.u + sampler.gen::<f64>() * self.v;
+        p - *origin
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f64 {
+        let ray = Ray {
+            origin: *origin,
+            direction: *direction,
+            time: 0.,
+        };
+        let hit = match self.intersect(&ray, 0.001, f64::INFINITY) {
+            Some(hit) => hit,
+            None => return 0.,
+        };
+
+        // Convert from density per unit area to per unit solid angle.
+        let dist_squared = hit.t * hit.t * direction.mag_squared();
+        let cosine = self.normal.dot(*direction).abs() / direction.mag();
+        dist_squared / (cosine * self.area)
+    }
+}
+
+// A rectangular box spanning two opposite corners, made of six quads whose
+// front faces all point outward.
+pub struct BoxShape {
+    sides: HittableObjects,
+    bbox: Aabb,
+}
+
+impl BoxShape {
+    pub fn new(a: &Point3, b: &Point3, mat: Arc<dyn Material>) -> Self {
+        let min = Point3::new(a.x.min(b.x), a.y.min(b.y), a.z.min(b.z));
+        let max = Point3::new(a.x.max(b.x), a.y.max(b.y), a.z.max(b.z));
+        let dx = Vec3::new(max.x - min.x, 0., 0.);
+        let dy = Vec3::new(0., max.y - min.y, 0.);
+        let dz = Vec3::new(0., 0., max.z - min.z);
+
+        let mut sides = HittableObjects::new();
+        let mut side =
+            |q: Point3, u: Vec3, v: Vec3| sides.add(Quad::new(&q, &u, &v, Arc::clone(&mat)));
+        side(min, dy, dx); // Back (-z).
+        side(min + dz, dx, dy); // Front (+z).
+        side(min, dz, dy); // Left (-x).
+        side(min + dx, dy, dz); // Right (+x).
+        side(min, dx, dz); // Bottom (-y).
+        side(min + dy, dz, dx); // Top (+y).
+
+        Self {
+            sides,
+            bbox: Aabb::new(&min, &max),
+        }
+    }
+}
+
+impl Hittable for BoxShape {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64, sampler: &mut Sampler) -> Option<Record> {
+        self.sides.hit(ray, t_min, t_max, sampler)
+    }
+
+    fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<Aabb> {
+        Some(self.bbox)
+    }
+
+    fn is_emissive(&self) -> bool {
+        self.sides.is_emissive()
+    }
+
+    fn sample_direction(&self, origin: &Point3, sampler: &mut Sampler) -> Vec3 {
+        self.sides.sample_direction(origin, sampler)
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f64 {
+        self.sides.pdf_value(origin, direction)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::{BoxShape, Quad};
+    use crate::{
+        hit::Hittable, material::Blank, point3::Point3, ray::Ray, sampler::Sampler, vec3::Vec3,
+    };
+
+    #[test]
+    fn quad_hit_reports_uvs() {
+        let quad = Quad::xy_rect(0., 2., 0., 4., -1., Arc::new(Blank::new()));
+        let ray = Ray {
+            origin: Point3::new(0.5, 3., 1.),
+            direction: Vec3::new(0., 0., -1.),
+            time: 0.,
+        };
+        let rec = quad
+            .intersect(&ray, 0.001, f64::INFINITY)
+            .expect("should hit");
+        assert!((rec.t - 2.).abs() < 1e-12);
+        assert!((rec.u - 0.25).abs() < 1e-12);
+        assert!((rec.v - 0.75).abs() < 1e-12);
+        assert!(rec.front_face);
+        assert_eq!(rec.normal, Vec3::new(0., 0., 1.));
+
+        let miss = Ray {
+            origin: Point3::new(2.5, 3., 1.),
+            direction: Vec3::new(0., 0., -1.),
+            time: 0.,
+        };
+        assert!(quad.intersect(&miss, 0.001, f64::INFINITY).is_none());
+    }
+
+    #[test]
+    fn box_faces_point_outward() {
+        let b = BoxShape::new(
+            &Point3::new(1., 1., 1.),
+            &Point3::new(-1., -1., -1.),
+            Arc::new(Blank::new()),
+        );
+        let mut sampler = Sampler::new(0);
+        let axes = [
+            Vec3::new(1., 0., 0.),
+            Vec3::new(0., 1., 0.),
+            Vec3::new(0., 0., 1.),
+        ];
+        for axis in &axes {
+            for &sign in &[1., -1.] {
+                // From outside, every face is hit on its front, and from
+                // inside, on its back.
+                let outside = Ray {
+                    origin: Point3::zero() + 3. * sign * *axis,
+                    direction: -sign * *axis,
+                    time: 0.,
+                };
+                let rec = b.hit(&outside, 0.001, f64::INFINITY, &mut sampler).unwrap();
+                assert!(rec.front_face);
+                assert!((rec.t - 2.).abs() < 1e-12);
+                assert_eq!(rec.normal, sign * *axis);
+
+                let inside = Ray {
+                    origin: Point3::zero(),
+                    direction: sign * *axis,
+                    time: 0.,
+                };
+                let rec = b.hit(&inside, 0.001, f64::INFINITY, &mut sampler).unwrap();
+                assert!(!rec.front_face);
+                assert_eq!(rec.normal, -sign * *axis);
+            }
+        }
+    }
+}
@@ -1,9 +1,12 @@
 use crate::{point3::Point3, vec3::Vec3};
 
-#[derive(Default)]
+#[derive(Clone, Copy, Default)]
 pub struct Ray {
     pub origin: Point3,
     pub direction: Vec3,
+    // When the ray was cast, for motion blur. Moving objects are hit where
+    // they are at this time.
+    pub time: f64,
 }
 
 impl Ray {
@@ -0,0 +1,193 @@
+use std::{
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Mutex,
+    },
+    thread,
+};
+
+use rand::Rng;
+
+use crate::{camera::Camera, color::Color, framebuffer::Framebuffer, ray::Ray, sampler::Sampler};
+
+// Image and sampling parameters, independent of how they're executed.
+#[derive(Clone)]
+pub struct Settings {
+    pub width: u32,
+    pub height: u32,
+    pub samples_per_pixel: u32,
+    pub max_depth: u32,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            width: 1200,
+            height: 800,
+            samples_per_pixel: 500,
+            max_depth: 50,
+        }
+    }
+}
+
+impl Settings {
+    pub fn aspect_ratio(&self) -> f64 {
+        f64::from(self.width) / f64::from(self.height)
+    }
+}
+
+pub struct Renderer {
+    pub width: u32,
+    pub height: u32,
+    pub samples_per_pixel: u32,
+    pub threads: usize,
+    pub seed: u64,
+}
+
+impl Renderer {
+    pub const fn new(width: u32, height: u32, samples_per_pixel: u32, threads: usize) -> Self {
+        Self {
+            width,
+            height,
+            samples_per_pixel,
+            threads,
+            seed: 0,
+        }
+    }
+
+    // Renders the image one scanline at a time, handing scanlines out to
+    // `threads` workers as they become free. `radiance` computes the color
+    // seen along a single camera ray.
+    //
+    // Returns the average of each pixel's samples, as linear radiance. Each
+    // pixel draws its samples from its own stream (see `Sampler::for_pixel`),
+    // so the result only depends on `seed`.
+    pub fn render<F>(&self, camera: &Camera, radiance: F) -> Framebuffer
+    where
+        F: Fn(&Ray, &mut Sampler) -> Color + Sync,
+    {
+        self.render_with_progress(camera, radiance, |_| {})
+    }
+
+    // As `render`, also calling `progress` with the number of scanlines left
+    // each time one is finished. It's called from the worker threads.
+    pub fn render_with_progress<F, P>(
+        &self,
+        camera: &Camera,
+        radiance: F,
+        progress: P,
+    ) -> Framebuffer
+    where
+        F: Fn(&Ray, &mut Sampler) -> Color + Sync,
+        P: Fn(u32) + Sync,
+    {
+        let mut fb = Framebuffer::new(self.width, self.height);
+        let remaining = AtomicU32::new(self.height);
+        let scanlines = Mutex::new(
+            fb.pixels
+                .chunks_mut(self.width as usize)
+                .zip(0..self.height),
+        );
+
+        thread::scope(|s| {
+            for _ in 0..self.threads.max(1) {
+                s.spawn(|| loop {
+                    let next = scanlines.lock().expect("scanline queue poisoned").next();
+                    let (row, y) = match next {
+                        Some(v) => v,
+                        None => break,
+                    };
+                    self.render_scanline(camera, &radiance, row, y);
+
+                    let left = remaining.fetch_sub(1, Ordering::Relaxed) - 1;
+                    progress(left);
+                });
+            }
+        });
+
+        fb
+    }
+
+    fn render_scanline<F>(&self, camera: &Camera, radiance: &F, row: &mut [Color], y: u32)
+    where
+        F: Fn(&Ray, &mut Sampler) -> Color,
+    {
+        // The camera places the origin at the bottom left corner, but the
+        // framebuffer places it at the top left corner.
+        let yy = self.height - y - 1;
+
+        let scale = 1. / f64::from(self.samples_per_pixel);
+        for (x, pixel) in (0..self.width).zip(row.iter_mut()) {
+            let mut sampler = Sampler::for_pixel(self.seed, x, y);
+            let mut sum = Color::default();
+            for _ in 0..self.samples_per_pixel {
+                let u = (f64::from(x) + sampler.gen::<f64>()) / f64::from(self.width - 1);
+                let v = (f64::from(yy) + sampler.gen::<f64>()) / f64::from(self.height - 1);
+                let ray = camera.get_ray(u, v, &mut sampler);
+                sum += radiance(&ray, &mut sampler);
+            }
+            *pixel = scale * sum;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::Renderer;
+    use crate::{
+        camera, color::Color, hit::Hittable, material::Lambertian, point3::Point3, ray::Ray,
+        sampler::Sampler, sphere::Sphere,
+    };
+
+    #[test]
+    fn same_seed_same_image() {
+        let sphere = Sphere::new(
+            &Point3::new(0., 0., 0.),
+            1.,
+            Arc::new(Lambertian::new(&Color::new(0.5, 0.5, 0.5))),
+        );
+        let camera = camera::Settings::default().build(2.);
+        let render = |threads, seed| {
+            let mut renderer = Renderer::new(16, 8, 4, threads);
+            renderer.seed = seed;
+            renderer.render(&camera, |ray, sampler| {
+                sphere.hit(ray, 0.001, f64::INFINITY, sampler).map_or_else(
+                    Color::default,
+                    |h_rec| {
+                        h_rec
+                            .mat
+                            .scatter(ray, &h_rec, sampler)
+                            .map_or_else(Color::default, |s| {
+                                s.attenuation * Color::new(s.scattered.direction.x, 1., 1.)
+                            })
+                    },
+                )
+            })
+        };
+
+        let image = render(1, 7);
+        assert_eq!(image, render(3, 7));
+        assert_ne!(image, render(1, 8));
+    }
+
+    #[test]
+    fn threads_share_the_work() {
+        let camera = camera::Settings::default().build(2.);
+        let radiance = |ray: &Ray, _: &mut Sampler| {
+            let d = ray.direction.unit();
+            Color::new(d.x, d.y, d.z)
+        };
+        let left = Mutex::new(Vec::new());
+        let image = Renderer::new(16, 8, 2, 4).render_with_progress(&camera, radiance, |n| {
+            left.lock().unwrap().push(n);
+        });
+        assert_eq!(image, Renderer::new(16, 8, 2, 1).render(&camera, radiance));
+
+        // Every scanline is reported finished exactly once.
+        let mut left = left.into_inner().unwrap();
+        left.sort_unstable();
+        assert_eq!(left, (0..8).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,50 @@
+use rand::{rngs::SmallRng, Error, RngCore, SeedableRng};
+
+// The source of every random choice made while building or rendering a
+// scene. It's passed around explicitly (rather than using `thread_rng`), so
+// that a given seed always produces the same image.
+pub struct Sampler {
+    rng: SmallRng,
+}
+
+impl Sampler {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            rng: SmallRng::seed_from_u64(seed),
+        }
+    }
+
+    // An independent stream for a single pixel. Pixels never share a stream,
+    // so the image doesn't depend on the order they're rendered in, or on
+    // how many threads render them.
+    pub fn for_pixel(seed: u64, x: u32, y: u32) -> Self {
+        let pixel = (u64::from(y) << 32) | u64::from(x);
+        Self::new(mix(seed ^ mix(pixel)))
+    }
+}
+
+// SplitMix64's finalizer. Turns nearby inputs into unrelated outputs.
+const fn mix(mut z: u64) -> u64 {
+    z = z.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+impl RngCore for Sampler {
+    fn next_u32(&mut self) -> u32 {
+        self.rng.next_u32()
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.rng.next_u64()
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.rng.fill_bytes(dest);
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+        self.rng.try_fill_bytes(dest)
+    }
+}
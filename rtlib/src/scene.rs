@@ -1,17 +1,33 @@
-use std::rc::Rc;
+use std::sync::Arc;
 
-use rand::{prelude::ThreadRng, Rng};
+use rand::Rng;
 
 use crate::{
+    camera,
     color::Color,
+    environment::Environment,
     hit::HittableObjects,
+    light::Light,
     material::{Dielectric, Lambertian, Metal},
     point3::Point3,
+    render,
+    sampler::Sampler,
     sphere::Sphere,
 };
 
-pub fn random() -> HittableObjects {
-    fn rand_sphere(a: i32, b: i32, rng: &mut ThreadRng) -> Option<Sphere> {
+// Everything needed to render an image.
+pub struct Scene {
+    pub camera: camera::Settings,
+    pub render: render::Settings,
+    pub world: HittableObjects,
+    // What rays see when they miss everything.
+    pub environment: Arc<dyn Environment>,
+    // Points, spots and suns, which only shadow rays find.
+    pub lights: Vec<Arc<dyn Light>>,
+}
+
+pub fn random(rng: &mut Sampler) -> HittableObjects {
+    fn rand_sphere(a: i32, b: i32, rng: &mut Sampler) -> Option<Sphere> {
         let choose_mat: f64 = rng.gen();
         let center = Point3::new(
             0.9_f64.mul_add(rng.gen::<f64>(), f64::from(a)),
@@ -22,18 +38,18 @@ pub fn random() -> HittableObjects {
         if (center - Point3::new(4., 0.2, 0.)).mag() > 0.9 {
             if choose_mat < 0.8 {
                 // diffuse
-                let albedo = Color::rand() * Color::rand();
-                let mat = Rc::new(Lambertian::new(&albedo));
+                let albedo = Color::rand(rng) * Color::rand(rng);
+                let mat = Arc::new(Lambertian::new(&albedo));
                 return Some(Sphere::new(&center, 0.2, mat));
             } else if choose_mat < 0.95 {
                 // metal
-                let albedo = Color::rand_in(0.5, 1.);
+                let albedo = Color::rand_in(0.5, 1., rng);
                 let fuzz = rng.gen_range(0.0..0.5);
-                let mat = Rc::new(Metal::new(&albedo, fuzz));
+                let mat = Arc::new(Metal::new(&albedo, fuzz));
                 return Some(Sphere::new(&center, 0.2, mat));
             } else {
                 // glass
-                let mat = Rc::new(Dielectric::new(1.5));
+                let mat = Arc::new(Dielectric::new(1.5));
                 return Some(Sphere::new(&center, 0.2, mat));
             }
         };
@@ -42,26 +58,24 @@ pub fn random() -> HittableObjects {
 
     let mut world = HittableObjects::new();
 
-    let ground_material = Rc::new(Lambertian::new(&Color::new(0.5, 0.5, 0.5)));
+    let ground_material = Arc::new(Lambertian::new(&Color::new(0.5, 0.5, 0.5)));
     world.add(Sphere::new(
         &Point3::new(0., -1000., 0.),
         1000.,
         ground_material,
     ));
 
-    let mut rng = rand::thread_rng();
-
     for a in -11..11 {
         for b in -11..11 {
-            if let Some(s) = rand_sphere(a, b, &mut rng) {
+            if let Some(s) = rand_sphere(a, b, rng) {
                 world.add(s)
             }
         }
     }
 
-    let mat1 = Rc::new(Dielectric::new(1.5));
-    let mat2 = Rc::new(Lambertian::new(&Color::new(0.4, 0.2, 0.1)));
-    let mat3 = Rc::new(Metal::new(&Color::new(0.7, 0.6, 0.5), 0.0));
+    let mat1 = Arc::new(Dielectric::new(1.5));
+    let mat2 = Arc::new(Lambertian::new(&Color::new(0.4, 0.2, 0.1)));
+    let mat3 = Arc::new(Metal::new(&Color::new(0.7, 0.6, 0.5), 0.0));
 
     let sphere1 = Sphere::new(&Point3::new(0., 1., 0.), 1., mat1);
     let sphere2 = Sphere::new(&Point3::new(-4., 1., 0.), 1., mat2);
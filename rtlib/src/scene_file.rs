@@ -0,0 +1,2018 @@
+// TOML scene descriptions. A scene file looks like:
+//
+//     [camera]
+//     look_from = [13, 2, 3]
+//     look_at = [0, 0, 0]
+//     vfov = 20
+//
+//     [render]
+//     width = 600
+//     height = 400
+//
+//     [textures.checks]
+//     type = "checker"
+//     scale = 1
+//     even = [0.2, 0.3, 0.1]
+//     odd = [0.9, 0.9, 0.9]
+//
+//     [materials.ground]
+//     type = "lambertian"
+//     albedo = "checks"
+//
+//     [[objects]]
+//     type = "sphere"
+//     center = [0, -1000, 0]
+//     radius = 1000
+//     material = "ground"
+//
+// Texture types, and their fields:
+//
+//     solid           color
+//     checker         scale, even, odd
+//     image           path, wrap ("repeat", "clamp" or "mirror")
+//     noise           frequency, octaves, seed, dark, light
+//     marble          (as noise)
+//     wood            (as noise)
+//     granite         (as noise)
+//
+// Material types, and their fields:
+//
+//     lambertian         albedo
+//     metal              albedo, fuzz
+//     dielectric         ir
+//     conductor          metal (gold, copper, aluminium or silver) or eta
+//                        and k, roughness
+//     rough_dielectric   ir, roughness
+//     diffuse_light      emit
+//     isotropic          albedo
+//     henyey_greenstein  albedo, g (in (-1, 1), default 0)
+//     principled         base_color, metallic, roughness, specular, sheen,
+//                        sheen_tint, clearcoat, clearcoat_gloss,
+//                        transmission, anisotropic
+//
+// Colors (albedo, emit, base_color) are either [r, g, b] or the name of a
+// texture. Likewise, fuzz, roughness and the principled material's other
+// parameters are either a number in [0, 1] or the name of a texture.
+//
+// Object types, and their fields:
+//
+//     sphere          center, radius, material
+//     moving_sphere   center0, center1, time0, time1, radius, material
+//     triangle        vertices, material
+//     quad            corner, u, v, material
+//     box             min, max, material
+//     volume          path, dims, min, max, material
+//     obj             path
+//
+// Any object can also be given a `transform`: a list of steps, applied in
+// order, such as
+//
+//     transform = [{ scale = [2, 2, 2] }, { rotate_y = 15 }, { translate = [265, 0, 295] }]
+//
+// with `rotate_x`, `rotate_y` and `rotate_z` in degrees. A transformed OBJ is
+// loaded once and shared by every object that refers to it.
+//
+// Objects can move, too. A `motion` such as
+//
+//     motion = { transform = [{ translate = [0, 1, 0] }], time0 = 0, time1 = 1 }
+//
+// takes the object from its `transform` at `time0` (default 0) to the one
+// given here at `time1` (default 1), holding still before and after. A
+// `moving_sphere` does the same for its center. Motion only blurs if the
+// camera's shutter is open for a while, from `shutter_open` to
+// `shutter_close`.
+//
+// Giving an object a `density` turns it into the boundary of a volume of
+// smoke or fog, which scatters light with the object's material (normally
+// "isotropic"). The boundary must be closed and convex.
+//
+// A `volume` is a grid of densities, stretched over the box from `min` to
+// `max` and scattering with its material. `path` is either a grid file (see
+// `DensityGrid::open`), or, if `dims = [nx, ny, nz]` is given, raw `f32`s.
+// Its `density`, if any, scales the grid's values.
+//
+// The `[environment]` is what rays see when they miss everything: either
+//
+//     type = "gradient"
+//     bottom = [1, 1, 1]
+//     top = [0.5, 0.7, 1]
+//
+// (the default), or an equirectangular `.hdr` or `.exr` image, turned about
+// the y axis by `rotation` degrees and scaled by `intensity`:
+//
+//     type = "map"
+//     path = "studio.hdr"
+//     rotation = 90
+//     intensity = 1.5
+//
+// or a daylight sky and sun, with the sun's elevation and azimuth in degrees
+// (0 is toward -z, 90 toward +x), the haziness of the air (`turbidity`, 1.7
+// to 10, default 3), and the color of the ground below the horizon:
+//
+//     type = "sky"
+//     elevation = 30
+//     azimuth = 45
+//     turbidity = 3
+//     ground = [0.3, 0.3, 0.3]
+//
+// Lights with no surface, which rays never hit but every bounce looks toward,
+// are listed as `[[lights]]`:
+//
+//     point           position, intensity
+//     spot            position, look_at, intensity, cone_angle (default 30),
+//                     cone_delta (default 5), ies, up
+//     directional     direction, irradiance
+//
+// A point or spot light's `intensity` is the irradiance it gives at a
+// distance of 1, and a directional light's `direction` is the way its light
+// travels. A spot light fades out over the last `cone_delta` degrees of its
+// cone, unless it's given an `ies` photometric profile, which shapes the beam
+// instead and points its axis at `look_at`. The profile's horizontal angle 0
+// leans toward `up`, which defaults to [0, 1, 0], or to [0, 0, -1] for a
+// light pointing straight up or down.
+//
+// Every section is optional, and omitted settings take the same defaults as
+// the built-in random scene. Relative paths (e.g. OBJ meshes, images) are
+// resolved against the directory holding the scene file.
+
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, HashMap},
+    error, fmt, fs, io,
+    marker::PhantomData,
+    mem,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use serde::{
+    de::{
+        self,
+        value::{MapAccessDeserializer, StringDeserializer},
+        DeserializeSeed, EnumAccess, IntoDeserializer, MapAccess, Unexpected, VariantAccess,
+        Visitor,
+    },
+    Deserialize, Deserializer,
+};
+use toml::de::{DeTable, DeValue};
+
+use crate::{
+    aabb::Aabb,
+    bvh::Bvh,
+    camera,
+    color::Color,
+    environment::{Environment, EnvironmentMap, Gradient},
+    hit::{Hittable, HittableObjects},
+    ies::IesProfile,
+    instance::Instance,
+    light::{DirectionalLight, Light, PointLight, SpotLight},
+    material::{
+        Conductor, ConductorKind, Dielectric, DiffuseLight, HenyeyGreenstein, Isotropic,
+        Lambertian, Material, Metal, RoughDielectric,
+    },
+    medium::ConstantMedium,
+    obj,
+    point3::Point3,
+    principled::Principled,
+    quad::{BoxShape, Quad},
+    render,
+    scene::Scene,
+    sky::Sky,
+    sphere::{MovingSphere, Sphere},
+    texture::{Checker, ImageTexture, NoiseTexture, Pattern, SolidColor, Texture, Wrap},
+    transform::{AnimatedTransform, Transform},
+    triangle::Triangle,
+    vec3::Vec3,
+    volume::{DensityGrid, GridMedium},
+};
+
+#[derive(Debug)]
+pub enum Error {
+    Io {
+        path: PathBuf,
+        source: io::Error,
+    },
+    // Malformed TOML, or a value of the wrong type.
+    Syntax {
+        path: PathBuf,
+        key: String,
+        source: Box<toml::de::Error>,
+    },
+    // Well-formed, but meaningless, e.g. a reference to a missing material.
+    Invalid {
+        path: PathBuf,
+        key: String,
+        message: String,
+    },
+    Obj(obj::Error),
+    Image {
+        path: PathBuf,
+        source: image::ImageError,
+    },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io { path, source } => write!(f, "{}: {}", path.display(), source),
+            Self::Syntax { path, key, source } => {
+                write!(f, "{}: {}: {}", path.display(), key, source)
+            }
+            Self::Invalid { path, key, message } => {
+                write!(f, "{}: {}: {}", path.display(), key, message)
+            }
+            Self::Obj(e) => e.fmt(f),
+            Self::Image { path, source } => write!(f, "{}: {}", path.display(), source),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Self::Io { source, .. } => Some(source),
+            Self::Syntax { source, .. } => Some(source),
+            Self::Invalid { .. } => None,
+            Self::Obj(e) => Some(e),
+            Self::Image { source, .. } => Some(source),
+        }
+    }
+}
+
+impl From<obj::Error> for Error {
+    fn from(e: obj::Error) -> Self {
+        Self::Obj(e)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SceneDesc {
+    #[serde(default)]
+    camera: CameraDesc,
+    #[serde(default)]
+    render: RenderDesc,
+    #[serde(default)]
+    textures: BTreeMap<String, Tagged<TextureDesc>>,
+    #[serde(default)]
+    materials: BTreeMap<String, Tagged<MaterialDesc>>,
+    #[serde(default)]
+    objects: Vec<ObjectDesc>,
+    #[serde(default)]
+    environment: Tagged<EnvironmentDesc>,
+    #[serde(default)]
+    lights: Vec<Tagged<LightDesc>>,
+}
+
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct CameraDesc {
+    look_from: [f64; 3],
+    look_at: [f64; 3],
+    up: [f64; 3],
+    vfov: f64,
+    aperture: f64,
+    focus_distance: f64,
+    shutter_open: f64,
+    shutter_close: f64,
+}
+
+impl Default for CameraDesc {
+    fn default() -> Self {
+        let d = camera::Settings::default();
+        Self {
+            look_from: [d.look_from.x, d.look_from.y, d.look_from.z],
+            look_at: [d.look_at.x, d.look_at.y, d.look_at.z],
+            up: [d.v_up.x, d.v_up.y, d.v_up.z],
+            vfov: d.vfov,
+            aperture: d.aperture,
+            focus_distance: d.focus_dist,
+            shutter_open: d.shutter_open,
+            shutter_close: d.shutter_close,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RenderDesc {
+    width: u32,
+    height: u32,
+    samples_per_pixel: u32,
+    max_depth: u32,
+}
+
+impl Default for RenderDesc {
+    fn default() -> Self {
+        let d = render::Settings::default();
+        Self {
+            width: d.width,
+            height: d.height,
+            samples_per_pixel: d.samples_per_pixel,
+            max_depth: d.max_depth,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+enum TextureDesc {
+    Solid {
+        color: [f64; 3],
+    },
+    Checker {
+        scale: f64,
+        even: [f64; 3],
+        odd: [f64; 3],
+    },
+    Image {
+        path: PathBuf,
+        #[serde(default = "default_wrap")]
+        wrap: WrapDesc,
+    },
+    Noise(NoiseDesc),
+    Marble(NoiseDesc),
+    Wood(NoiseDesc),
+    Granite(NoiseDesc),
+}
+
+// Omitted fields take the pattern's own defaults.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct NoiseDesc {
+    frequency: Option<f64>,
+    octaves: Option<u32>,
+    #[serde(default)]
+    seed: u64,
+    dark: Option<[f64; 3]>,
+    light: Option<[f64; 3]>,
+}
+
+#[derive(Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum WrapDesc {
+    Repeat,
+    Clamp,
+    Mirror,
+}
+
+const fn default_wrap() -> WrapDesc {
+    WrapDesc::Repeat
+}
+
+#[derive(Deserialize)]
+#[serde(untagged, expecting = "a color [r, g, b] or a texture name")]
+enum ColorParam {
+    Value([f64; 3]),
+    Texture(String),
+}
+
+#[derive(Deserialize)]
+#[serde(untagged, expecting = "a number or a texture name")]
+enum ScalarParam {
+    Value(f64),
+    Texture(String),
+}
+
+impl Default for ScalarParam {
+    fn default() -> Self {
+        Self::Value(0.)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+enum MaterialDesc {
+    Lambertian {
+        albedo: ColorParam,
+    },
+    Metal {
+        albedo: ColorParam,
+        #[serde(default)]
+        fuzz: ScalarParam,
+    },
+    Dielectric {
+        ir: f64,
+    },
+    // Either a named `metal`, or its `eta` and `k`.
+    Conductor {
+        metal: Option<ConductorKindDesc>,
+        eta: Option<[f64; 3]>,
+        k: Option<[f64; 3]>,
+        #[serde(default)]
+        roughness: ScalarParam,
+    },
+    RoughDielectric {
+        ir: f64,
+        #[serde(default)]
+        roughness: ScalarParam,
+    },
+    DiffuseLight {
+        emit: ColorParam,
+    },
+    Isotropic {
+        albedo: ColorParam,
+    },
+    HenyeyGreenstein {
+        albedo: ColorParam,
+        #[serde(default)]
+        g: f64,
+    },
+    // Unset parameters keep `Principled`'s defaults.
+    Principled {
+        base_color: ColorParam,
+        metallic: Option<ScalarParam>,
+        roughness: Option<ScalarParam>,
+        specular: Option<ScalarParam>,
+        sheen: Option<ScalarParam>,
+        sheen_tint: Option<ScalarParam>,
+        clearcoat: Option<ScalarParam>,
+        clearcoat_gloss: Option<ScalarParam>,
+        transmission: Option<ScalarParam>,
+        anisotropic: Option<ScalarParam>,
+    },
+}
+
+#[derive(Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum ConductorKindDesc {
+    Gold,
+    Copper,
+    #[serde(alias = "aluminum")]
+    Aluminium,
+    Silver,
+}
+
+struct ObjectDesc {
+    shape: ShapeDesc,
+    transform: Vec<TransformDesc>,
+    motion: Option<MotionDesc>,
+    density: Option<f64>,
+}
+
+// The keys any object may have, besides its shape's.
+#[derive(Default)]
+struct Placement {
+    transform: Option<Vec<TransformDesc>>,
+    motion: Option<MotionDesc>,
+    density: Option<f64>,
+}
+
+impl<'de> Extra<'de> for Placement {
+    const FIELDS: &'static [&'static str] = &["transform", "motion", "density"];
+
+    fn read<M: MapAccess<'de>>(&mut self, key: &str, map: &mut M) -> Result<(), M::Error> {
+        match key {
+            "transform" => self.transform = Some(map.next_value()?),
+            "motion" => self.motion = Some(map.next_value()?),
+            _ => self.density = Some(map.next_value()?),
+        }
+        Ok(())
+    }
+}
+
+impl<'de> Deserialize<'de> for ObjectDesc {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let (shape, placement): (_, Placement) =
+            deserializer.deserialize_map(TaggedVisitor(PhantomData))?;
+        Ok(Self {
+            shape,
+            transform: placement.transform.unwrap_or_default(),
+            motion: placement.motion,
+            density: placement.density,
+        })
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MotionDesc {
+    transform: Vec<TransformDesc>,
+    #[serde(default)]
+    time0: f64,
+    #[serde(default = "default_time1")]
+    time1: f64,
+}
+
+const fn default_time1() -> f64 {
+    1.
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+enum ShapeDesc {
+    Sphere {
+        center: [f64; 3],
+        radius: f64,
+        material: String,
+    },
+    MovingSphere {
+        center0: [f64; 3],
+        center1: [f64; 3],
+        #[serde(default)]
+        time0: f64,
+        #[serde(default = "default_time1")]
+        time1: f64,
+        radius: f64,
+        material: String,
+    },
+    Triangle {
+        vertices: [[f64; 3]; 3],
+        material: String,
+    },
+    // A parallelogram with edges `u` and `v` leaving `corner`.
+    Quad {
+        corner: [f64; 3],
+        u: [f64; 3],
+        v: [f64; 3],
+        material: String,
+    },
+    #[serde(rename = "box")]
+    BoxShape {
+        min: [f64; 3],
+        max: [f64; 3],
+        material: String,
+    },
+    Volume {
+        path: PathBuf,
+        dims: Option<[usize; 3]>,
+        min: [f64; 3],
+        max: [f64; 3],
+        material: String,
+    },
+    // Materials come from the OBJ's own MTL files.
+    Obj {
+        path: PathBuf,
+    },
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+enum TransformDesc {
+    Translate([f64; 3]),
+    Scale([f64; 3]),
+    RotateX(f64),
+    RotateY(f64),
+    RotateZ(f64),
+}
+
+// Without one, the sky is the default `Gradient`.
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+enum EnvironmentDesc {
+    Gradient {
+        bottom: Option<[f64; 3]>,
+        top: Option<[f64; 3]>,
+    },
+    Map {
+        path: PathBuf,
+        #[serde(default)]
+        rotation: f64,
+        intensity: Option<f64>,
+    },
+    Sky {
+        elevation: f64,
+        #[serde(default)]
+        azimuth: f64,
+        #[serde(default = "default_turbidity")]
+        turbidity: f64,
+        ground: Option<[f64; 3]>,
+        intensity: Option<f64>,
+    },
+}
+
+const fn default_turbidity() -> f64 {
+    3.
+}
+
+impl Default for EnvironmentDesc {
+    fn default() -> Self {
+        Self::Gradient {
+            bottom: None,
+            top: None,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+enum LightDesc {
+    Point {
+        position: [f64; 3],
+        intensity: [f64; 3],
+    },
+    Spot {
+        position: [f64; 3],
+        look_at: [f64; 3],
+        intensity: [f64; 3],
+        #[serde(default = "default_cone_angle")]
+        cone_angle: f64,
+        #[serde(default = "default_cone_delta")]
+        cone_delta: f64,
+        ies: Option<PathBuf>,
+        up: Option<[f64; 3]>,
+    },
+    Directional {
+        direction: [f64; 3],
+        irradiance: [f64; 3],
+    },
+}
+
+const fn default_cone_angle() -> f64 {
+    30.
+}
+
+const fn default_cone_delta() -> f64 {
+    5.
+}
+
+// A section whose `type` picks its variant, with the variant's fields beside
+// it. Serde's internally tagged enums would read the whole table into a
+// buffer first, to find `type`, and then errors could only name the table, not
+// the key or line. Instead, `parse` moves `type` to the front of the table, so
+// it can be read first and the rest handed straight to the variant.
+struct Tagged<T>(T);
+
+impl<T: Default> Default for Tagged<T> {
+    fn default() -> Self {
+        Self(T::default())
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for Tagged<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let (value, NoExtra) = deserializer.deserialize_map(TaggedVisitor(PhantomData))?;
+        Ok(Self(value))
+    }
+}
+
+// Keys a tagged table has besides its variant's fields.
+trait Extra<'de>: Default {
+    const FIELDS: &'static [&'static str];
+
+    // Reads the value of `key`, which is one of `FIELDS`.
+    fn read<M: MapAccess<'de>>(&mut self, key: &str, map: &mut M) -> Result<(), M::Error>;
+}
+
+#[derive(Default)]
+struct NoExtra;
+
+impl<'de> Extra<'de> for NoExtra {
+    const FIELDS: &'static [&'static str] = &[];
+
+    fn read<M: MapAccess<'de>>(&mut self, _key: &str, _map: &mut M) -> Result<(), M::Error> {
+        Ok(())
+    }
+}
+
+struct TaggedVisitor<T, E>(PhantomData<(T, E)>);
+
+impl<'de, T: Deserialize<'de>, E: Extra<'de>> Visitor<'de> for TaggedVisitor<T, E> {
+    type Value = (T, E);
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a table with a type")
+    }
+
+    fn visit_map<M: MapAccess<'de>>(self, mut map: M) -> Result<Self::Value, M::Error> {
+        let mut extra = E::default();
+        let value = T::deserialize(TaggedTable {
+            map: &mut map,
+            extra: &mut extra,
+        })?;
+        Ok((value, extra))
+    }
+}
+
+// A table with `type` first, seen as an externally tagged enum.
+struct TaggedTable<'a, M, E> {
+    map: &'a mut M,
+    extra: &'a mut E,
+}
+
+impl<'a, 'de, M: MapAccess<'de>, E: Extra<'de>> Deserializer<'de> for TaggedTable<'a, M, E> {
+    type Error = M::Error;
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        if self.map.next_key::<String>()?.as_deref() != Some("type") {
+            return Err(de::Error::missing_field("type"));
+        }
+        let tag = self.map.next_value_seed(Tag(variants))?;
+        visitor.visit_enum(Variant { tag, table: self })
+    }
+
+    fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Self::Error> {
+        Err(de::Error::custom(
+            "only enums can be read from a tagged table",
+        ))
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map struct identifier ignored_any
+    }
+}
+
+// The value of `type`, which must name one of the variants.
+struct Tag(&'static [&'static str]);
+
+impl<'de> DeserializeSeed<'de> for Tag {
+    type Value = String;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<String, D::Error> {
+        let tag = String::deserialize(deserializer)?;
+        if self.0.contains(&tag.as_str()) {
+            Ok(tag)
+        } else {
+            Err(de::Error::unknown_variant(&tag, self.0))
+        }
+    }
+}
+
+struct Variant<'a, M, E> {
+    tag: String,
+    table: TaggedTable<'a, M, E>,
+}
+
+impl<'a, 'de, M: MapAccess<'de>, E: Extra<'de>> EnumAccess<'de> for Variant<'a, M, E> {
+    type Error = M::Error;
+    type Variant = TaggedTable<'a, M, E>;
+
+    fn variant_seed<S: DeserializeSeed<'de>>(
+        self,
+        seed: S,
+    ) -> Result<(S::Value, Self::Variant), Self::Error> {
+        let tag: StringDeserializer<M::Error> = self.tag.into_deserializer();
+        Ok((seed.deserialize(tag)?, self.table))
+    }
+}
+
+impl<'a, 'de, M: MapAccess<'de>, E: Extra<'de>> VariantAccess<'de> for TaggedTable<'a, M, E> {
+    type Error = M::Error;
+
+    fn unit_variant(self) -> Result<(), Self::Error> {
+        match self.map.next_key::<String>()? {
+            Some(key) => Err(de::Error::unknown_field(&key, &[])),
+            None => Ok(()),
+        }
+    }
+
+    fn newtype_variant_seed<S: DeserializeSeed<'de>>(
+        self,
+        seed: S,
+    ) -> Result<S::Value, Self::Error> {
+        seed.deserialize(MapAccessDeserializer::new(Fields {
+            table: self,
+            names: None,
+        }))
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(
+        self,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        Err(de::Error::invalid_type(Unexpected::Map, &visitor))
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_map(Fields {
+            table: self,
+            names: Some(fields),
+        })
+    }
+}
+
+// The rest of a tagged table, after `type`, as the variant's fields. Extra
+// keys are taken out along the way.
+struct Fields<'a, M, E> {
+    table: TaggedTable<'a, M, E>,
+    // The variant's, if known.
+    names: Option<&'static [&'static str]>,
+}
+
+impl<'a, 'de, M: MapAccess<'de>, E: Extra<'de>> MapAccess<'de> for Fields<'a, M, E> {
+    type Error = M::Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Self::Error> {
+        if E::FIELDS.is_empty() {
+            return self.table.map.next_key_seed(seed);
+        }
+        let key = FieldName {
+            names: self.names,
+            extra: E::FIELDS,
+        };
+        while let Some(key) = self.table.map.next_key_seed(key)? {
+            if E::FIELDS.contains(&key.as_str()) {
+                self.table.extra.read(&key, self.table.map)?;
+            } else {
+                let key: StringDeserializer<M::Error> = key.into_deserializer();
+                return seed.deserialize(key).map(Some);
+            }
+        }
+        Ok(None)
+    }
+
+    fn next_value_seed<S: DeserializeSeed<'de>>(
+        &mut self,
+        seed: S,
+    ) -> Result<S::Value, Self::Error> {
+        self.table.map.next_value_seed(seed)
+    }
+}
+
+// A key of a table with extra keys, which must be one of them or one of the
+// variant's fields.
+#[derive(Clone, Copy)]
+struct FieldName {
+    names: Option<&'static [&'static str]>,
+    extra: &'static [&'static str],
+}
+
+impl<'de> DeserializeSeed<'de> for FieldName {
+    type Value = String;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<String, D::Error> {
+        let key = String::deserialize(deserializer)?;
+        let names = match self.names {
+            Some(names)
+                if !names.contains(&key.as_str()) && !self.extra.contains(&key.as_str()) =>
+            {
+                names
+            }
+            _ => return Ok(key),
+        };
+        let expected: Vec<String> = names
+            .iter()
+            .chain(self.extra)
+            .map(|name| format!("`{}`", name))
+            .collect();
+        Err(de::Error::custom(format!(
+            "unknown field `{}`, expected one of {}",
+            key,
+            expected.join(", ")
+        )))
+    }
+}
+
+pub fn load(path: &Path) -> Result<Scene, Error> {
+    let text = fs::read_to_string(path).map_err(|source| Error::Io {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    parse(&text, path)
+}
+
+// `path` is only used for error messages and resolving relative paths.
+pub fn parse(text: &str, path: &Path) -> Result<Scene, Error> {
+    let syntax_error = |key: String, mut source: toml::de::Error| {
+        source.set_input(Some(text));
+        Error::Syntax {
+            path: path.to_path_buf(),
+            key,
+            source: Box::new(source),
+        }
+    };
+    let mut root = DeTable::parse(text).map_err(|e| syntax_error("<document>".into(), e))?;
+    move_types_first(root.get_mut());
+    let desc: SceneDesc = serde_path_to_error::deserialize(toml::Deserializer::from(root))
+        .map_err(|e| syntax_error(e.path().to_string(), e.into_inner()))?;
+
+    Builder {
+        path,
+        shutter: (desc.camera.shutter_open, desc.camera.shutter_close),
+        meshes: RefCell::default(),
+    }
+    .build(desc)
+}
+
+// For `Tagged`: puts `type` first in each section that has one.
+fn move_types_first(root: &mut DeTable<'_>) {
+    let type_first = |value: &mut DeValue<'_>| {
+        if let DeValue::Table(table) = value {
+            if let Some((key, tag)) = table.remove_entry("type") {
+                let rest = mem::take(table);
+                table.insert(key, tag);
+                table.extend(rest);
+            }
+        }
+    };
+    for (key, value) in root.iter_mut() {
+        match (key.get_ref().as_ref(), value.get_mut()) {
+            ("objects" | "lights", DeValue::Array(tables)) => {
+                for table in tables.as_mut() {
+                    type_first(table.get_mut());
+                }
+            }
+            ("textures" | "materials", DeValue::Table(tables)) => {
+                for (_, table) in tables.iter_mut() {
+                    type_first(table.get_mut());
+                }
+            }
+            ("environment", table) => type_first(table),
+            _ => {}
+        }
+    }
+}
+
+struct Builder<'a> {
+    path: &'a Path,
+    // The camera's, for building BVHs.
+    shutter: (f64, f64),
+    // OBJ files loaded for instancing, keyed by path.
+    meshes: RefCell<HashMap<PathBuf, Arc<dyn Hittable>>>,
+}
+
+impl Builder<'_> {
+    fn invalid(&self, key: &str, message: &str) -> Error {
+        Error::Invalid {
+            path: self.path.to_path_buf(),
+            key: key.to_string(),
+            message: message.to_string(),
+        }
+    }
+
+    fn positive(&self, key: &str, v: f64) -> Result<f64, Error> {
+        if v > 0. {
+            Ok(v)
+        } else {
+            Err(self.invalid(key, "must be positive"))
+        }
+    }
+
+    fn build(&self, desc: SceneDesc) -> Result<Scene, Error> {
+        let camera = self.camera(&desc.camera)?;
+        let render = self.render(&desc.render)?;
+
+        let mut textures = HashMap::new();
+        for (name, Tagged(t)) in &desc.textures {
+            textures.insert(name.as_str(), self.texture(name, t)?);
+        }
+
+        let mut materials = HashMap::new();
+        for (name, Tagged(m)) in &desc.materials {
+            materials.insert(name.as_str(), self.material(name, m, &textures)?);
+        }
+
+        let mut world = HittableObjects::new();
+        for (i, o) in desc.objects.iter().enumerate() {
+            self.object(&format!("objects[{}]", i), o, &materials, &mut world)?;
+        }
+
+        let mut lights = Vec::new();
+        for (i, Tagged(l)) in desc.lights.iter().enumerate() {
+            lights.push(self.light(&format!("lights[{}]", i), l)?);
+        }
+
+        Ok(Scene {
+            camera,
+            render,
+            world,
+            environment: self.environment(&desc.environment.0)?,
+            lights,
+        })
+    }
+
+    fn camera(&self, desc: &CameraDesc) -> Result<camera::Settings, Error> {
+        let look_from = point(desc.look_from);
+        let look_at = point(desc.look_at);
+        if look_from == look_at {
+            return Err(self.invalid("camera.look_at", "must differ from look_from"));
+        }
+        let up = vec3(desc.up);
+        let view = look_at - look_from;
+        if up.cross(view).mag() <= 1e-9 * up.mag() * view.mag() {
+            return Err(self.invalid("camera.up", "must not be zero, or along the view direction"));
+        }
+        if !(0. < desc.vfov && desc.vfov < 180.) {
+            return Err(self.invalid("camera.vfov", "must be between 0 and 180 degrees"));
+        }
+        if desc.aperture < 0. {
+            return Err(self.invalid("camera.aperture", "must not be negative"));
+        }
+        if desc.shutter_close < desc.shutter_open {
+            return Err(self.invalid("camera.shutter_close", "must not come before shutter_open"));
+        }
+
+        Ok(camera::Settings {
+            look_from,
+            look_at,
+            v_up: up,
+            vfov: desc.vfov,
+            aperture: desc.aperture,
+            focus_dist: self.positive("camera.focus_distance", desc.focus_distance)?,
+            shutter_open: desc.shutter_open,
+            shutter_close: desc.shutter_close,
+        })
+    }
+
+    fn render(&self, desc: &RenderDesc) -> Result<render::Settings, Error> {
+        // The camera maps pixels onto [0, 1] by dividing by `width - 1`.
+        if desc.width < 2 {
+            return Err(self.invalid("render.width", "must be at least 2"));
+        }
+        if desc.height < 2 {
+            return Err(self.invalid("render.height", "must be at least 2"));
+        }
+        if desc.samples_per_pixel == 0 {
+            return Err(self.invalid("render.samples_per_pixel", "must be positive"));
+        }
+        if desc.max_depth == 0 {
+            return Err(self.invalid("render.max_depth", "must be positive"));
+        }
+
+        Ok(render::Settings {
+            width: desc.width,
+            height: desc.height,
+            samples_per_pixel: desc.samples_per_pixel,
+            max_depth: desc.max_depth,
+        })
+    }
+
+    fn texture(&self, name: &str, desc: &TextureDesc) -> Result<Arc<dyn Texture>, Error> {
+        let key = format!("textures.{}", name);
+        Ok(match desc {
+            TextureDesc::Solid { color: c } => Arc::new(SolidColor::new(&color(*c))),
+            TextureDesc::Checker { scale, even, odd } => {
+                let scale = self.positive(&format!("{}.scale", key), *scale)?;
+                Arc::new(Checker::from_colors(scale, &color(*even), &color(*odd)))
+            }
+            TextureDesc::Image { path, wrap } => {
+                let dir = self.path.parent().unwrap_or_else(|| Path::new(""));
+                let path = dir.join(path);
+                let mut image =
+                    ImageTexture::open(&path).map_err(|source| Error::Image { path, source })?;
+                image.wrap = match wrap {
+                    WrapDesc::Repeat => Wrap::Repeat,
+                    WrapDesc::Clamp => Wrap::Clamp,
+                    WrapDesc::Mirror => Wrap::Mirror,
+                };
+                Arc::new(image)
+            }
+            TextureDesc::Noise(noise) => self.noise(&key, Pattern::Noise, noise)?,
+            TextureDesc::Marble(noise) => self.noise(&key, Pattern::Marble, noise)?,
+            TextureDesc::Wood(noise) => self.noise(&key, Pattern::Wood, noise)?,
+            TextureDesc::Granite(noise) => self.noise(&key, Pattern::Granite, noise)?,
+        })
+    }
+
+    fn noise(
+        &self,
+        key: &str,
+        pattern: Pattern,
+        desc: &NoiseDesc,
+    ) -> Result<Arc<dyn Texture>, Error> {
+        let mut texture = NoiseTexture::new(pattern, desc.seed);
+        if let Some(frequency) = desc.frequency {
+            texture.frequency = self.positive(&format!("{}.frequency", key), frequency)?;
+        }
+        if let Some(octaves) = desc.octaves {
+            if octaves == 0 {
+                return Err(self.invalid(&format!("{}.octaves", key), "must be positive"));
+            }
+            texture.octaves = octaves;
+        }
+        texture.dark = desc.dark.map_or(texture.dark, color);
+        texture.light = desc.light.map_or(texture.light, color);
+        Ok(Arc::new(texture))
+    }
+
+    fn material(
+        &self,
+        name: &str,
+        desc: &MaterialDesc,
+        textures: &HashMap<&str, Arc<dyn Texture>>,
+    ) -> Result<Arc<dyn Material>, Error> {
+        let key = format!("materials.{}", name);
+        let lookup = |field: &str, name: &str| {
+            textures.get(name).cloned().ok_or_else(|| {
+                self.invalid(
+                    &format!("{}.{}", key, field),
+                    &format!("undefined texture {:?}", name),
+                )
+            })
+        };
+        let color_param = |field: &str, param: &ColorParam| match param {
+            ColorParam::Value(c) => Ok(Arc::new(SolidColor::new(&color(*c))) as Arc<dyn Texture>),
+            ColorParam::Texture(name) => lookup(field, name),
+        };
+        // For parameters in [0, 1].
+        let unit_param = |field: &str, param: &ScalarParam| match param {
+            ScalarParam::Value(v) => {
+                if !(0. ..=1.).contains(v) {
+                    return Err(self.invalid(&format!("{}.{}", key, field), "must be in [0, 1]"));
+                }
+                Ok(Arc::new(SolidColor::gray(*v)) as Arc<dyn Texture>)
+            }
+            ScalarParam::Texture(name) => lookup(field, name),
+        };
+
+        Ok(match desc {
+            MaterialDesc::Lambertian { albedo } => {
+                Arc::new(Lambertian::textured(color_param("albedo", albedo)?))
+            }
+            MaterialDesc::Metal { albedo, fuzz } => Arc::new(Metal::textured(
+                color_param("albedo", albedo)?,
+                unit_param("fuzz", fuzz)?,
+            )),
+            MaterialDesc::Dielectric { ir } => {
+                Arc::new(Dielectric::new(self.positive(&format!("{}.ir", key), *ir)?))
+            }
+            MaterialDesc::Conductor {
+                metal,
+                eta,
+                k,
+                roughness,
+            } => {
+                let roughness = unit_param("roughness", roughness)?;
+                match (metal, eta, k) {
+                    (Some(metal), None, None) => {
+                        let kind = match metal {
+                            ConductorKindDesc::Gold => ConductorKind::Gold,
+                            ConductorKindDesc::Copper => ConductorKind::Copper,
+                            ConductorKindDesc::Aluminium => ConductorKind::Aluminium,
+                            ConductorKindDesc::Silver => ConductorKind::Silver,
+                        };
+                        Arc::new(Conductor::preset(kind, roughness))
+                    }
+                    (None, Some(eta), Some(k)) => {
+                        Arc::new(Conductor::textured(&color(*eta), &color(*k), roughness))
+                    }
+                    _ => return Err(self.invalid(&key, "needs either a metal, or both eta and k")),
+                }
+            }
+            MaterialDesc::RoughDielectric { ir, roughness } => Arc::new(RoughDielectric::textured(
+                self.positive(&format!("{}.ir", key), *ir)?,
+                unit_param("roughness", roughness)?,
+            )),
+            MaterialDesc::DiffuseLight { emit } => {
+                Arc::new(DiffuseLight::textured(color_param("emit", emit)?))
+            }
+            MaterialDesc::Isotropic { albedo } => {
+                Arc::new(Isotropic::textured(color_param("albedo", albedo)?))
+            }
+            MaterialDesc::HenyeyGreenstein { albedo, g } => {
+                if !(-1. < *g && *g < 1.) {
+                    return Err(self.invalid(&format!("{}.g", key), "must be in (-1, 1)"));
+                }
+                Arc::new(HenyeyGreenstein::textured(
+                    color_param("albedo", albedo)?,
+                    *g,
+                ))
+            }
+            MaterialDesc::Principled {
+                base_color,
+                metallic,
+                roughness,
+                specular,
+                sheen,
+                sheen_tint,
+                clearcoat,
+                clearcoat_gloss,
+                transmission,
+                anisotropic,
+            } => {
+                let mut material = Principled::textured(color_param("base_color", base_color)?);
+                for (field, param, slot) in [
+                    ("metallic", metallic, &mut material.metallic),
+                    ("roughness", roughness, &mut material.roughness),
+                    ("specular", specular, &mut material.specular),
+                    ("sheen", sheen, &mut material.sheen),
+                    ("sheen_tint", sheen_tint, &mut material.sheen_tint),
+                    ("clearcoat", clearcoat, &mut material.clearcoat),
+                    (
+                        "clearcoat_gloss",
+                        clearcoat_gloss,
+                        &mut material.clearcoat_gloss,
+                    ),
+                    ("transmission", transmission, &mut material.transmission),
+                    ("anisotropic", anisotropic, &mut material.anisotropic),
+                ] {
+                    if let Some(param) = param {
+                        *slot = unit_param(field, param)?;
+                    }
+                }
+                Arc::new(material)
+            }
+        })
+    }
+
+    fn object(
+        &self,
+        key: &str,
+        desc: &ObjectDesc,
+        materials: &HashMap<&str, Arc<dyn Material>>,
+        world: &mut HittableObjects,
+    ) -> Result<(), Error> {
+        let lookup = |name: &str| {
+            materials.get(name).cloned().ok_or_else(|| {
+                self.invalid(
+                    &format!("{}.material", key),
+                    &format!("undefined material {:?}", name),
+                )
+            })
+        };
+
+        let mut shapes = HittableObjects::new();
+        match &desc.shape {
+            ShapeDesc::Sphere {
+                center,
+                radius,
+                material,
+            } => {
+                let radius = self.positive(&format!("{}.radius", key), *radius)?;
+                shapes.add(Sphere::new(&point(*center), radius, lookup(material)?));
+            }
+            ShapeDesc::MovingSphere {
+                center0,
+                center1,
+                time0,
+                time1,
+                radius,
+                material,
+            } => {
+                let radius = self.positive(&format!("{}.radius", key), *radius)?;
+                if time1 <= time0 {
+                    return Err(self.invalid(&format!("{}.time1", key), "must come after time0"));
+                }
+                shapes.add(MovingSphere::new(
+                    &point(*center0),
+                    &point(*center1),
+                    *time0,
+                    *time1,
+                    radius,
+                    lookup(material)?,
+                ));
+            }
+            ShapeDesc::Triangle { vertices, material } => {
+                let [p0, p1, p2] = vertices;
+                shapes.add(Triangle::new(
+                    &point(*p0),
+                    &point(*p1),
+                    &point(*p2),
+                    lookup(material)?,
+                ));
+            }
+            ShapeDesc::Quad {
+                corner,
+                u,
+                v,
+                material,
+            } => {
+                let (u, v) = (vec3(*u), vec3(*v));
+                if u.cross(v).is_near_zero() {
+                    return Err(self.invalid(key, "u and v must not be parallel"));
+                }
+                shapes.add(Quad::new(&point(*corner), &u, &v, lookup(material)?));
+            }
+            ShapeDesc::BoxShape { min, max, material } => {
+                shapes.add(BoxShape::new(&point(*min), &point(*max), lookup(material)?));
+            }
+            ShapeDesc::Volume {
+                path,
+                dims,
+                min,
+                max,
+                material,
+            } => {
+                let bounds = Aabb::new(&point(*min), &point(*max));
+                let size = bounds.max - bounds.min;
+                if size.x <= 0. || size.y <= 0. || size.z <= 0. {
+                    return Err(self.invalid(
+                        &format!("{}.max", key),
+                        "must be greater than min along every axis",
+                    ));
+                }
+                let density = match desc.density {
+                    Some(density) => self.positive(&format!("{}.density", key), density)?,
+                    None => 1.,
+                };
+                let dir = self.path.parent().unwrap_or_else(|| Path::new(""));
+                let path = dir.join(path);
+                let grid = match dims {
+                    Some(dims) => DensityGrid::open_raw(&path, *dims),
+                    None => DensityGrid::open(&path),
+                }
+                .map_err(|source| Error::Io { path, source })?;
+                shapes.add(GridMedium::new(
+                    Arc::new(grid),
+                    &bounds,
+                    density,
+                    lookup(material)?,
+                ));
+            }
+            ShapeDesc::Obj { path } => {
+                let dir = self.path.parent().unwrap_or_else(|| Path::new(""));
+                let path = dir.join(path);
+                if desc.transform.is_empty() && desc.motion.is_none() {
+                    shapes = obj::load(&path)?;
+                } else {
+                    shapes.push(self.mesh(path)?);
+                }
+            }
+        }
+
+        // Volumes take care of their own densities.
+        let is_volume = matches!(desc.shape, ShapeDesc::Volume { .. });
+        if let Some(density) = desc.density.filter(|_| !is_volume) {
+            let density = self.positive(&format!("{}.density", key), density)?;
+            let phase_function = match &desc.shape {
+                ShapeDesc::Sphere { material, .. }
+                | ShapeDesc::MovingSphere { material, .. }
+                | ShapeDesc::Triangle { material, .. }
+                | ShapeDesc::Quad { material, .. }
+                | ShapeDesc::BoxShape { material, .. } => lookup(material)?,
+                ShapeDesc::Volume { .. } => unreachable!("volumes scale their own density"),
+                ShapeDesc::Obj { .. } => {
+                    return Err(self.invalid(
+                        &format!("{}.density", key),
+                        "isn't supported for OBJ meshes",
+                    ))
+                }
+            };
+            let boundary = shapes.into_objects().remove(0);
+            shapes = HittableObjects::new();
+            shapes.add(ConstantMedium::with_phase_function(
+                boundary,
+                density,
+                phase_function,
+            ));
+        }
+
+        if desc.transform.is_empty() && desc.motion.is_none() {
+            world.extend(shapes.into_objects());
+            return Ok(());
+        }
+        let to_world = self.transform(&format!("{}.transform", key), &desc.transform)?;
+        let object = match shapes.len() {
+            1 => shapes.into_objects().remove(0),
+            _ => Arc::new(Bvh::new(shapes, self.shutter.0, self.shutter.1)),
+        };
+        match &desc.motion {
+            Some(motion) => {
+                let key = format!("{}.motion", key);
+                if motion.time1 <= motion.time0 {
+                    return Err(self.invalid(&format!("{}.time1", key), "must come after time0"));
+                }
+                let end = self.transform(&format!("{}.transform", key), &motion.transform)?;
+                let motion = AnimatedTransform::new(&to_world, &end, motion.time0, motion.time1);
+                world.add(Instance::moving(object, &motion));
+            }
+            None => world.add(Instance::new(object, &to_world)),
+        }
+        Ok(())
+    }
+
+    fn mesh(&self, path: PathBuf) -> Result<Arc<dyn Hittable>, Error> {
+        if let Some(mesh) = self.meshes.borrow().get(&path) {
+            return Ok(Arc::clone(mesh));
+        }
+        let mesh: Arc<dyn Hittable> =
+            Arc::new(Bvh::new(obj::load(&path)?, self.shutter.0, self.shutter.1));
+        self.meshes.borrow_mut().insert(path, Arc::clone(&mesh));
+        Ok(mesh)
+    }
+
+    fn environment(&self, desc: &EnvironmentDesc) -> Result<Arc<dyn Environment>, Error> {
+        Ok(match desc {
+            EnvironmentDesc::Gradient { bottom, top } => {
+                let d = Gradient::default();
+                Arc::new(Gradient::new(
+                    &bottom.map_or(d.bottom, color),
+                    &top.map_or(d.top, color),
+                ))
+            }
+            EnvironmentDesc::Map {
+                path,
+                rotation,
+                intensity,
+            } => {
+                let dir = self.path.parent().unwrap_or_else(|| Path::new(""));
+                let path = dir.join(path);
+                let mut map =
+                    EnvironmentMap::open(&path).map_err(|source| Error::Io { path, source })?;
+                map.rotation = *rotation;
+                if let Some(intensity) = intensity {
+                    map.intensity = self.positive("environment.intensity", *intensity)?;
+                }
+                Arc::new(map)
+            }
+            EnvironmentDesc::Sky {
+                elevation,
+                azimuth,
+                turbidity,
+                ground,
+                intensity,
+            } => {
+                if !(0. ..=90.).contains(elevation) {
+                    return Err(self.invalid("environment.elevation", "must be in [0, 90]"));
+                }
+                if !(1.7..=10.).contains(turbidity) {
+                    return Err(self.invalid("environment.turbidity", "must be in [1.7, 10]"));
+                }
+                let mut sky = Sky::new(*elevation, *azimuth, *turbidity);
+                if let Some(ground) = ground {
+                    sky.ground_albedo = color(*ground);
+                }
+                if let Some(intensity) = intensity {
+                    sky.intensity = self.positive("environment.intensity", *intensity)?;
+                }
+                Arc::new(sky)
+            }
+        })
+    }
+
+    fn light(&self, key: &str, desc: &LightDesc) -> Result<Arc<dyn Light>, Error> {
+        Ok(match desc {
+            LightDesc::Point {
+                position,
+                intensity,
+            } => Arc::new(PointLight::new(&point(*position), &color(*intensity))),
+            LightDesc::Spot {
+                position,
+                look_at,
+                intensity,
+                cone_angle,
+                cone_delta,
+                ies,
+                up,
+            } => {
+                let position = point(*position);
+                let look_at = point(*look_at);
+                if position == look_at {
+                    return Err(
+                        self.invalid(&format!("{}.look_at", key), "must differ from position")
+                    );
+                }
+                if !(0. < *cone_angle && *cone_angle <= 90.) {
+                    return Err(self.invalid(&format!("{}.cone_angle", key), "must be in (0, 90]"));
+                }
+                if !(0. ..=*cone_angle).contains(cone_delta) {
+                    return Err(self.invalid(
+                        &format!("{}.cone_delta", key),
+                        "must be between 0 and cone_angle",
+                    ));
+                }
+                let mut spot = SpotLight::new(
+                    &position,
+                    &(look_at - position),
+                    &color(*intensity),
+                    *cone_angle,
+                    *cone_delta,
+                );
+                if let Some(path) = ies {
+                    let dir = self.path.parent().unwrap_or_else(|| Path::new(""));
+                    let path = dir.join(path);
+                    let profile =
+                        IesProfile::open(&path).map_err(|source| Error::Io { path, source })?;
+                    spot.profile = Some(Arc::new(profile));
+                }
+                if let Some(up) = up {
+                    let (up, axis) = (vec3(*up), look_at - position);
+                    if up.cross(axis).mag() <= 1e-9 * up.mag() * axis.mag() {
+                        return Err(self.invalid(
+                            &format!("{}.up", key),
+                            "must not be zero, or along the light's axis",
+                        ));
+                    }
+                    spot.orient(&up);
+                }
+                Arc::new(spot)
+            }
+            LightDesc::Directional {
+                direction,
+                irradiance,
+            } => {
+                let direction = vec3(*direction);
+                if direction.mag() == 0. {
+                    return Err(self.invalid(&format!("{}.direction", key), "must not be zero"));
+                }
+                Arc::new(DirectionalLight::new(&direction, &color(*irradiance)))
+            }
+        })
+    }
+
+    fn transform(&self, key: &str, steps: &[TransformDesc]) -> Result<Transform, Error> {
+        let mut t = Transform::identity();
+        for (i, step) in steps.iter().enumerate() {
+            let next = match step {
+                TransformDesc::Translate(v) => Transform::translate(&vec3(*v)),
+                TransformDesc::Scale([x, y, z]) => {
+                    if *x == 0. || *y == 0. || *z == 0. {
+                        return Err(self.invalid(
+                            &format!("{}[{}].scale", key, i),
+                            "must not be zero along any axis",
+                        ));
+                    }
+                    Transform::scale(*x, *y, *z)
+                }
+                TransformDesc::RotateX(degrees) => Transform::rotate_x(*degrees),
+                TransformDesc::RotateY(degrees) => Transform::rotate_y(*degrees),
+                TransformDesc::RotateZ(degrees) => Transform::rotate_z(*degrees),
+            };
+            t = t.then(&next);
+        }
+        Ok(t)
+    }
+}
+
+const fn point(v: [f64; 3]) -> Point3 {
+    Point3::new(v[0], v[1], v[2])
+}
+
+const fn vec3(v: [f64; 3]) -> Vec3 {
+    Vec3::new(v[0], v[1], v[2])
+}
+
+const fn color(v: [f64; 3]) -> Color {
+    Color::new(v[0], v[1], v[2])
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, fs, path::Path, process};
+
+    use super::{parse, Builder, SceneDesc};
+    use crate::{
+        color::Color, framebuffer::Framebuffer, hdr, hit::Hittable, point3::Point3, ray::Ray,
+        sampler::Sampler, vec3::Vec3,
+    };
+
+    #[test]
+    fn parses_scene() {
+        let text = r#"
+[camera]
+look_from = [0, 0, 5]
+look_at = [0, 0, 0]
+vfov = 40
+
+[render]
+width = 64
+height = 32
+
+[materials.red]
+type = "lambertian"
+albedo = [0.8, 0.1, 0.1]
+
+[materials.lamp]
+type = "diffuse_light"
+emit = [4, 4, 4]
+
+[[objects]]
+type = "sphere"
+center = [0, 0, 0]
+radius = 1
+material = "red"
+
+[[objects]]
+type = "sphere"
+center = [0, 3, 0]
+radius = 0.5
+material = "lamp"
+"#;
+        let scene = parse(text, Path::new("test.toml")).unwrap();
+        assert_eq!(scene.render.width, 64);
+        assert_eq!(scene.render.samples_per_pixel, 500);
+        assert!((scene.camera.vfov - 40.).abs() < 1e-12);
+        assert_eq!(scene.world.len(), 2);
+        assert_eq!(scene.world.lights().len(), 1);
+
+        // Lights with no surface, with a spot's profile read from beside the
+        // scene file.
+        let dir = std::env::temp_dir();
+        let name = format!("rtlib-spot-{}.ies", process::id());
+        fs::write(
+            dir.join(&name),
+            "TILT=NONE\n1 1000 1 3 1 1 2 0 0 0\n1 1 10\n0 45 90\n0\n50 100 0\n",
+        )
+        .unwrap();
+        let text = format!(
+            "[[lights]]\ntype = \"point\"\nposition = [0, 4, 0]\nintensity = [1, 1, 1]\n\
+             [[lights]]\ntype = \"spot\"\nposition = [0, 4, 0]\nlook_at = [0, 0, 0]\n\
+             intensity = [2, 2, 2]\nies = {:?}\nup = [1, 0, 0]\n\
+             [[lights]]\ntype = \"directional\"\ndirection = [0, -1, 0]\n\
+             irradiance = [1, 1, 1]\n",
+            name
+        );
+        let scene = parse(&text, &dir.join("test.toml"));
+        fs::remove_file(dir.join(&name)).unwrap();
+
+        let scene = scene.unwrap();
+        assert_eq!(scene.lights.len(), 3);
+        // Straight down, the profile gives half its brightest intensity.
+        let below = scene.lights[1].illuminate(&Point3::zero()).unwrap();
+        assert!((below.irradiance.r - 2. * 0.5 / 16.).abs() < 1e-12);
+        // The file's gone now.
+        assert!(matches!(
+            parse(&text, &dir.join("test.toml")),
+            Err(super::Error::Io { .. })
+        ));
+    }
+
+    #[test]
+    fn transforms_compose_in_order() {
+        // Scaled about the origin first, and then moved: a sphere of radius
+        // 2 around x = 10. The other way around, it'd be around x = 20.
+        let text = r#"
+[materials.gray]
+type = "lambertian"
+albedo = [0.5, 0.5, 0.5]
+
+[[objects]]
+type = "sphere"
+center = [0, 0, 0]
+radius = 1
+material = "gray"
+transform = [{ scale = [2, 2, 2] }, { translate = [10, 0, 0] }]
+"#;
+        let scene = parse(text, Path::new("test.toml")).unwrap();
+        let bounds = scene.world.bounding_box(0., 0.).unwrap();
+        assert!((bounds.min - Point3::new(8., -2., -2.)).mag() < 1e-9);
+        assert!((bounds.max - Point3::new(12., 2., 2.)).mag() < 1e-9);
+    }
+
+    #[test]
+    fn transformed_meshes_are_shared() {
+        let dir = std::env::temp_dir();
+        let obj = dir.join(format!("rtlib-shared-{}.obj", process::id()));
+        fs::write(&obj, "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\n").unwrap();
+        let text = format!(
+            "[[objects]]\ntype = \"obj\"\npath = {0:?}\ntransform = [{{ rotate_y = 90 }}]\n\
+             [[objects]]\ntype = \"obj\"\npath = {0:?}\ntransform = [{{ translate = [5, 0, 0] }}]\n",
+            obj.file_name().unwrap()
+        );
+        let desc: SceneDesc = toml::from_str(&text).unwrap();
+        let builder = Builder {
+            path: &dir.join("test.toml"),
+            shutter: (0., 0.),
+            meshes: RefCell::default(),
+        };
+        let scene = builder.build(desc);
+        fs::remove_file(&obj).unwrap();
+
+        assert_eq!(scene.unwrap().world.len(), 2);
+        assert_eq!(builder.meshes.borrow().len(), 1);
+    }
+
+    #[test]
+    fn transformed_meshes_can_be_lights() {
+        // A glowing unit square, turned to face down from 2 above the origin,
+        // and a sphere that doesn't glow.
+        let dir = std::env::temp_dir();
+        let name = format!("rtlib-lamp-{}", process::id());
+        fs::write(dir.join(format!("{}.mtl", name)), "newmtl glow\nKe 4 4 4\n").unwrap();
+        fs::write(
+            dir.join(format!("{}.obj", name)),
+            format!(
+                "mtllib {}.mtl\nusemtl glow\nv 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nf 1 2 3 4\n",
+                name
+            ),
+        )
+        .unwrap();
+        let text = format!(
+            "[materials.gray]\ntype = \"lambertian\"\nalbedo = [0.5, 0.5, 0.5]\n\
+             [[objects]]\ntype = \"sphere\"\ncenter = [0, -3, 0]\nradius = 1\nmaterial = \"gray\"\n\
+             [[objects]]\ntype = \"obj\"\npath = \"{}.obj\"\ntransform = [\
+             {{ translate = [-0.5, -0.5, 0] }}, {{ rotate_x = 90 }}, {{ translate = [0, 2, 0] }}]\n",
+            name
+        );
+        let scene = parse(&text, &dir.join("test.toml"));
+        fs::remove_file(dir.join(format!("{}.obj", name))).unwrap();
+        fs::remove_file(dir.join(format!("{}.mtl", name))).unwrap();
+
+        let world = scene.unwrap().world;
+        let lights = world.lights();
+        assert_eq!(lights.len(), 1);
+
+        // Every sample lands on the square, and weighting them by 1 / pdf
+        // recovers the solid angle it covers.
+        let mut sampler = Sampler::new(0);
+        let n = 20_000;
+        let mut solid_angle = 0.;
+        for _ in 0..n {
+            let direction = lights.sample_direction(&Point3::zero(), &mut sampler);
+            let ray = Ray {
+                origin: Point3::zero(),
+                direction,
+                time: 0.,
+            };
+            let hit = world.hit(&ray, 0.001, f64::INFINITY, &mut sampler).unwrap();
+            assert!((hit.p.y - 2.).abs() < 1e-9);
+            solid_angle += 1. / lights.pdf_value(&Point3::zero(), &direction) / f64::from(n);
+        }
+        let expected = 4. * (0.25f64 / 4.25).asin();
+        assert!(
+            (solid_angle - expected).abs() < 0.02 * expected,
+            "{}",
+            solid_angle
+        );
+    }
+
+    #[test]
+    fn volumes_read_raw_grids() {
+        // Two cells of 0.5, as raw floats, filling a unit cube.
+        let dir = std::env::temp_dir();
+        let raw = dir.join(format!("rtlib-volume-{}.raw", process::id()));
+        let bytes: Vec<u8> = [0.5f32, 0.5].iter().flat_map(|v| v.to_le_bytes()).collect();
+        fs::write(&raw, bytes).unwrap();
+        let scene = |dims: &str, density: &str| {
+            let text = format!(
+                "[materials.fog]\ntype = \"isotropic\"\nalbedo = [1, 1, 1]\n\
+                 [[objects]]\ntype = \"volume\"\npath = {:?}\ndims = {}\n\
+                 min = [0, 0, 0]\nmax = [1, 1, 1]\nmaterial = \"fog\"\n{}",
+                raw.file_name().unwrap(),
+                dims,
+                density
+            );
+            parse(&text, &dir.join("test.toml"))
+        };
+        let plain = scene("[2, 1, 1]", "");
+        let thick = scene("[2, 1, 1]", "density = 2");
+        let wrong_size = scene("[3, 1, 1]", "");
+        fs::remove_file(&raw).unwrap();
+        assert!(wrong_size.is_err());
+
+        // Light crossing the cube is dimmed by e^-0.5, or by e^-1 with the
+        // density doubled.
+        let ray = Ray {
+            origin: Point3::new(-1., 0.5, 0.5),
+            direction: Vec3::new(1., 0., 0.),
+            time: 0.,
+        };
+        let mut sampler = Sampler::new(0);
+        let mut transmittance = |world: &dyn Hittable| {
+            let n = 20_000;
+            let total: f64 = (0..n)
+                .map(|_| world.transmittance(&ray, 0., f64::INFINITY, &mut sampler))
+                .sum();
+            total / f64::from(n)
+        };
+        let plain = transmittance(&plain.unwrap().world);
+        let thick = transmittance(&thick.unwrap().world);
+        assert!((plain - (-0.5f64).exp()).abs() < 0.02, "{}", plain);
+        assert!((thick - (-1f64).exp()).abs() < 0.02, "{}", thick);
+    }
+
+    #[test]
+    fn environment_maps_load_relative_to_the_scene() {
+        let dir = std::env::temp_dir();
+        let name = format!("rtlib-environment-{}.hdr", process::id());
+        let mut image = Framebuffer::new(4, 2);
+        for c in &mut image.pixels {
+            *c = Color::new(0.5, 0.5, 0.5);
+        }
+        let mut bytes = Vec::new();
+        hdr::write_radiance(&mut bytes, &image).unwrap();
+        fs::write(dir.join(&name), bytes).unwrap();
+        let scene = |fields: &str| {
+            let text = format!(
+                "[environment]\ntype = \"map\"\npath = {:?}\n{}\n",
+                name, fields
+            );
+            parse(&text, &dir.join("test.toml"))
+        };
+        let bright = scene("rotation = 90\nintensity = 2");
+        let dark = scene("intensity = 0");
+        fs::remove_file(dir.join(&name)).unwrap();
+
+        let radiance = bright.unwrap().environment.radiance(&Vec3::new(0., 1., 0.));
+        assert!((radiance.g - 1.).abs() < 0.01, "{:?}", radiance);
+        assert_eq!(
+            dark.err().unwrap().to_string(),
+            format!(
+                "{}: environment.intensity: must be positive",
+                dir.join("test.toml").display()
+            )
+        );
+        // The file's gone now.
+        assert!(matches!(scene(""), Err(super::Error::Io { .. })));
+
+        let text = "[environment]\ntype = \"map\"\npath = \"sky.png\"\n";
+        let err = parse(text, Path::new("test.toml")).err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "sky.png: environment maps must be .hdr or .exr files"
+        );
+    }
+
+    #[test]
+    fn errors_name_the_key() {
+        let text = "[camera]\nvfov = \"wide\"\n";
+        let err = parse(text, Path::new("test.toml")).err().unwrap();
+        assert!(err.to_string().starts_with("test.toml: camera.vfov: "));
+
+        // Within sections that have a type, too, whichever order the keys are
+        // in, and with the line they're on.
+        for (text, key, line) in &[
+            (
+                "[[objects]]\nradius = \"big\"\ntype = \"sphere\"\ncenter = [0, 0, 0]\n",
+                "objects[0].radius",
+                2,
+            ),
+            (
+                "[materials.m]\ntype = \"metal\"\nalbedo = [1, 1, 1]\nfuzz = [1]\n",
+                "materials.m.fuzz",
+                4,
+            ),
+            (
+                "[textures.t]\ntype = \"marble\"\noctaves = -1\n",
+                "textures.t.octaves",
+                3,
+            ),
+            (
+                "[[lights]]\ntype = \"point\"\nposition = [0, 0, 0]\nintensity = 3\n",
+                "lights[0].intensity",
+                4,
+            ),
+            (
+                "[environment]\nelevation = \"high\"\ntype = \"sky\"\n",
+                "environment.elevation",
+                2,
+            ),
+            ("[[objects]]\ntype = \"cube\"\n", "objects[0].type", 2),
+        ] {
+            let err = parse(text, Path::new("test.toml"))
+                .err()
+                .unwrap()
+                .to_string();
+            assert!(err.starts_with(&format!("test.toml: {}: ", key)), "{}", err);
+            assert!(err.contains(&format!("line {},", line)), "{}", err);
+        }
+        // Unknown keys are named, along with every key the object could have.
+        let text = "[[objects]]\ntype = \"sphere\"\ncolour = 1\n";
+        let err = parse(text, Path::new("test.toml"))
+            .err()
+            .unwrap()
+            .to_string();
+        assert!(err.starts_with("test.toml: objects[0].colour: "), "{}", err);
+        assert!(err.ends_with(
+            "unknown field `colour`, expected one of `center`, `radius`, `material`, \
+             `transform`, `motion`, `density`\n"
+        ));
+
+        for up in &["[0, 0, 0]", "[0, -2, 0]"] {
+            let text = format!("[camera]\nlook_from = [0, 5, 0]\nup = {}\n", up);
+            let err = parse(&text, Path::new("test.toml")).err().unwrap();
+            assert_eq!(
+                err.to_string(),
+                "test.toml: camera.up: must not be zero, or along the view direction"
+            );
+        }
+
+        let text =
+            "[[objects]]\ntype = \"sphere\"\ncenter = [0, 0, 0]\nradius = 1\nmaterial = \"nope\"\n";
+        let err = parse(text, Path::new("test.toml")).err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "test.toml: objects[0].material: undefined material \"nope\""
+        );
+
+        let text = "[materials.m]\ntype = \"lambertian\"\nalbedo = \"nope\"\n";
+        let err = parse(text, Path::new("test.toml")).err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "test.toml: materials.m.albedo: undefined texture \"nope\""
+        );
+
+        let text = "[materials.m]\ntype = \"lambertian\"\nalbedo = [1, 1, 1]\n\
+                    [[objects]]\ntype = \"sphere\"\ncenter = [0, 0, 0]\nradius = 1\n\
+                    material = \"m\"\ntransform = [{ rotate_x = 10 }, { scale = [1, 0, 1] }]\n";
+        let err = parse(text, Path::new("test.toml")).err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "test.toml: objects[0].transform[1].scale: must not be zero along any axis"
+        );
+
+        let text = "[materials.fog]\ntype = \"isotropic\"\nalbedo = [1, 1, 1]\n\
+                    [[objects]]\ntype = \"volume\"\npath = \"none.grid\"\n\
+                    min = [0, 0, 0]\nmax = [1, 0, 1]\nmaterial = \"fog\"\n";
+        let err = parse(text, Path::new("test.toml")).err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "test.toml: objects[0].max: must be greater than min along every axis"
+        );
+
+        let sphere = "[materials.m]\ntype = \"lambertian\"\nalbedo = [1, 1, 1]\n\
+                      [[objects]]\nmaterial = \"m\"\nradius = 1\n";
+        let text = format!(
+            "{}type = \"moving_sphere\"\ncenter0 = [0, 0, 0]\ncenter1 = [1, 0, 0]\n\
+             time0 = 1\ntime1 = 1\n",
+            sphere
+        );
+        let err = parse(&text, Path::new("test.toml")).err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "test.toml: objects[0].time1: must come after time0"
+        );
+        let text = format!(
+            "{}type = \"sphere\"\ncenter = [0, 0, 0]\n\
+             motion = {{ transform = [{{ translate = [1, 0, 0] }}], time0 = 2 }}\n",
+            sphere
+        );
+        let err = parse(&text, Path::new("test.toml")).err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "test.toml: objects[0].motion.time1: must come after time0"
+        );
+
+        let spot = |fields: &str| {
+            let text = format!(
+                "[[lights]]\ntype = \"point\"\nposition = [0, 0, 0]\nintensity = [1, 1, 1]\n\
+                 [[lights]]\ntype = \"spot\"\nposition = [0, 4, 0]\nintensity = [1, 1, 1]\n{}\n",
+                fields
+            );
+            parse(&text, Path::new("test.toml")).map(|_| ())
+        };
+        assert!(spot("look_at = [0, 0, 0]\ncone_angle = 90\ncone_delta = 90").is_ok());
+        for (fields, message) in &[
+            (
+                "look_at = [0, 4, 0]",
+                "lights[1].look_at: must differ from position",
+            ),
+            (
+                "look_at = [0, 0, 0]\ncone_angle = 0",
+                "lights[1].cone_angle: must be in (0, 90]",
+            ),
+            (
+                "look_at = [0, 0, 0]\ncone_angle = 91",
+                "lights[1].cone_angle: must be in (0, 90]",
+            ),
+            (
+                "look_at = [0, 0, 0]\ncone_angle = 20\ncone_delta = 25",
+                "lights[1].cone_delta: must be between 0 and cone_angle",
+            ),
+            (
+                "look_at = [0, 0, 0]\ncone_delta = -1",
+                "lights[1].cone_delta: must be between 0 and cone_angle",
+            ),
+            (
+                "look_at = [0, 0, 0]\nup = [0, 2, 0]",
+                "lights[1].up: must not be zero, or along the light's axis",
+            ),
+            (
+                "look_at = [0, 0, 0]\nup = [0, 0, 0]",
+                "lights[1].up: must not be zero, or along the light's axis",
+            ),
+        ] {
+            assert_eq!(
+                spot(fields).err().unwrap().to_string(),
+                format!("test.toml: {}", message)
+            );
+        }
+        let text = "[[lights]]\ntype = \"directional\"\ndirection = [0, 0, 0]\n\
+                    irradiance = [1, 1, 1]\n";
+        let err = parse(text, Path::new("test.toml")).err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "test.toml: lights[0].direction: must not be zero"
+        );
+
+        let conductor = |fields: &str| {
+            let text = format!("[materials.m]\ntype = \"conductor\"\n{}\n", fields);
+            parse(&text, Path::new("test.toml")).map(|_| ())
+        };
+        assert!(conductor("metal = \"gold\"\nroughness = 0.3").is_ok());
+        assert!(conductor("eta = [0.2, 0.9, 1.1]\nk = [3.9, 2.4, 2.2]").is_ok());
+        for fields in &[
+            "",
+            "eta = [0.2, 0.9, 1.1]",
+            "metal = \"gold\"\nk = [3.9, 2.4, 2.2]",
+        ] {
+            assert_eq!(
+                conductor(fields).err().unwrap().to_string(),
+                "test.toml: materials.m: needs either a metal, or both eta and k"
+            );
+        }
+        assert_eq!(
+            conductor("metal = \"silver\"\nroughness = 1.5")
+                .err()
+                .unwrap()
+                .to_string(),
+            "test.toml: materials.m.roughness: must be in [0, 1]"
+        );
+
+        // With several mistakes, the first by name is reported, every time.
+        let text = "[materials.b]\ntype = \"lambertian\"\nalbedo = \"x\"\n\
+                    [materials.a]\ntype = \"lambertian\"\nalbedo = \"y\"\n\
+                    [materials.c]\ntype = \"lambertian\"\nalbedo = \"z\"\n";
+        for _ in 0..10 {
+            let err = parse(text, Path::new("test.toml")).err().unwrap();
+            assert!(err
+                .to_string()
+                .starts_with("test.toml: materials.a.albedo: "));
+        }
+    }
+}
@@ -0,0 +1,352 @@
+// A procedural daylight sky, after Preetham, Shirley and Smits, "A Practical
+// Analytic Model for Daylight" (1999), with the sun as a disk of its true
+// size and brightness.
+//
+// Radiance comes out in units of 10,000 cd/m², so that a clear sky's zenith
+// is about 1, and the sun, which is over 100,000 times brighter, lights the
+// scene like it really would.
+
+use std::f64::consts::PI;
+
+use crate::{color::Color, environment::Environment, onb::Onb, vec3::Vec3};
+
+// cd/m² per unit of radiance.
+const UNIT: f64 = 10_000.;
+
+// The sun's angular radius, in radians.
+const SUN_RADIUS: f64 = 0.004_65;
+
+// Illuminance from the sun above the atmosphere, in lux.
+const SOLAR_ILLUMINANCE: f64 = 128_000.;
+
+pub struct Sky {
+    // Unit vector toward the sun.
+    sun: Vec3,
+    // Perez coefficients (A to E) for the luminance Y and the chromaticities
+    // x and y, and their values at the zenith.
+    perez: [[f64; 5]; 3],
+    zenith: [f64; 3],
+    // Luminance of the sky's formula toward the zenith, over its value there;
+    // the normalization for every other direction.
+    zenith_perez: [f64; 3],
+    sun_radiance: Color,
+    // Light falling on a horizontal surface, from the sky and the sun.
+    illuminance: Color,
+    // Diffuse reflectance of the ground, seen below the horizon.
+    pub ground_albedo: Color,
+    // Scales the sky's radiance.
+    pub intensity: f64,
+}
+
+impl Sky {
+    // The sun's `elevation` above the horizon and its `azimuth` are in
+    // degrees. Azimuth works like a compass bearing with -z as north: 0 puts
+    // the sun toward -z, and 90 toward +x. `turbidity` is how hazy the air
+    // is, from 2 (very clear) to 10 (hazy).
+    //
+    // Panics unless elevation is in [0, 90] and turbidity in [1.7, 10],
+    // where the model holds.
+    pub fn new(elevation: f64, azimuth: f64, turbidity: f64) -> Self {
+        assert!(
+            (0. ..=90.).contains(&elevation),
+            "elevation must be in [0, 90]"
+        );
+        assert!(
+            (1.7..=10.).contains(&turbidity),
+            "turbidity must be in [1.7, 10]"
+        );
+        let (elevation, azimuth) = (elevation.to_radians(), azimuth.to_radians());
+        let sun = Vec3::new(
+            elevation.cos() * azimuth.sin(),
+            elevation.sin(),
+            -elevation.cos() * azimuth.cos(),
+        );
+        let theta_s = PI / 2. - elevation;
+        let t = turbidity;
+
+        let perez = [
+            [
+                0.1787f64.mul_add(t, -1.4630),
+                (-0.3554f64).mul_add(t, 0.4275),
+                (-0.0227f64).mul_add(t, 5.3251),
+                0.1206f64.mul_add(t, -2.5771),
+                (-0.0670f64).mul_add(t, 0.3703),
+            ],
+            [
+                (-0.0193f64).mul_add(t, -0.2592),
+                (-0.0665f64).mul_add(t, 0.0008),
+                (-0.0004f64).mul_add(t, 0.2125),
+                (-0.0641f64).mul_add(t, -0.8989),
+                (-0.0033f64).mul_add(t, 0.0452),
+            ],
+            [
+                (-0.0167f64).mul_add(t, -0.2608),
+                (-0.0950f64).mul_add(t, 0.0092),
+                (-0.0079f64).mul_add(t, 0.2102),
+                (-0.0441f64).mul_add(t, -1.6537),
+                (-0.0109f64).mul_add(t, 0.0529),
+            ],
+        ];
+
+        // Zenith luminance, in kcd/m², and chromaticity.
+        let chi = (4. / 9. - t / 120.) * 2f64.mul_add(-theta_s, PI);
+        let luminance = 4.0453f64.mul_add(t, -4.9710) * chi.tan() - 0.2155 * t + 2.4192;
+        let chromaticity = |m: [[f64; 4]; 3]| {
+            let angles = [theta_s.powi(3), theta_s * theta_s, theta_s, 1.];
+            let turbidities = [t * t, t, 1.];
+            let mut sum = 0.;
+            for (row, tt) in m.iter().zip(&turbidities) {
+                for (c, a) in row.iter().zip(&angles) {
+                    sum += tt * c * a;
+                }
+            }
+            sum
+        };
+        let zenith = [
+            1000. * luminance.max(0.),
+            chromaticity([
+                [0.00166, -0.00375, 0.00209, 0.],
+                [-0.02903, 0.06377, -0.03202, 0.00394],
+                [0.11693, -0.21196, 0.06052, 0.25886],
+            ]),
+            chromaticity([
+                [0.00275, -0.00610, 0.00317, 0.],
+                [-0.04214, 0.08970, -0.04153, 0.00516],
+                [0.15346, -0.26756, 0.06670, 0.26688],
+            ]),
+        ];
+        let zenith_perez = perez.map(|c| perez_function(&c, 0., theta_s));
+
+        let mut sky = Self {
+            sun,
+            perez,
+            zenith,
+            zenith_perez,
+            sun_radiance: sun_radiance(theta_s, t),
+            illuminance: Color::default(),
+            ground_albedo: Color::new(0.3, 0.3, 0.3),
+            intensity: 1.,
+        };
+        sky.illuminance = sky.horizontal_illuminance();
+        sky
+    }
+
+    // Radiance of the sky alone, without the sun, above the horizon.
+    fn sky_radiance(&self, direction: &Vec3) -> Color {
+        let cos_theta = direction.y.max(1e-4);
+        let gamma = direction.dot(self.sun).clamp(-1., 1.).acos();
+        let [luminance, x, y] = [0, 1, 2].map(|i| {
+            let f = perez_function(&self.perez[i], cos_theta.acos(), gamma);
+            self.zenith[i] * f / self.zenith_perez[i]
+        });
+        xyy_to_rgb(x, y, luminance / UNIT)
+    }
+
+    fn in_sun(&self, direction: &Vec3) -> bool {
+        direction.dot(self.sun) >= SUN_RADIUS.cos()
+    }
+
+    // The sky's, from a grid of directions over the upper hemisphere, plus
+    // the sun's.
+    #[allow(clippy::cast_precision_loss)]
+    fn horizontal_illuminance(&self) -> Color {
+        let (rings, segments) = (64, 128);
+        let mut total = Color::default();
+        for i in 0..rings {
+            let theta = (f64::from(i) + 0.5) / f64::from(rings) * PI / 2.;
+            let solid_angle =
+                theta.sin() * (PI / 2. / f64::from(rings)) * (2. * PI / f64::from(segments));
+            for j in 0..segments {
+                let phi = (f64::from(j) + 0.5) / f64::from(segments) * 2. * PI;
+                let direction = Vec3::new(
+                    theta.sin() * phi.cos(),
+                    theta.cos(),
+                    theta.sin() * phi.sin(),
+                );
+                total += (theta.cos() * solid_angle) * self.sky_radiance(&direction);
+            }
+        }
+        total + (self.sun.y * sun_solid_angle()) * self.sun_radiance
+    }
+
+    // How often `sample` aims for the sun.
+    fn sun_probability(&self) -> f64 {
+        if self.sun.y > 0. {
+            0.5
+        } else {
+            0.
+        }
+    }
+}
+
+impl Environment for Sky {
+    fn radiance(&self, direction: &Vec3) -> Color {
+        let radiance = if direction.y < 0. {
+            (1. / PI) * (self.ground_albedo * self.illuminance)
+        } else if self.in_sun(direction) {
+            self.sky_radiance(direction) + self.sun_radiance
+        } else {
+            self.sky_radiance(direction)
+        };
+        self.intensity * radiance
+    }
+
+    // Half the time within the sun's disk, and otherwise uniformly over the
+    // sphere, which suits the smooth sky well enough.
+    fn sample(&self, u: [f64; 2]) -> Option<(Vec3, f64)> {
+        let p_sun = self.sun_probability();
+        let direction = if u[0] < p_sun {
+            let u0 = u[0] / p_sun;
+            let cos_theta = u0.mul_add(SUN_RADIUS.cos() - 1., 1.);
+            let sin_theta = cos_theta.mul_add(-cos_theta, 1.).max(0.).sqrt();
+            let phi = 2. * PI * u[1];
+            Onb::from_w(&self.sun).local(&Vec3::new(
+                sin_theta * phi.cos(),
+                sin_theta * phi.sin(),
+                cos_theta,
+            ))
+        } else {
+            let u0 = (u[0] - p_sun) / (1. - p_sun);
+            let z = 2f64.mul_add(-u0, 1.);
+            let r = z.mul_add(-z, 1.).max(0.).sqrt();
+            let phi = 2. * PI * u[1];
+            Vec3::new(r * phi.cos(), z, r * phi.sin())
+        };
+        Some((direction, self.pdf(&direction)))
+    }
+
+    fn pdf(&self, direction: &Vec3) -> f64 {
+        let p_sun = self.sun_probability();
+        let sun = if self.in_sun(direction) {
+            p_sun / sun_solid_angle()
+        } else {
+            0.
+        };
+        sun + (1. - p_sun) / (4. * PI)
+    }
+}
+
+// Relative luminance (or chromaticity) at a polar angle `theta` from the
+// zenith, and an angle `gamma` from the sun.
+fn perez_function(c: &[f64; 5], theta: f64, gamma: f64) -> f64 {
+    let [a, b, cc, d, e] = *c;
+    let cos_gamma = gamma.cos();
+    a.mul_add((b / theta.cos().max(1e-4)).exp(), 1.)
+        * (cc * (d * gamma).exp() + e * cos_gamma * cos_gamma + 1.)
+}
+
+// CIE xyY to linear sRGB, with negative components clipped.
+fn xyy_to_rgb(x: f64, y: f64, luminance: f64) -> Color {
+    if y <= 0. {
+        return Color::default();
+    }
+    let big_x = x / y * luminance;
+    let big_z = (1. - x - y) / y * luminance;
+    Color::new(
+        (3.2406 * big_x - 1.5372 * luminance - 0.4986 * big_z).max(0.),
+        (-0.9689 * big_x + 1.8758 * luminance + 0.0415 * big_z).max(0.),
+        (0.0557 * big_x - 0.2040 * luminance + 1.0570 * big_z).max(0.),
+    )
+}
+
+fn sun_solid_angle() -> f64 {
+    2. * PI * (1. - SUN_RADIUS.cos())
+}
+
+// The sun's disk as seen through the atmosphere from `theta_s` off the
+// zenith: white sunlight, dimmed by Rayleigh scattering from the air and
+// Mie scattering from haze along the way, both of which take more blue than
+// red.
+fn sun_radiance(theta_s: f64, turbidity: f64) -> Color {
+    // Relative optical air mass (Kasten and Young).
+    let degrees = theta_s.to_degrees();
+    let air_mass = 1. / 0.50572f64.mul_add((96.07995 - degrees).powf(-1.6364), theta_s.cos());
+    let beta = 0.04608f64.mul_add(turbidity, -0.04586);
+    // Roughly red, green and blue wavelengths, in micrometers.
+    let transmittance = |lambda: f64| {
+        let rayleigh = 0.008735 * lambda.powf(-4.08);
+        let mie = beta * lambda.powf(-1.3);
+        (-(rayleigh + mie) * air_mass).exp()
+    };
+    let radiance = SOLAR_ILLUMINANCE / sun_solid_angle() / UNIT;
+    Color::new(
+        radiance * transmittance(0.65),
+        radiance * transmittance(0.55),
+        radiance * transmittance(0.45),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::PI;
+
+    use super::{sun_solid_angle, Sky};
+    use crate::{environment::Environment, vec3::Vec3};
+
+    #[test]
+    fn sun_is_bright_and_reddens_low_down() {
+        let high = Sky::new(60., 0., 3.);
+        let low = Sky::new(5., 0., 3.);
+        let toward_sun = |elevation: f64| {
+            let e = elevation.to_radians();
+            Vec3::new(0., e.sin(), -e.cos())
+        };
+
+        // Sunlight is far brighter than the sky beside it.
+        let sun = high.radiance(&toward_sun(60.));
+        let beside = high.radiance(&toward_sun(50.));
+        assert!(sun.g > 10_000. * beside.g);
+
+        // Low down, it's dimmer and redder.
+        let low_sun = low.radiance(&toward_sun(5.));
+        assert!(low_sun.g < sun.g);
+        assert!(low_sun.r / low_sun.b > sun.r / sun.b);
+
+        // The sky is bluest away from the sun, and brightest around it.
+        let zenith = high.radiance(&Vec3::new(0., 1., 0.));
+        assert!(zenith.b > zenith.r);
+        let opposite = high.radiance(&Vec3::new(0., 0.5, 0.75f64.sqrt()));
+        assert!(beside.g > opposite.g);
+
+        // Noon light on the ground is in the tens of thousands of lux, which
+        // is several units.
+        let lux = high.illuminance.g;
+        assert!((3. ..15.).contains(&lux), "{}", lux);
+    }
+
+    #[test]
+    fn sampling_finds_the_light() {
+        let sky = Sky::new(40., 120., 4.);
+        let n = 200;
+        let mut estimate = 0.;
+        for i in 0..n {
+            for j in 0..n {
+                let u = [
+                    (f64::from(i) + 0.5) / f64::from(n),
+                    (f64::from(j) + 0.5) / f64::from(n),
+                ];
+                let (direction, pdf) = sky.sample(u).unwrap();
+                assert!((sky.pdf(&direction) - pdf).abs() <= 1e-9 * pdf);
+                if direction.y > 0. {
+                    estimate += direction.y * sky.radiance(&direction).g / pdf;
+                }
+            }
+        }
+        estimate /= f64::from(n * n);
+        let expected = sky.illuminance.g;
+        assert!((estimate - expected).abs() < 0.02 * expected);
+        assert!((sky.pdf(&Vec3::new(0., -1., 0.)) - 0.5 / (4. * PI)).abs() < 1e-12);
+
+        // A sun on the horizon is partly below it. The ground hides that part,
+        // but sampling still picks it, so the density must count it.
+        let sky = Sky::new(0.1, 0., 3.);
+        let below = Vec3::new(
+            0.,
+            -(0.1f64.to_radians()).sin(),
+            -(0.1f64.to_radians()).cos(),
+        );
+        let expected = 0.5 / sun_solid_angle() + 0.5 / (4. * PI);
+        assert!((sky.pdf(&below) - expected).abs() < 1e-9 * expected);
+        assert_eq!(sky.radiance(&below), sky.radiance(&Vec3::new(0., -1., 0.)));
+    }
+}
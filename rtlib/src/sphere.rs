@@ -1,26 +1,96 @@
-use std::rc::Rc;
+use std::{f64::consts::PI, sync::Arc};
+
+use rand::Rng;
 
 use crate::{
+    aabb::Aabb,
     hit::{Hittable, Record},
     material::{Blank, Material},
+    onb::Onb,
     point3::Point3,
     ray::Ray,
+    sampler::Sampler,
+    vec3::Vec3,
 };
 
 pub struct Sphere {
     pub center: Point3,
     pub radius: f64,
-    pub mat: Rc<dyn Material>,
+    pub mat: Arc<dyn Material>,
 }
 
 impl Sphere {
-    pub fn new(center: &Point3, radius: f64, mat: Rc<dyn Material>) -> Self {
+    pub fn new(center: &Point3, radius: f64, mat: Arc<dyn Material>) -> Self {
         Self {
             center: *center,
             radius,
             mat,
         }
     }
+
+    // Maps a point on the unit sphere to UVs: u goes around the y axis
+    // starting from -x, and v goes from the bottom (-y) to the top (+y).
+    fn uv(p: &Vec3) -> (f64, f64) {
+        let theta = (-p.y).clamp(-1., 1.).acos();
+        let phi = (-p.z).atan2(p.x) + PI;
+        (phi / (2. * PI), theta / PI)
+    }
+
+    fn intersect(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<Record> {
+        intersect(&self.center, self.radius, &self.mat, ray, t_min, t_max)
+    }
+}
+
+fn intersect(
+    center: &Point3,
+    radius: f64,
+    mat: &Arc<dyn Material>,
+    ray: &Ray,
+    t_min: f64,
+    t_max: f64,
+) -> Option<Record> {
+    // Compute the discriminant.
+    let oc = ray.origin - *center;
+    let a = ray.direction.mag_squared();
+    let half_b = oc.dot(ray.direction);
+    let c = oc.mag_squared() - radius * radius;
+    let discriminant = half_b * half_b - a * c;
+    if discriminant < 0. {
+        return None;
+    }
+
+    // Pick a root that lies in the acceptable range, if possible.
+    let sqrtd = discriminant.sqrt();
+    let sub_root = (-half_b - sqrtd) / a;
+    let add_root = (-half_b + sqrtd) / a;
+    let root: Option<f64> = if t_min < sub_root && sub_root < t_max {
+        Some(sub_root)
+    } else if t_min < add_root && add_root < t_max {
+        Some(add_root)
+    } else {
+        None
+    };
+
+    // Update the HitRecord.
+    let mut record = Record::new();
+    match root {
+        Some(v) => record.t = v,
+        None => return None, // No hit.
+    }
+    record.p = ray.at(record.t);
+    let outward_normal = (record.p - *center) / radius;
+    record.set_face_normal(ray, &outward_normal);
+    let (u, v) = Sphere::uv(&outward_normal);
+    record.u = u;
+    record.v = v;
+    record.mat = Arc::clone(mat);
+
+    Some(record)
+}
+
+fn bounding_box(center: &Point3, radius: f64) -> Aabb {
+    let r = Vec3::new(radius, radius, radius);
+    Aabb::new(&(*center - r), &(*center + r))
 }
 
 impl Default for Sphere {
@@ -28,46 +98,183 @@ impl Default for Sphere {
         Self {
             center: Point3::zero(),
             radius: 0.,
-            mat: Rc::new(Blank::new()),
+            mat: Arc::new(Blank::new()),
         }
     }
 }
 
 impl Hittable for Sphere {
-    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<Record> {
-        // Compute the discriminant.
-        let oc = ray.origin - self.center;
-        let a = ray.direction.mag_squared();
-        let half_b = oc.dot(ray.direction);
-        let c = oc.mag_squared() - self.radius * self.radius;
-        let discriminant = half_b * half_b - a * c;
-        if discriminant < 0. {
-            return None;
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64, _sampler: &mut Sampler) -> Option<Record> {
+        self.intersect(ray, t_min, t_max)
+    }
+
+    fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<Aabb> {
+        Some(bounding_box(&self.center, self.radius))
+    }
+
+    fn is_emissive(&self) -> bool {
+        self.mat.is_emissive()
+    }
+
+    // Samples the cone of directions that the sphere subtends, or every
+    // direction if `origin` is inside the sphere.
+    fn sample_direction(&self, origin: &Point3, sampler: &mut Sampler) -> Vec3 {
+        let to_center = self.center - *origin;
+        let dist_squared = to_center.mag_squared();
+        if dist_squared <= self.radius * self.radius {
+            return Vec3::rand_unit(sampler);
         }
 
-        // Pick a root that lies in the acceptable range, if possible.
-        let sqrtd = discriminant.sqrt();
-        let sub_root = (-half_b - sqrtd) / a;
-        let add_root = (-half_b + sqrtd) / a;
-        let root: Option<f64> = if t_min < sub_root && sub_root < t_max {
-            Some(sub_root)
-        } else if t_min < add_root && add_root < t_max {
-            Some(add_root)
-        } else {
-            None
+        let r1: f64 = sampler.gen();
+        let r2: f64 = sampler.gen();
+        let cos_theta_max = (1. - self.radius * self.radius / dist_squared).sqrt();
+        let z = r2.mul_add(cos_theta_max - 1., 1.);
+        let phi = 2. * PI * r1;
+        let sin_theta = (1. - z * z).sqrt();
+
+        Onb::from_w(&to_center).local(&Vec3::new(phi.cos() * sin_theta, phi.sin() * sin_theta, z))
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f64 {
+        let ray = Ray {
+            origin: *origin,
+            direction: *direction,
+            time: 0.,
         };
+        if self.intersect(&ray, 0.001, f64::INFINITY).is_none() {
+            return 0.;
+        }
+
+        let dist_squared = (self.center - *origin).mag_squared();
+        if dist_squared <= self.radius * self.radius {
+            return 1. / (4. * PI);
+        }
+        let cos_theta_max = (1. - self.radius * self.radius / dist_squared).sqrt();
+        1. / (2. * PI * (1. - cos_theta_max))
+    }
+}
 
-        // Update the HitRecord.
-        let mut record = Record::new();
-        match root {
-            Some(v) => record.t = v,
-            None => return None, // No hit.
+// A sphere moving in a straight line, from `center0` at `time0` to `center1`
+// at `time1`. Like an `AnimatedTransform`, it holds still outside that
+// interval.
+//
+// It isn't sampled as a light, even if it glows, since where it is depends on
+// the time.
+pub struct MovingSphere {
+    pub center0: Point3,
+    pub center1: Point3,
+    pub time0: f64,
+    pub time1: f64,
+    pub radius: f64,
+    pub mat: Arc<dyn Material>,
+}
+
+impl MovingSphere {
+    // Panics unless `time0` comes before `time1`.
+    pub fn new(
+        center0: &Point3,
+        center1: &Point3,
+        time0: f64,
+        time1: f64,
+        radius: f64,
+        mat: Arc<dyn Material>,
+    ) -> Self {
+        assert!(time0 < time1, "time1 must come after time0");
+        Self {
+            center0: *center0,
+            center1: *center1,
+            time0,
+            time1,
+            radius,
+            mat,
         }
-        record.p = ray.at(record.t);
-        let outward_normal = (record.p - self.center) / self.radius;
-        record.set_face_normal(ray, &outward_normal);
-        record.mat = self.mat.clone();
+    }
+
+    pub fn center(&self, time: f64) -> Point3 {
+        let s = ((time - self.time0) / (self.time1 - self.time0)).clamp(0., 1.);
+        self.center0 + s * (self.center1 - self.center0)
+    }
+}
 
-        Some(record)
+impl Hittable for MovingSphere {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64, _sampler: &mut Sampler) -> Option<Record> {
+        intersect(
+            &self.center(ray.time),
+            self.radius,
+            &self.mat,
+            ray,
+            t_min,
+            t_max,
+        )
+    }
+
+    fn bounding_box(&self, time0: f64, time1: f64) -> Option<Aabb> {
+        let start = bounding_box(&self.center(time0), self.radius);
+        let end = bounding_box(&self.center(time1), self.radius);
+        Some(start.union(&end))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::MovingSphere;
+    use crate::{
+        hit::Hittable,
+        material::Blank,
+        point3::Point3,
+        ray::Ray,
+        sampler::Sampler,
+        transform::{AnimatedTransform, Transform},
+        vec3::Vec3,
+    };
+
+    #[test]
+    fn moving_sphere_is_hit_where_it_is() {
+        let sphere = MovingSphere::new(
+            &Point3::new(0., 0., 0.),
+            &Point3::new(4., 0., 0.),
+            0.,
+            1.,
+            1.,
+            Arc::new(Blank::new()),
+        );
+        let mut sampler = Sampler::new(0);
+        let ray_at = |time| Ray {
+            origin: Point3::new(2., 0., 5.),
+            direction: Vec3::new(0., 0., -1.),
+            time,
+        };
+
+        // Straight down the middle, it's only there halfway through.
+        assert!(sphere
+            .hit(&ray_at(0.), 0.001, f64::INFINITY, &mut sampler)
+            .is_none());
+        let rec = sphere
+            .hit(&ray_at(0.5), 0.001, f64::INFINITY, &mut sampler)
+            .expect("should hit");
+        assert!((rec.t - 4.).abs() < 1e-12);
+
+        let bbox = sphere.bounding_box(0., 1.).unwrap();
+        assert_eq!(bbox.min, Point3::new(-1., -1., -1.));
+        assert_eq!(bbox.max, Point3::new(5., 1., 1.));
+
+        // Before and after, it stays where the motion starts and ends, the
+        // same as an animated instance would, so a wide shutter blurs both
+        // alike.
+        let motion = AnimatedTransform::new(
+            &Transform::identity(),
+            &Transform::translate(&Vec3::new(4., 0., 0.)),
+            0.,
+            1.,
+        );
+        for time in &[-1., 2.] {
+            assert_eq!(
+                sphere.center(*time),
+                motion.at(*time).point(&Point3::zero())
+            );
+        }
+        assert_eq!(sphere.bounding_box(-1., 2.).unwrap(), bbox);
     }
 }
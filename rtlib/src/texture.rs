@@ -0,0 +1,283 @@
+// Spatially varying material parameters, looked up by surface coordinates
+// (u, v) and hit point p.
+
+use std::{path::Path, sync::Arc};
+
+use crate::{color::Color, perlin::Perlin, point3::Point3};
+
+pub trait Texture: Send + Sync {
+    fn value(&self, u: f64, v: f64, p: &Point3) -> Color;
+
+    // For single-valued parameters, such as roughness.
+    fn scalar(&self, u: f64, v: f64, p: &Point3) -> f64 {
+        self.value(u, v, p).luminance()
+    }
+}
+
+pub struct SolidColor {
+    pub color: Color,
+}
+
+impl SolidColor {
+    pub const fn new(color: &Color) -> Self {
+        Self { color: *color }
+    }
+
+    // The same value in every channel, for scalar parameters.
+    pub const fn gray(v: f64) -> Self {
+        Self::new(&Color::new(v, v, v))
+    }
+}
+
+impl Texture for SolidColor {
+    fn value(&self, _u: f64, _v: f64, _p: &Point3) -> Color {
+        self.color
+    }
+}
+
+// A 3D checkerboard of `scale`-sized cubes, alternating between two textures.
+// Being solid, it needs no UVs, and doesn't stretch near a sphere's poles.
+pub struct Checker {
+    pub scale: f64,
+    pub even: Arc<dyn Texture>,
+    pub odd: Arc<dyn Texture>,
+}
+
+impl Checker {
+    pub fn new(scale: f64, even: Arc<dyn Texture>, odd: Arc<dyn Texture>) -> Self {
+        Self { scale, even, odd }
+    }
+
+    pub fn from_colors(scale: f64, even: &Color, odd: &Color) -> Self {
+        Self::new(
+            scale,
+            Arc::new(SolidColor::new(even)),
+            Arc::new(SolidColor::new(odd)),
+        )
+    }
+}
+
+impl Texture for Checker {
+    fn value(&self, u: f64, v: f64, p: &Point3) -> Color {
+        let cell = |x: f64| (x / self.scale).floor();
+        #[allow(clippy::cast_possible_truncation)]
+        let parity = (cell(p.x) + cell(p.y) + cell(p.z)) as i64;
+        if parity.rem_euclid(2) == 0 {
+            self.even.value(u, v, p)
+        } else {
+            self.odd.value(u, v, p)
+        }
+    }
+}
+
+// What `ImageTexture` does with UVs outside [0, 1].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Wrap {
+    Repeat,
+    Clamp,
+    Mirror,
+}
+
+impl Wrap {
+    // Maps a texel coordinate into [0, size).
+    #[allow(clippy::cast_possible_wrap, clippy::cast_sign_loss)]
+    fn apply(self, i: i64, size: usize) -> usize {
+        let size = size as i64;
+        let i = match self {
+            Self::Repeat => i.rem_euclid(size),
+            Self::Clamp => i.clamp(0, size - 1),
+            Self::Mirror => {
+                let i = i.rem_euclid(2 * size);
+                if i < size {
+                    i
+                } else {
+                    2 * size - 1 - i
+                }
+            }
+        };
+        i as usize
+    }
+}
+
+// An image, bilinearly filtered. (0, 0) is the bottom left corner and (1, 1)
+// the top right.
+pub struct ImageTexture {
+    width: usize,
+    height: usize,
+    // Linear values, in row-major order with the top row first.
+    pixels: Vec<Color>,
+    pub wrap: Wrap,
+}
+
+impl ImageTexture {
+    // Panics unless there are `width * height` pixels.
+    pub fn new(width: usize, height: usize, pixels: Vec<Color>) -> Self {
+        assert!(width > 0 && height > 0 && pixels.len() == width * height);
+        Self {
+            width,
+            height,
+            pixels,
+            wrap: Wrap::Repeat,
+        }
+    }
+
+    // Loads any format `image` can decode, e.g. PNG or JPEG. Pixel values are
+    // taken to be sRGB-encoded.
+    pub fn open(path: &Path) -> Result<Self, image::ImageError> {
+        let img = image::open(path)?.into_rgb16();
+        let decode = |x: u16| srgb_eotf(f64::from(x) / f64::from(u16::MAX));
+        let pixels = img
+            .pixels()
+            .map(|p| Color::new(decode(p[0]), decode(p[1]), decode(p[2])))
+            .collect();
+        Ok(Self::new(
+            img.width() as usize,
+            img.height() as usize,
+            pixels,
+        ))
+    }
+
+    fn texel(&self, x: i64, y: i64) -> Color {
+        let x = self.wrap.apply(x, self.width);
+        let y = self.wrap.apply(y, self.height);
+        self.pixels[y * self.width + x]
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, u: f64, v: f64, _p: &Point3) -> Color {
+        // Texel centers sit at half-integer coordinates.
+        #[allow(clippy::cast_precision_loss)]
+        let x = u.mul_add(self.width as f64, -0.5);
+        #[allow(clippy::cast_precision_loss)]
+        let y = (1. - v).mul_add(self.height as f64, -0.5);
+        if !(x.is_finite() && y.is_finite()) {
+            return Color::default();
+        }
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        #[allow(clippy::cast_possible_truncation)]
+        let (x0, y0) = (x0 as i64, y0 as i64);
+
+        let top = (1. - fx) * self.texel(x0, y0) + fx * self.texel(x0 + 1, y0);
+        let bottom = (1. - fx) * self.texel(x0, y0 + 1) + fx * self.texel(x0 + 1, y0 + 1);
+        (1. - fy) * top + fy * bottom
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Pattern {
+    // Plain fBm.
+    Noise,
+    // Veins of turbulence across stripes along z.
+    Marble,
+    // Rings around the y axis, wobbled by fBm.
+    Wood,
+    // Fine, high-contrast speckles of turbulence.
+    Granite,
+}
+
+// A procedural pattern built from Perlin noise, blending from `dark` to
+// `light`. The point is scaled by `frequency` before any noise lookups, and
+// `octaves` layers of noise go into each fractal sum.
+pub struct NoiseTexture {
+    pub pattern: Pattern,
+    pub frequency: f64,
+    pub octaves: u32,
+    pub dark: Color,
+    pub light: Color,
+    perlin: Perlin,
+}
+
+impl NoiseTexture {
+    // Every pattern gets its own default frequency and colors. `seed` picks
+    // the underlying noise.
+    pub fn new(pattern: Pattern, seed: u64) -> Self {
+        let (frequency, dark, light) = match pattern {
+            Pattern::Noise => (4., Color::new(0., 0., 0.), Color::new(1., 1., 1.)),
+            Pattern::Marble => (1., Color::new(0.2, 0.2, 0.25), Color::new(0.95, 0.95, 0.9)),
+            Pattern::Wood => (1., Color::new(0.35, 0.2, 0.08), Color::new(0.7, 0.5, 0.3)),
+            Pattern::Granite => (8., Color::new(0.1, 0.1, 0.1), Color::new(0.8, 0.75, 0.7)),
+        };
+        Self {
+            pattern,
+            frequency,
+            octaves: 7,
+            dark,
+            light,
+            perlin: Perlin::new(seed),
+        }
+    }
+}
+
+impl Texture for NoiseTexture {
+    fn value(&self, _u: f64, _v: f64, p: &Point3) -> Color {
+        let f = self.frequency;
+        let q = Point3::new(f * p.x, f * p.y, f * p.z);
+        let t = match self.pattern {
+            Pattern::Noise => 0.5 * (1. + self.perlin.fbm(&q, self.octaves)),
+            Pattern::Marble => {
+                let turbulence = self.perlin.turbulence(&q, self.octaves);
+                0.5 * (1. + 10_f64.mul_add(turbulence, 4. * q.z).sin())
+            }
+            Pattern::Wood => {
+                let r = 10. * q.x.hypot(q.z);
+                self.perlin
+                    .fbm(&q, self.octaves)
+                    .mul_add(2., r)
+                    .rem_euclid(1.)
+            }
+            Pattern::Granite => 1.5 * self.perlin.turbulence(&q, self.octaves),
+        }
+        .clamp(0., 1.);
+        (1. - t) * self.dark + t * self.light
+    }
+}
+
+fn srgb_eotf(x: f64) -> f64 {
+    if x <= 0.040_45 {
+        x / 12.92
+    } else {
+        ((x + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Checker, ImageTexture, Texture, Wrap};
+    use crate::{color::Color, point3::Point3};
+
+    #[test]
+    fn checker_alternates() {
+        let black = Color::new(0., 0., 0.);
+        let white = Color::new(1., 1., 1.);
+        let checker = Checker::from_colors(1., &white, &black);
+        let at = |x, y, z| checker.value(0., 0., &Point3::new(x, y, z));
+        assert_eq!(at(0.5, 0.5, 0.5), white);
+        assert_eq!(at(1.5, 0.5, 0.5), black);
+        assert_eq!(at(-0.5, 0.5, 0.5), black);
+        assert_eq!(at(-0.5, -0.5, 0.5), white);
+    }
+
+    #[test]
+    fn image_filters_and_wraps() {
+        let black = Color::new(0., 0., 0.);
+        let white = Color::new(1., 1., 1.);
+        let mut tex = ImageTexture::new(2, 1, vec![black, white]);
+        let p = Point3::zero();
+
+        // Texel centers are exact, and halfway between them is a blend.
+        assert_eq!(tex.value(0.25, 0.5, &p), black);
+        assert_eq!(tex.value(0.75, 0.5, &p), white);
+        assert_eq!(tex.value(0.5, 0.5, &p), Color::new(0.5, 0.5, 0.5));
+
+        // Past the right edge, repeating wraps back to black; clamping and
+        // mirroring stay white.
+        assert_eq!(tex.value(1.25, 0.5, &p), black);
+        tex.wrap = Wrap::Clamp;
+        assert_eq!(tex.value(1.25, 0.5, &p), white);
+        tex.wrap = Wrap::Mirror;
+        assert_eq!(tex.value(1.25, 0.5, &p), white);
+        assert_eq!(tex.value(1.75, 0.5, &p), black);
+    }
+}
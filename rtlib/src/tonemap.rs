@@ -0,0 +1,162 @@
+// Display transforms. Rendered radiance is linear and unbounded, so before it
+// can go into an 8-bit image it's scaled by an exposure, compressed into
+// [0, 1] by a tone mapping curve, and then encoded with the sRGB OETF (see
+// `Color::to_rgb`).
+
+use crate::{color::Color, framebuffer::Framebuffer};
+
+pub trait ToneMapper: Send + Sync {
+    // Maps exposed linear radiance to display-linear values, nominally in
+    // [0, 1]. Anything outside that range is clipped on output.
+    fn map(&self, c: &Color) -> Color;
+}
+
+// No tone mapping at all: everything above 1.0 clips.
+pub struct Clamp;
+
+impl ToneMapper for Clamp {
+    fn map(&self, c: &Color) -> Color {
+        *c
+    }
+}
+
+// Reinhard et al. 2002, L / (1 + L) applied to luminance so that hues are
+// kept. Only infinite radiance reaches white.
+pub struct Reinhard;
+
+impl ToneMapper for Reinhard {
+    fn map(&self, c: &Color) -> Color {
+        scale_luminance(c, |l| l / (1. + l))
+    }
+}
+
+// Reinhard's curve, but with `white` (in exposed luminance) mapping to 1.0.
+pub struct ExtendedReinhard {
+    pub white: f64,
+}
+
+impl ExtendedReinhard {
+    pub const DEFAULT_WHITE: f64 = 4.;
+}
+
+impl ToneMapper for ExtendedReinhard {
+    fn map(&self, c: &Color) -> Color {
+        let w2 = self.white * self.white;
+        scale_luminance(c, |l| l * (1. + l / w2) / (1. + l))
+    }
+}
+
+// John Hable's filmic curve from Uncharted 2, per channel. `white` is the
+// exposed radiance that maps to 1.0; anything brighter clips.
+pub struct Hable {
+    pub white: f64,
+}
+
+impl Hable {
+    // Hable's white point of 11.2, before his exposure bias.
+    pub const DEFAULT_WHITE: f64 = 5.6;
+    const EXPOSURE_BIAS: f64 = 2.;
+
+    fn curve(x: f64) -> f64 {
+        const A: f64 = 0.15; // Shoulder strength.
+        const B: f64 = 0.50; // Linear strength.
+        const C: f64 = 0.10; // Linear angle.
+        const D: f64 = 0.20; // Toe strength.
+        const E: f64 = 0.02; // Toe numerator.
+        const F: f64 = 0.30; // Toe denominator.
+        (x * (A * x + C * B) + D * E) / (x * (A * x + B) + D * F) - E / F
+    }
+}
+
+impl ToneMapper for Hable {
+    fn map(&self, c: &Color) -> Color {
+        let scale = 1. / Self::curve(Self::EXPOSURE_BIAS * self.white);
+        per_channel(c, |x| {
+            (Self::curve(Self::EXPOSURE_BIAS * x) * scale).min(1.)
+        })
+    }
+}
+
+// Krzysztof Narkowicz's fit of the ACES filmic reference rendering and output
+// transforms, per channel.
+pub struct Aces;
+
+impl ToneMapper for Aces {
+    fn map(&self, c: &Color) -> Color {
+        per_channel(c, |x| {
+            let x = 0.6 * x;
+            (x * (2.51 * x + 0.03) / (x * (2.43 * x + 0.59) + 0.14)).clamp(0., 1.)
+        })
+    }
+}
+
+pub const NAMES: [&str; 5] = ["clamp", "reinhard", "reinhard-extended", "hable", "aces"];
+
+// Looks up a tone mapper by its name in `NAMES`. `white` sets the white point
+// of the curves that have one, and is otherwise ignored.
+pub fn by_name(name: &str, white: Option<f64>) -> Option<Box<dyn ToneMapper>> {
+    Some(match name {
+        "clamp" => Box::new(Clamp),
+        "reinhard" => Box::new(Reinhard),
+        "reinhard-extended" => Box::new(ExtendedReinhard {
+            white: white.unwrap_or(ExtendedReinhard::DEFAULT_WHITE),
+        }),
+        "hable" => Box::new(Hable {
+            white: white.unwrap_or(Hable::DEFAULT_WHITE),
+        }),
+        "aces" => Box::new(Aces),
+        _ => return None,
+    })
+}
+
+// Converts a framebuffer to 8-bit sRGB, after scaling it by 2^`exposure`
+// (i.e. `exposure` is in stops, or EV).
+pub fn to_rgb8(fb: &Framebuffer, tone_mapper: &dyn ToneMapper, exposure: f64) -> Vec<[u8; 3]> {
+    let scale = exposure.exp2();
+    fb.pixels
+        .iter()
+        .map(|c| tone_mapper.map(&(scale * *c)).to_rgb())
+        .collect()
+}
+
+fn per_channel<F: Fn(f64) -> f64>(c: &Color, f: F) -> Color {
+    Color::new(f(c.r), f(c.g), f(c.b))
+}
+
+fn scale_luminance<F: Fn(f64) -> f64>(c: &Color, f: F) -> Color {
+    let l = c.luminance();
+    if l <= 0. {
+        return Color::default();
+    }
+    (f(l) / l) * *c
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{by_name, NAMES};
+    use crate::color::Color;
+
+    #[test]
+    fn curves_are_increasing_and_bounded() {
+        for name in &NAMES[1..] {
+            let tm = by_name(name, None).unwrap();
+            let mut prev = 0.;
+            for i in 1..=80 {
+                let x = 0.05 * f64::from(i);
+                let y = tm.map(&Color::new(x, x, x)).g;
+                assert!(prev <= y, "{} isn't increasing at {}", name, x);
+                assert!(y <= 1. + 1e-9, "{} exceeds 1 at {}", name, x);
+                prev = y;
+            }
+        }
+    }
+
+    #[test]
+    fn white_points_map_to_one() {
+        for (name, white) in &[("reinhard-extended", 3.), ("hable", 6.)] {
+            let tm = by_name(name, Some(*white)).unwrap();
+            let y = tm.map(&Color::new(*white, *white, *white));
+            assert!((y.r - 1.).abs() < 1e-9, "{} maps white to {}", name, y.r);
+        }
+    }
+}
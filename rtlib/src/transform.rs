@@ -0,0 +1,562 @@
+// Affine transforms, as 4x4 matrices. Each transform carries its own inverse,
+// so inverting one is free and never fails.
+
+use crate::{aabb::Aabb, point3::Point3, ray::Ray, vec3::Vec3};
+
+type Matrix = [[f64; 4]; 4];
+
+// The linear (non-translating) part of a transform.
+type Matrix3 = [[f64; 3]; 3];
+
+const IDENTITY: Matrix = [
+    [1., 0., 0., 0.],
+    [0., 1., 0., 0.],
+    [0., 0., 1., 0.],
+    [0., 0., 0., 1.],
+];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transform {
+    m: Matrix,
+    inv: Matrix,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self::identity()
+    }
+}
+
+impl Transform {
+    pub const fn identity() -> Self {
+        Self {
+            m: IDENTITY,
+            inv: IDENTITY,
+        }
+    }
+
+    pub const fn translate(offset: &Vec3) -> Self {
+        let Vec3 { x, y, z } = *offset;
+        Self {
+            m: [
+                [1., 0., 0., x],
+                [0., 1., 0., y],
+                [0., 0., 1., z],
+                [0., 0., 0., 1.],
+            ],
+            inv: [
+                [1., 0., 0., -x],
+                [0., 1., 0., -y],
+                [0., 0., 1., -z],
+                [0., 0., 0., 1.],
+            ],
+        }
+    }
+
+    // Scales about the origin. Panics if any factor is zero, since the result
+    // couldn't be inverted.
+    pub fn scale(x: f64, y: f64, z: f64) -> Self {
+        assert!(
+            x != 0. && y != 0. && z != 0.,
+            "scale factors must be non-zero"
+        );
+        Self {
+            m: [
+                [x, 0., 0., 0.],
+                [0., y, 0., 0.],
+                [0., 0., z, 0.],
+                [0., 0., 0., 1.],
+            ],
+            inv: [
+                [1. / x, 0., 0., 0.],
+                [0., 1. / y, 0., 0.],
+                [0., 0., 1. / z, 0.],
+                [0., 0., 0., 1.],
+            ],
+        }
+    }
+
+    // Rotates counterclockwise about `axis` (when looking back along it) by
+    // `degrees`.
+    pub fn rotate(axis: &Vec3, degrees: f64) -> Self {
+        let a = axis.unit();
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        let t = 1. - cos;
+        let m = [
+            [
+                t * a.x * a.x + cos,
+                t * a.x * a.y - sin * a.z,
+                t * a.x * a.z + sin * a.y,
+                0.,
+            ],
+            [
+                t * a.x * a.y + sin * a.z,
+                t * a.y * a.y + cos,
+                t * a.y * a.z - sin * a.x,
+                0.,
+            ],
+            [
+                t * a.x * a.z - sin * a.y,
+                t * a.y * a.z + sin * a.x,
+                t * a.z * a.z + cos,
+                0.,
+            ],
+            [0., 0., 0., 1.],
+        ];
+        // Rotations are orthogonal, so the inverse is the transpose.
+        Self {
+            m,
+            inv: transpose(&m),
+        }
+    }
+
+    pub fn rotate_x(degrees: f64) -> Self {
+        Self::rotate(&Vec3::new(1., 0., 0.), degrees)
+    }
+
+    pub fn rotate_y(degrees: f64) -> Self {
+        Self::rotate(&Vec3::new(0., 1., 0.), degrees)
+    }
+
+    pub fn rotate_z(degrees: f64) -> Self {
+        Self::rotate(&Vec3::new(0., 0., 1.), degrees)
+    }
+
+    // This transform followed by `next`.
+    pub fn then(&self, next: &Self) -> Self {
+        Self {
+            m: mul(&next.m, &self.m),
+            inv: mul(&self.inv, &next.inv),
+        }
+    }
+
+    pub const fn inverse(&self) -> Self {
+        Self {
+            m: self.inv,
+            inv: self.m,
+        }
+    }
+
+    pub fn point(&self, p: &Point3) -> Point3 {
+        let m = &self.m;
+        let row = |r: &[f64; 4]| r[0].mul_add(p.x, r[1].mul_add(p.y, r[2].mul_add(p.z, r[3])));
+        Point3::new(row(&m[0]), row(&m[1]), row(&m[2]))
+    }
+
+    pub fn vector(&self, v: &Vec3) -> Vec3 {
+        linear(&self.m, v)
+    }
+
+    // Normals transform by the inverse transpose, to stay perpendicular to
+    // their surfaces. The result isn't normalized.
+    pub fn normal(&self, n: &Vec3) -> Vec3 {
+        linear(&transpose(&self.inv), n)
+    }
+
+    // Leaves the direction unnormalized, so that distances along the ray
+    // (i.e. `t`) are the same in both spaces.
+    pub fn ray(&self, ray: &Ray) -> Ray {
+        Ray {
+            origin: self.point(&ray.origin),
+            direction: self.vector(&ray.direction),
+            time: ray.time,
+        }
+    }
+
+    // The box around the transformed corners of `bbox`.
+    pub fn bounding_box(&self, bbox: &Aabb) -> Aabb {
+        let mut out = Aabb::empty();
+        for i in 0..8 {
+            let corner = Point3::new(
+                if i & 1 == 0 { bbox.min.x } else { bbox.max.x },
+                if i & 2 == 0 { bbox.min.y } else { bbox.max.y },
+                if i & 4 == 0 { bbox.min.z } else { bbox.max.z },
+            );
+            out = out.union_point(&self.point(&corner));
+        }
+        out
+    }
+
+    // The determinant of the linear (non-translating) part.
+    pub fn determinant(&self) -> f64 {
+        determinant3(&linear_part(&self.m))
+    }
+}
+
+// A transform that changes over time, going from `start` at `time0` to `end`
+// at `time1`, and holding still outside that interval.
+//
+// Blending the matrices directly would make a turning object shrink and skew
+// partway through, so each end is split into a translation, a rotation and a
+// scale (which may include shear), and those are interpolated separately.
+#[derive(Clone, Copy, Debug)]
+pub struct AnimatedTransform {
+    time0: f64,
+    time1: f64,
+    start: Decomposed,
+    end: Decomposed,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Decomposed {
+    translation: Vec3,
+    // A unit quaternion, as [x, y, z, w].
+    rotation: [f64; 4],
+    scale: Matrix3,
+}
+
+impl AnimatedTransform {
+    // Panics unless `time0` comes before `time1`.
+    pub fn new(start: &Transform, end: &Transform, time0: f64, time1: f64) -> Self {
+        assert!(time0 < time1, "time1 must come after time0");
+        Self {
+            time0,
+            time1,
+            start: decompose(start),
+            end: decompose(end),
+        }
+    }
+
+    pub fn at(&self, time: f64) -> Transform {
+        self.interpolate(self.fraction(time))
+    }
+
+    // A box around `bbox` as it moves between `time0` and `time1`.
+    pub fn bounding_box(&self, bbox: &Aabb, time0: f64, time1: f64) -> Aabb {
+        const STEPS: u32 = 64;
+        let (s0, s1) = (self.fraction(time0), self.fraction(time1));
+        let mut out = Aabb::empty();
+        for i in 0..=STEPS {
+            let s = s0 + (s1 - s0) * f64::from(i) / f64::from(STEPS);
+            out = out.union(&self.interpolate(s).bounding_box(bbox));
+        }
+
+        // Between steps, the corners swing along arcs, which can bulge out
+        // past the boxes at either end. Pad by (more than) the most that one
+        // step's worth of arc can stray from its chord.
+        let turn = rotation_angle(&self.start.rotation, &self.end.rotation) * (s1 - s0).abs();
+        let mut radius: f64 = 0.;
+        for i in 0..8 {
+            let corner = Vec3::new(
+                if i & 1 == 0 { bbox.min.x } else { bbox.max.x },
+                if i & 2 == 0 { bbox.min.y } else { bbox.max.y },
+                if i & 4 == 0 { bbox.min.z } else { bbox.max.z },
+            );
+            radius = radius
+                .max(apply3(&self.start.scale, &corner).mag())
+                .max(apply3(&self.end.scale, &corner).mag());
+        }
+        let pad = radius * (1. - (turn / f64::from(STEPS)).cos());
+        let pad = Vec3::new(pad, pad, pad);
+        Aabb::new(&(out.min - pad), &(out.max + pad))
+    }
+
+    // How far through the motion `time` is, from 0 to 1.
+    fn fraction(&self, time: f64) -> f64 {
+        ((time - self.time0) / (self.time1 - self.time0)).clamp(0., 1.)
+    }
+
+    fn interpolate(&self, s: f64) -> Transform {
+        let (a, b) = (&self.start, &self.end);
+        let mut scale = [[0.; 3]; 3];
+        for (i, row) in scale.iter_mut().enumerate() {
+            for (j, v) in row.iter_mut().enumerate() {
+                *v = s.mul_add(b.scale[i][j] - a.scale[i][j], a.scale[i][j]);
+            }
+        }
+        compose(
+            &(a.translation + s * (b.translation - a.translation)),
+            &slerp(&a.rotation, &b.rotation, s),
+            &scale,
+        )
+    }
+}
+
+// Splits `t` into translation × rotation × scale.
+fn decompose(t: &Transform) -> Decomposed {
+    let m = &t.m;
+    let a = linear_part(m);
+
+    // Polar decomposition: repeatedly averaging a matrix with its inverse
+    // transpose converges on the rotation nearest to it.
+    let mut r = a;
+    for _ in 0..100 {
+        let it = transpose3(&inverse3(&r));
+        let mut change: f64 = 0.;
+        for i in 0..3 {
+            for j in 0..3 {
+                let next = 0.5 * (r[i][j] + it[i][j]);
+                change = change.max((next - r[i][j]).abs());
+                r[i][j] = next;
+            }
+        }
+        if change < 1e-12 {
+            break;
+        }
+    }
+    // A mirroring transform gives an improper rotation. Flip it, and leave
+    // the mirroring to the scale.
+    if determinant3(&r) < 0. {
+        for v in r.iter_mut().flatten() {
+            *v = -*v;
+        }
+    }
+
+    Decomposed {
+        translation: Vec3::new(m[0][3], m[1][3], m[2][3]),
+        rotation: quaternion(&r),
+        scale: mul3(&transpose3(&r), &a),
+    }
+}
+
+fn compose(translation: &Vec3, rotation: &[f64; 4], scale: &Matrix3) -> Transform {
+    let r = rotation_matrix(rotation);
+    let l = mul3(&r, scale);
+    let l_inv = mul3(&inverse3(scale), &transpose3(&r));
+    let t_inv = -apply3(&l_inv, translation);
+
+    let mut m = IDENTITY;
+    let mut inv = IDENTITY;
+    for i in 0..3 {
+        m[i][..3].copy_from_slice(&l[i]);
+        inv[i][..3].copy_from_slice(&l_inv[i]);
+    }
+    for (i, (t, t_inv)) in [
+        (translation.x, t_inv.x),
+        (translation.y, t_inv.y),
+        (translation.z, t_inv.z),
+    ]
+    .iter()
+    .enumerate()
+    {
+        m[i][3] = *t;
+        inv[i][3] = *t_inv;
+    }
+    Transform { m, inv }
+}
+
+fn quaternion(r: &Matrix3) -> [f64; 4] {
+    let trace = r[0][0] + r[1][1] + r[2][2];
+    if trace > 0. {
+        let s = 0.5 / (trace + 1.).sqrt();
+        [
+            (r[2][1] - r[1][2]) * s,
+            (r[0][2] - r[2][0]) * s,
+            (r[1][0] - r[0][1]) * s,
+            0.25 / s,
+        ]
+    } else if r[0][0] > r[1][1] && r[0][0] > r[2][2] {
+        let s = 2. * (1. + r[0][0] - r[1][1] - r[2][2]).sqrt();
+        [
+            0.25 * s,
+            (r[0][1] + r[1][0]) / s,
+            (r[0][2] + r[2][0]) / s,
+            (r[2][1] - r[1][2]) / s,
+        ]
+    } else if r[1][1] > r[2][2] {
+        let s = 2. * (1. + r[1][1] - r[0][0] - r[2][2]).sqrt();
+        [
+            (r[0][1] + r[1][0]) / s,
+            0.25 * s,
+            (r[1][2] + r[2][1]) / s,
+            (r[0][2] - r[2][0]) / s,
+        ]
+    } else {
+        let s = 2. * (1. + r[2][2] - r[0][0] - r[1][1]).sqrt();
+        [
+            (r[0][2] + r[2][0]) / s,
+            (r[1][2] + r[2][1]) / s,
+            0.25 * s,
+            (r[1][0] - r[0][1]) / s,
+        ]
+    }
+}
+
+fn rotation_matrix(q: &[f64; 4]) -> Matrix3 {
+    let [x, y, z, w] = *q;
+    [
+        [
+            1. - 2. * (y * y + z * z),
+            2. * (x * y - z * w),
+            2. * (x * z + y * w),
+        ],
+        [
+            2. * (x * y + z * w),
+            1. - 2. * (x * x + z * z),
+            2. * (y * z - x * w),
+        ],
+        [
+            2. * (x * z - y * w),
+            2. * (y * z + x * w),
+            1. - 2. * (x * x + y * y),
+        ],
+    ]
+}
+
+// Spherical linear interpolation, the short way around.
+fn slerp(a: &[f64; 4], b: &[f64; 4], s: f64) -> [f64; 4] {
+    let mut cos = (0..4).map(|i| a[i] * b[i]).sum::<f64>();
+    let mut b = *b;
+    if cos < 0. {
+        cos = -cos;
+        for v in &mut b {
+            *v = -*v;
+        }
+    }
+
+    let (wa, wb) = if cos > 0.9995 {
+        // Nearly parallel: lerp, and normalize below.
+        (1. - s, s)
+    } else {
+        let theta = cos.acos();
+        let sin = theta.sin();
+        (((1. - s) * theta).sin() / sin, (s * theta).sin() / sin)
+    };
+    let mut q = [0.; 4];
+    for i in 0..4 {
+        q[i] = wa * a[i] + wb * b[i];
+    }
+    let norm = q.iter().map(|v| v * v).sum::<f64>().sqrt();
+    q.map(|v| v / norm)
+}
+
+// The angle of the rotation from `a` to `b`, in radians.
+fn rotation_angle(a: &[f64; 4], b: &[f64; 4]) -> f64 {
+    let cos = (0..4).map(|i| a[i] * b[i]).sum::<f64>().abs().min(1.);
+    2. * cos.acos()
+}
+
+fn linear_part(m: &Matrix) -> Matrix3 {
+    let mut out = [[0.; 3]; 3];
+    for (i, row) in out.iter_mut().enumerate() {
+        row.copy_from_slice(&m[i][..3]);
+    }
+    out
+}
+
+fn apply3(m: &Matrix3, v: &Vec3) -> Vec3 {
+    let row = |r: &[f64; 3]| r[0].mul_add(v.x, r[1].mul_add(v.y, r[2] * v.z));
+    Vec3::new(row(&m[0]), row(&m[1]), row(&m[2]))
+}
+
+fn mul3(a: &Matrix3, b: &Matrix3) -> Matrix3 {
+    let mut out = [[0.; 3]; 3];
+    for (i, row) in out.iter_mut().enumerate() {
+        for (j, v) in row.iter_mut().enumerate() {
+            *v = (0..3).map(|k| a[i][k] * b[k][j]).sum();
+        }
+    }
+    out
+}
+
+fn transpose3(m: &Matrix3) -> Matrix3 {
+    let mut out = [[0.; 3]; 3];
+    for (i, row) in out.iter_mut().enumerate() {
+        for (j, v) in row.iter_mut().enumerate() {
+            *v = m[j][i];
+        }
+    }
+    out
+}
+
+fn determinant3(m: &Matrix3) -> f64 {
+    m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+        - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+        + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+}
+
+// By cofactors. `m` must be invertible.
+fn inverse3(m: &Matrix3) -> Matrix3 {
+    let det = determinant3(m);
+    let mut out = [[0.; 3]; 3];
+    for (i, row) in out.iter_mut().enumerate() {
+        for (j, v) in row.iter_mut().enumerate() {
+            // The cofactor of m[j][i], for the adjugate's transpose.
+            let (r0, r1) = ((j + 1) % 3, (j + 2) % 3);
+            let (c0, c1) = ((i + 1) % 3, (i + 2) % 3);
+            *v = (m[r0][c0] * m[r1][c1] - m[r0][c1] * m[r1][c0]) / det;
+        }
+    }
+    out
+}
+
+fn linear(m: &Matrix, v: &Vec3) -> Vec3 {
+    let row = |r: &[f64; 4]| r[0].mul_add(v.x, r[1].mul_add(v.y, r[2] * v.z));
+    Vec3::new(row(&m[0]), row(&m[1]), row(&m[2]))
+}
+
+fn mul(a: &Matrix, b: &Matrix) -> Matrix {
+    let mut out = [[0.; 4]; 4];
+    for (i, row) in out.iter_mut().enumerate() {
+        for (j, v) in row.iter_mut().enumerate() {
+            *v = (0..4).map(|k| a[i][k] * b[k][j]).sum();
+        }
+    }
+    out
+}
+
+fn transpose(m: &Matrix) -> Matrix {
+    let mut out = [[0.; 4]; 4];
+    for (i, row) in out.iter_mut().enumerate() {
+        for (j, v) in row.iter_mut().enumerate() {
+            *v = m[j][i];
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AnimatedTransform, Transform};
+    use crate::{point3::Point3, vec3::Vec3};
+
+    fn assert_near(a: Point3, b: Point3) {
+        assert!((a - b).mag() < 1e-9, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn composes_in_order() {
+        let t = Transform::scale(2., 2., 2.)
+            .then(&Transform::rotate_y(90.))
+            .then(&Transform::translate(&Vec3::new(0., 1., 0.)));
+        let p = Point3::new(1., 0., 0.);
+        // Scaled to (2, 0, 0), rotated to (0, 0, -2), then moved up.
+        assert_near(t.point(&p), Point3::new(0., 1., -2.));
+        assert_near(t.inverse().point(&t.point(&p)), p);
+        assert!((t.determinant() - 8.).abs() < 1e-9);
+    }
+
+    #[test]
+    fn normals_stay_perpendicular() {
+        let t = Transform::scale(1., 4., 1.).then(&Transform::rotate_z(30.));
+        // A plane containing the x and z axes, and one of its tangents.
+        let tangent = Vec3::new(1., 0., 1.);
+        let normal = Vec3::new(0., 1., 0.);
+        assert!(t.vector(&tangent).dot(t.normal(&normal)).abs() < 1e-9);
+
+        let skew = Vec3::new(1., 1., 0.);
+        let normal = Vec3::new(1., -1., 0.);
+        assert!(t.vector(&skew).dot(t.normal(&normal)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn animation_turns_without_shrinking() {
+        let start = Transform::scale(2., 1., 1.);
+        let end = Transform::scale(2., 1., 1.)
+            .then(&Transform::rotate_y(90.))
+            .then(&Transform::translate(&Vec3::new(0., 4., 0.)));
+        let motion = AnimatedTransform::new(&start, &end, 1., 3.);
+        let p = Point3::new(1., 0., 0.);
+
+        assert_near(motion.at(0.).point(&p), start.point(&p));
+        assert_near(motion.at(3.).point(&p), end.point(&p));
+        // Halfway, it's turned 45 degrees and kept its length, rather than
+        // cutting the corner.
+        let half = Transform::scale(2., 1., 1.)
+            .then(&Transform::rotate_y(45.))
+            .then(&Transform::translate(&Vec3::new(0., 2., 0.)));
+        let mid = motion.at(2.);
+        assert_near(mid.point(&p), half.point(&p));
+        assert_near(mid.inverse().point(&mid.point(&p)), p);
+    }
+}
@@ -0,0 +1,383 @@
+use std::sync::Arc;
+
+use rand::Rng;
+
+use crate::{
+    aabb::Aabb,
+    bvh::Tree,
+    hit::{Hittable, Record},
+    material::Material,
+    point3::Point3,
+    ray::Ray,
+    sampler::Sampler,
+    vec3::Vec3,
+};
+
+// Vertex and index buffers shared by every triangle in a mesh. Per-vertex
+// normals and UVs are optional; leave them empty, or give one per position.
+pub struct TriangleMesh {
+    pub positions: Vec<Point3>,
+    pub normals: Vec<Vec3>,
+    pub uvs: Vec<(f64, f64)>,
+    pub indices: Vec<[usize; 3]>,
+    pub mat: Arc<dyn Material>,
+}
+
+impl TriangleMesh {
+    pub fn new(positions: Vec<Point3>, indices: Vec<[usize; 3]>, mat: Arc<dyn Material>) -> Self {
+        Self {
+            positions,
+            normals: Vec::new(),
+            uvs: Vec::new(),
+            indices,
+            mat,
+        }
+    }
+
+    pub fn vertices(&self, index: usize) -> [Point3; 3] {
+        let [i0, i1, i2] = self.indices[index];
+        let p = &self.positions;
+        [p[i0], p[i1], p[i2]]
+    }
+
+    pub fn area(&self, index: usize) -> f64 {
+        let [p0, p1, p2] = self.vertices(index);
+        0.5 * (p1 - p0).cross(p2 - p0).mag()
+    }
+
+    // Möller–Trumbore, against face `index`.
+    fn intersect(&self, index: usize, ray: &Ray, t_min: f64, t_max: f64) -> Option<Record> {
+        let [p0, p1, p2] = self.vertices(index);
+        let e1 = p1 - p0;
+        let e2 = p2 - p0;
+
+        let pvec = ray.direction.cross(e2);
+        let det = e1.dot(pvec);
+        if det.abs() < 1e-12 {
+            return None; // Parallel to the triangle's plane.
+        }
+        let inv_det = 1. / det;
+
+        let tvec = ray.origin - p0;
+        let b1 = tvec.dot(pvec) * inv_det;
+        if !(0. ..=1.).contains(&b1) {
+            return None;
+        }
+
+        let qvec = tvec.cross(e1);
+        let b2 = ray.direction.dot(qvec) * inv_det;
+        if b2 < 0. || b1 + b2 > 1. {
+            return None;
+        }
+
+        let t = e2.dot(qvec) * inv_det;
+        if t <= t_min || t_max <= t {
+            return None;
+        }
+        let b0 = 1. - b1 - b2;
+
+        let mut record = Record::new();
+        record.t = t;
+        record.p = ray.at(t);
+        record.set_face_normal(ray, &e1.cross(e2).unit());
+        record.mat = Arc::clone(&self.mat);
+
+        let [i0, i1, i2] = self.indices[index];
+        if !self.normals.is_empty() {
+            // Shade with the interpolated normal, but keep it on the side the
+            // geometric normal picked.
+            let n = &self.normals;
+            let shading = (b0 * n[i0] + b1 * n[i1] + b2 * n[i2]).unit();
+            record.normal = if shading.dot(record.normal) < 0. {
+                -shading
+            } else {
+                shading
+            };
+        }
+        if self.uvs.is_empty() {
+            record.u = b1;
+            record.v = b2;
+        } else {
+            let uv = &self.uvs;
+            record.u = b2.mul_add(uv[i2].0, b0.mul_add(uv[i0].0, b1 * uv[i1].0));
+            record.v = b2.mul_add(uv[i2].1, b0.mul_add(uv[i0].1, b1 * uv[i1].1));
+        }
+
+        Some(record)
+    }
+
+    fn face_bounding_box(&self, index: usize) -> Aabb {
+        let [p0, p1, p2] = self.vertices(index);
+        Aabb::new(&p0, &p0).union_point(&p1).union_point(&p2)
+    }
+
+    // A point picked uniformly by area on face `index`.
+    fn sample_point(&self, index: usize, sampler: &mut Sampler) -> Point3 {
+        let [p0, p1, p2] = self.vertices(index);
+        let s = sampler.gen::<f64>().sqrt();
+        let b2 = s * sampler.gen::<f64>();
+        let b1 = s - b2;
+        p0 + b1 * (p1 - p0) + b2 * (p2 - p0)
+    }
+
+    // Converts a density of 1 / `area` over face `index` to one per unit solid
+    // angle around `ray.origin`. It's zero if the ray misses the face.
+    fn solid_angle_pdf(&self, index: usize, ray: &Ray, area: f64) -> f64 {
+        let hit = match self.intersect(index, ray, 0.001, f64::INFINITY) {
+            Some(hit) => hit,
+            None => return 0.,
+        };
+        let [p0, p1, p2] = self.vertices(index);
+        let n = (p1 - p0).cross(p2 - p0).unit();
+        let dist_squared = hit.t * hit.t * ray.direction.mag_squared();
+        let cosine = n.dot(ray.direction).abs() / ray.direction.mag();
+        dist_squared / (cosine * area)
+    }
+}
+
+// A whole mesh as a single hittable, with its own BVH over the faces. Faces
+// are only ever referred to by index, so there's no per-face allocation.
+pub struct Mesh {
+    mesh: TriangleMesh,
+    faces: Tree,
+    // Running totals of the faces' areas, for picking one to sample.
+    areas: Vec<f64>,
+}
+
+impl Mesh {
+    pub fn new(mesh: TriangleMesh) -> Self {
+        let bboxes: Vec<_> = (0..mesh.indices.len())
+            .map(|i| mesh.face_bounding_box(i))
+            .collect();
+        let mut total = 0.;
+        let areas = (0..mesh.indices.len())
+            .map(|i| {
+                total += mesh.area(i);
+                total
+            })
+            .collect();
+        Self {
+            faces: Tree::new(&bboxes),
+            areas,
+            mesh,
+        }
+    }
+
+    fn total_area(&self) -> f64 {
+        self.areas.last().copied().unwrap_or(0.)
+    }
+}
+
+impl Hittable for Mesh {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64, _sampler: &mut Sampler) -> Option<Record> {
+        let mut hit_record = None;
+        self.faces.traverse(ray, t_min, t_max, |i, closest_so_far| {
+            match self.mesh.intersect(i, ray, t_min, closest_so_far) {
+                Some(rec) => {
+                    let t = rec.t;
+                    hit_record = Some(rec);
+                    t
+                }
+                None => closest_so_far,
+            }
+        });
+        hit_record
+    }
+
+    fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<Aabb> {
+        self.faces.bounding_box()
+    }
+
+    fn is_emissive(&self) -> bool {
+        self.mesh.mat.is_emissive() && self.total_area() > 0.
+    }
+
+    // Picks a face in proportion to its area, then a point on it.
+    fn sample_direction(&self, origin: &Point3, sampler: &mut Sampler) -> Vec3 {
+        let a = sampler.gen::<f64>() * self.total_area();
+        let face = self
+            .areas
+            .partition_point(|&total| total <= a)
+            .min(self.areas.len() - 1);
+        self.mesh.sample_point(face, sampler) - *origin
+    }
+
+    // The sum over every face the direction passes through, since any of them
+    // could have been sampled.
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f64 {
+        let ray = Ray {
+            origin: *origin,
+            direction: *direction,
+            time: 0.,
+        };
+        let total = self.total_area();
+        let mut pdf = 0.;
+        self.faces.traverse(&ray, 0.001, f64::INFINITY, |i, t_max| {
+            pdf += self.mesh.solid_angle_pdf(i, &ray, total);
+            t_max
+        });
+        pdf
+    }
+}
+
+// A standalone triangle, i.e. a mesh with a single face.
+pub struct Triangle {
+    mesh: TriangleMesh,
+}
+
+impl Triangle {
+    pub fn new(p0: &Point3, p1: &Point3, p2: &Point3, mat: Arc<dyn Material>) -> Self {
+        Self {
+            mesh: TriangleMesh::new(vec![*p0, *p1, *p2], vec![[0, 1, 2]], mat),
+        }
+    }
+
+    pub fn vertices(&self) -> [Point3; 3] {
+        self.mesh.vertices(0)
+    }
+
+    pub fn area(&self) -> f64 {
+        self.mesh.area(0)
+    }
+}
+
+impl Hittable for Triangle {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64, _sampler: &mut Sampler) -> Option<Record> {
+        self.mesh.intersect(0, ray, t_min, t_max)
+    }
+
+    fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<Aabb> {
+        Some(self.mesh.face_bounding_box(0))
+    }
+
+    fn is_emissive(&self) -> bool {
+        self.mesh.mat.is_emissive()
+    }
+
+    // Samples a point uniformly by area.
+    fn sample_direction(&self, origin: &Point3, sampler: &mut Sampler) -> Vec3 {
+        self.mesh.sample_point(0, sampler) - *origin
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f64 {
+        let ray = Ray {
+            origin: *origin,
+            direction: *direction,
+            time: 0.,
+        };
+        self.mesh.solid_angle_pdf(0, &ray, self.area())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::{Mesh, Triangle, TriangleMesh};
+    use crate::{
+        hit::Hittable, material::Blank, point3::Point3, ray::Ray, sampler::Sampler, vec3::Vec3,
+    };
+
+    #[test]
+    fn hit_reports_barycentrics() {
+        let tri = Triangle::new(
+            &Point3::new(0., 0., 0.),
+            &Point3::new(1., 0., 0.),
+            &Point3::new(0., 1., 0.),
+            Arc::new(Blank::new()),
+        );
+        let ray = Ray {
+            origin: Point3::new(0.25, 0.5, 1.),
+            direction: Vec3::new(0., 0., -1.),
+            time: 0.,
+        };
+        let rec = tri
+            .mesh
+            .intersect(0, &ray, 0.001, f64::INFINITY)
+            .expect("should hit");
+        assert!((rec.t - 1.).abs() < 1e-12);
+        assert!((rec.u - 0.25).abs() < 1e-12);
+        assert!((rec.v - 0.5).abs() < 1e-12);
+        assert!(rec.front_face);
+        assert_eq!(rec.normal, Vec3::new(0., 0., 1.));
+
+        let miss = Ray {
+            origin: Point3::new(0.75, 0.5, 1.),
+            direction: Vec3::new(0., 0., -1.),
+            time: 0.,
+        };
+        assert!(tri.mesh.intersect(0, &miss, 0.001, f64::INFINITY).is_none());
+    }
+
+    #[test]
+    fn mesh_finds_the_nearest_face() {
+        // A wavy 8x8 grid, checked against testing every face in turn.
+        let n: u32 = 8;
+        let mut positions = Vec::new();
+        for i in 0..=n {
+            for j in 0..=n {
+                let (x, z) = (f64::from(i), f64::from(j));
+                positions.push(Point3::new(x, (0.7 * x).sin() * z.cos(), z));
+            }
+        }
+        let mut indices = Vec::new();
+        let side = n as usize + 1;
+        let corner = |i: usize, j: usize| i * side + j;
+        for i in 0..side - 1 {
+            for j in 0..side - 1 {
+                indices.push([corner(i, j), corner(i + 1, j), corner(i + 1, j + 1)]);
+                indices.push([corner(i, j), corner(i + 1, j + 1), corner(i, j + 1)]);
+            }
+        }
+        let mesh = Mesh::new(TriangleMesh::new(
+            positions,
+            indices,
+            Arc::new(Blank::new()),
+        ));
+        let mut sampler = Sampler::new(0);
+
+        for k in 0..200 {
+            let a = f64::from(k) * 0.1;
+            let ray = Ray {
+                origin: Point3::new(4. + 6. * a.cos(), 3., 4. + 6. * a.sin()),
+                direction: Vec3::new(-a.cos() + 0.1 * a.sin(), -0.8, -a.sin()),
+                time: 0.,
+            };
+            let want = (0..mesh.mesh.indices.len())
+                .filter_map(|i| mesh.mesh.intersect(i, &ray, 0.001, f64::INFINITY))
+                .map(|r| r.t)
+                .fold(None, |m: Option<f64>, t| Some(m.map_or(t, |m| m.min(t))));
+            let got = mesh
+                .hit(&ray, 0.001, f64::INFINITY, &mut sampler)
+                .map(|r| r.t);
+            assert_eq!(want, got);
+        }
+    }
+
+    #[test]
+    fn shading_normals_face_the_ray() {
+        // Counter-clockwise seen from +z, but with vertex normals that say the
+        // triangle faces -z (and lean a little toward +x).
+        let mut mesh = TriangleMesh::new(
+            vec![
+                Point3::new(0., 0., 0.),
+                Point3::new(1., 0., 0.),
+                Point3::new(0., 1., 0.),
+            ],
+            vec![[0, 1, 2]],
+            Arc::new(Blank::new()),
+        );
+        mesh.normals = vec![Vec3::new(0.6, 0., -0.8); 3];
+
+        for z in &[1., -1.] {
+            let ray = Ray {
+                origin: Point3::new(0.25, 0.25, *z),
+                direction: Vec3::new(0., 0., -z),
+                time: 0.,
+            };
+            let rec = mesh.intersect(0, &ray, 0.001, f64::INFINITY).unwrap();
+            assert!(rec.normal.dot(ray.direction) < 0.);
+            assert!((rec.normal.x.abs() - 0.6).abs() < 1e-12);
+        }
+    }
+}
@@ -2,7 +2,7 @@ use std::ops;
 
 use rand::Rng;
 
-use crate::point3::Point3;
+use crate::{point3::Point3, sampler::Sampler};
 
 #[derive(Clone, Copy, Debug, Default, PartialEq)]
 pub struct Vec3 {
@@ -21,8 +21,7 @@ impl Vec3 {
     }
 
     // Exclusive, i.e. cannot return <1., 1., 1.>.
-    pub fn rand_in_unit_sphere() -> Self {
-        let mut rng = rand::thread_rng();
+    pub fn rand_in_unit_sphere(rng: &mut Sampler) -> Self {
         let mut v = Self::default();
         loop {
             v.x = rng.gen_range(-1.0..1.0);
@@ -35,8 +34,8 @@ impl Vec3 {
     }
 
     // Exclusive, i.e. cannot return <1., 1., 1.>.
-    pub fn rand_in_unit_hemisphere(normal: &Self) -> Self {
-        let v = Self::rand_in_unit_sphere();
+    pub fn rand_in_unit_hemisphere(normal: &Self, rng: &mut Sampler) -> Self {
+        let v = Self::rand_in_unit_sphere(rng);
         if v.dot(*normal) > 0. {
             v
         } else {
@@ -44,8 +43,7 @@ impl Vec3 {
         }
     }
 
-    pub fn rand_in_unit_disk() -> Self {
-        let mut rng = rand::thread_rng();
+    pub fn rand_in_unit_disk(rng: &mut Sampler) -> Self {
         let mut v = Self::zero();
         loop {
             v.x = rng.gen_range(-1.0..1.0);
@@ -56,8 +54,8 @@ impl Vec3 {
         }
     }
 
-    pub fn rand_unit() -> Self {
-        Self::rand_in_unit_sphere().unit()
+    pub fn rand_unit(rng: &mut Sampler) -> Self {
+        Self::rand_in_unit_sphere(rng).unit()
     }
 
     pub fn is_near_zero(&self) -> bool {
@@ -127,6 +125,19 @@ impl ops::Div<f64> for Vec3 {
     }
 }
 
+impl ops::Index<usize> for Vec3 {
+    type Output = f64;
+
+    fn index(&self, axis: usize) -> &Self::Output {
+        match axis {
+            0 => &self.x,
+            1 => &self.y,
+            2 => &self.z,
+            _ => panic!("axis out of range: {}", axis),
+        }
+    }
+}
+
 impl ops::Mul<f64> for Vec3 {
     type Output = Self;
 
@@ -1,37 +1,33 @@
-use std::path::Path;
+mod opts;
+
+use std::{
+    ffi::OsStr,
+    fs::{File, OpenOptions},
+    io::{self, BufWriter, Write},
+    path::Path,
+    process,
+    sync::Arc,
+    thread,
+};
 
 use clap::Clap;
 use image::ImageBuffer;
-use rand::Rng;
-
 use rtlib::{
-    camera::Camera,
-    color::Color,
-    hit::{Hittable, HittableObjects},
-    point3::Point3,
-    ray::Ray,
-    scene,
-    vec3::Vec3,
+    bvh::Bvh,
+    environment::Gradient,
+    framebuffer::Framebuffer,
+    hdr::{self, Precision},
+    integrator::Context,
+    render::Renderer,
+    sampler::Sampler,
+    scene::{self, Scene},
+    scene_file, tonemap,
 };
 
-// Image
-const IMAGE_WIDTH: u32 = 1200;
-const IMAGE_HEIGHT: u32 = 800;
-const SAMPLES_PER_PIXEL: u32 = 500;
-const MAX_DEPTH: u32 = 50;
-
-#[derive(Clap)]
-#[clap(
-    version = "0.1.0",
-    author = "Calvin Figuereo-Supraner <mail@calvin.page>"
-)]
-struct Opts {
-    #[clap(short, long)]
-    filename: String,
-}
+use opts::Opts;
 
 fn main() {
-    let opts: Opts = Opts::parse();
+    let opts = Opts::parse();
 
     // There's a silly race condition here, where another process might create
     // the file _after_ the no-exist check, but _before_ the write attempt. This
@@ -43,73 +39,100 @@ fn main() {
         panic!("file already exists")
     }
 
-    let mut imgbuf = ImageBuffer::new(IMAGE_WIDTH, IMAGE_HEIGHT);
-
-    // Set up the world.
-    let world = scene::random();
-
-    // Set up camera.
-    let look_from = Point3::new(13., 2., 3.);
-    let look_at = Point3::new(0., 0., 0.);
-    let v_up = Vec3::new(0., 1., 0.);
-    let dist_to_focus = 10.;
-    let aperture = 0.1;
-    let aspect_ratio = f64::from(IMAGE_WIDTH) / f64::from(IMAGE_HEIGHT);
-    let vfov: f64 = 20.; // degrees
-
-    let camera = Camera::new(
-        &look_from,
-        &look_at,
-        &v_up,
-        vfov,
-        aspect_ratio,
-        aperture,
-        dist_to_focus,
+    let mut scene = match &opts.scene {
+        Some(path) => scene_file::load(path).unwrap_or_else(|e| {
+            eprintln!("error: {}", e);
+            process::exit(1);
+        }),
+        None => Scene {
+            camera: Default::default(),
+            render: Default::default(),
+            world: scene::random(&mut Sampler::new(opts.seed)),
+            environment: Arc::new(Gradient::default()),
+            lights: Vec::new(),
+        },
+    };
+    if let Err(e) = opts.apply(&mut scene) {
+        eprintln!("error: {}", e);
+        process::exit(1);
+    }
+    let settings = &scene.render;
+
+    let camera = scene.camera.build(settings.aspect_ratio());
+    let lights = scene.world.lights();
+    let world = Bvh::new(
+        scene.world,
+        scene.camera.shutter_open,
+        scene.camera.shutter_close,
     );
 
-    let mut rng = rand::thread_rng();
-
-    for (x, y, pixel) in imgbuf.enumerate_pixels_mut() {
-        // `enumerate_pixels_mut` places the origin at the top left corner, but
-        // the author places the origin at the bottom left corner. Luckily, we
-        // can adjust on the fly by altering the y-coordinate.
-        let yy = IMAGE_HEIGHT - y - 1;
+    let threads = opts
+        .threads
+        .unwrap_or_else(|| thread::available_parallelism().map_or(1, std::num::NonZeroUsize::get));
+    let mut renderer = Renderer::new(
+        settings.width,
+        settings.height,
+        settings.samples_per_pixel,
+        threads,
+    );
+    renderer.seed = opts.seed;
+    let integrator = opts.integrator(settings.max_depth);
+    let context = Context {
+        world: &world,
+        lights: &lights,
+        delta_lights: &scene.lights,
+        environment: scene.environment.as_ref(),
+    };
+    let fb = renderer.render_with_progress(
+        &camera,
+        |ray, sampler| integrator.radiance(ray, &context, sampler),
+        |left| println!("Scanlines remaining: {}", left),
+    );
 
-        if x == 0 {
-            println!("Scanlines remaining: {}", yy);
-        }
+    save(&fb, &opts);
+}
 
-        let mut color = Color::default();
-        for _ in 0..SAMPLES_PER_PIXEL {
-            let u = (f64::from(x) + rng.gen::<f64>()) / f64::from(IMAGE_WIDTH - 1);
-            let v = (f64::from(yy) + rng.gen::<f64>()) / f64::from(IMAGE_HEIGHT - 1);
-            let ray = camera.get_ray(u, v);
-            color += ray_color(&ray, &world, MAX_DEPTH);
+// HDR formats get the linear framebuffer as-is. Anything else is tone mapped
+// and left to `image`, which deduces the format from the file extension.
+fn save(fb: &Framebuffer, opts: &Opts) {
+    let path = Path::new(&opts.filename);
+    let extension = path
+        .extension()
+        .and_then(OsStr::to_str)
+        .map(str::to_ascii_lowercase);
+    let precision = if opts.exr_float {
+        Precision::Float
+    } else {
+        Precision::Half
+    };
+
+    match extension.as_deref() {
+        Some("exr") => write_new(path, |w| hdr::write_exr(w, fb, precision)),
+        Some("pfm") => write_new(path, |w| hdr::write_pfm(w, fb)),
+        Some("hdr") => write_new(path, |w| hdr::write_radiance(w, fb)),
+        _ => {
+            let rgb = tonemap::to_rgb8(fb, opts.tone_mapper().as_ref(), opts.exposure);
+            let mut imgbuf = ImageBuffer::new(fb.width, fb.height);
+            for (pixel, rgb) in imgbuf.pixels_mut().zip(rgb) {
+                *pixel = image::Rgb(rgb);
+            }
+            imgbuf.save(path).expect("failed to write to file");
         }
-
-        *pixel = image::Rgb(color.to_rgb(SAMPLES_PER_PIXEL))
     }
-
-    imgbuf
-        .save(&opts.filename)
-        .expect("failed to write to file")
 }
 
-fn ray_color(ray: &Ray, world: &HittableObjects, depth: u32) -> Color {
-    if depth == 0 {
-        return Color::new(0., 0., 0.);
-    }
-
-    world.hit(ray, 0.001, std::f64::INFINITY).map_or_else(
-        || {
-            let t = 0.5 * (ray.direction.unit().y + 1.);
-            (1. - t) * Color::new(1., 1., 1.) + t * Color::new(0.5, 0.7, 1.)
-        },
-        |h_rec| {
-            h_rec.mat.scatter(ray, &h_rec).map_or_else(
-                || Color::new(0., 0., 0.),
-                |s_rec| s_rec.attenuation * ray_color(&s_rec.scattered, world, depth - 1),
-            )
-        },
-    )
+// Unlike `ImageBuffer::save`, this refuses to overwrite an existing file.
+fn write_new<F>(path: &Path, write: F)
+where
+    F: FnOnce(&mut BufWriter<File>) -> io::Result<()>,
+{
+    let file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(path)
+        .expect("failed to create file");
+    let mut w = BufWriter::new(file);
+    write(&mut w)
+        .and_then(|_| w.flush())
+        .expect("failed to write to file");
 }
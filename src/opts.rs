@@ -0,0 +1,272 @@
+use std::path::PathBuf;
+
+use clap::Clap;
+
+use rtlib::{
+    integrator::{self, Integrator},
+    point3::Point3,
+    scene::Scene,
+    tonemap::{self, ToneMapper},
+};
+
+// Render and camera settings are all optional. When given, they override the
+// scene's own (or, without `--scene`, the random scene's defaults).
+#[derive(Clap)]
+#[clap(
+    version = "0.1.0",
+    author = "Calvin Figuereo-Supraner <mail@calvin.page>"
+)]
+pub struct Opts {
+    #[clap(short, long)]
+    pub filename: String,
+    /// Number of render threads. Defaults to the number of available cores.
+    #[clap(short, long, parse(try_from_str = parse_positive), allow_hyphen_values = true)]
+    pub threads: Option<usize>,
+    /// Scene description (TOML). Defaults to the built-in random scene.
+    #[clap(short, long, parse(from_os_str))]
+    pub scene: Option<PathBuf>,
+    /// Seed for every random choice. The same seed always gives the same image.
+    #[clap(long, default_value = "0")]
+    pub seed: u64,
+    /// Write 32-bit float channels to .exr files, instead of half floats.
+    #[clap(long)]
+    pub exr_float: bool,
+    /// Rendering algorithm: path (full global illumination), direct (a single
+    /// diffuse bounce), ao (ambient occlusion), or a debug view of the
+    /// surfaces' normals, depth, albedo or uv coordinates.
+    #[clap(long, default_value = "path", parse(try_from_str = parse_integrator))]
+    pub integrator: String,
+    /// How far ambient occlusion looks for occluders. Defaults to forever.
+    #[clap(long, parse(try_from_str = parse_positive_f64), allow_hyphen_values = true)]
+    pub ao_distance: Option<f64>,
+    /// Tone mapping curve for 8-bit images: clamp, reinhard,
+    /// reinhard-extended, hable or aces.
+    #[clap(long, default_value = "clamp", parse(try_from_str = parse_tonemap))]
+    pub tonemap: String,
+    /// Exposure adjustment in stops (EV), applied before tone mapping.
+    #[clap(long, default_value = "0", parse(try_from_str = parse_f64), allow_hyphen_values = true)]
+    pub exposure: f64,
+    /// White point for reinhard-extended and hable, in exposed radiance.
+    #[clap(long, parse(try_from_str = parse_positive_f64), allow_hyphen_values = true)]
+    pub white_point: Option<f64>,
+
+    /// Image width in pixels. Defaults to the scene file's, or 1200 without
+    /// --scene
+    #[clap(long, parse(try_from_str = parse_dimension), allow_hyphen_values = true)]
+    pub width: Option<u32>,
+    /// Image height in pixels. Defaults to the scene file's, or 800 without
+    /// --scene
+    #[clap(long, parse(try_from_str = parse_dimension), allow_hyphen_values = true)]
+    pub height: Option<u32>,
+    /// Samples per pixel. Defaults to the scene file's, or 500 without --scene
+    #[clap(long, parse(try_from_str = parse_positive), allow_hyphen_values = true)]
+    pub samples: Option<u32>,
+    /// Maximum number of bounces per path. Defaults to the scene file's, or 50
+    /// without --scene
+    #[clap(long, parse(try_from_str = parse_positive), allow_hyphen_values = true)]
+    pub max_depth: Option<u32>,
+
+    /// Vertical field of view, in degrees. Defaults to the scene file's, or 20
+    /// without --scene
+    #[clap(long, parse(try_from_str = parse_vfov), allow_hyphen_values = true)]
+    pub vfov: Option<f64>,
+    /// Lens aperture; 0 disables defocus blur. Defaults to the scene file's,
+    /// or 0.1 without --scene
+    #[clap(long, parse(try_from_str = parse_non_negative), allow_hyphen_values = true)]
+    pub aperture: Option<f64>,
+    /// Distance to the plane of perfect focus. Defaults to the scene file's, or
+    /// 10 without --scene
+    #[clap(long, parse(try_from_str = parse_positive_f64), allow_hyphen_values = true)]
+    pub focus_dist: Option<f64>,
+    /// Camera position, as x,y,z. Defaults to the scene file's, or 13,2,3
+    /// without --scene
+    #[clap(long, parse(try_from_str = parse_point), allow_hyphen_values = true)]
+    pub look_from: Option<Point3>,
+    /// Point the camera looks at, as x,y,z. Defaults to the scene file's, or
+    /// 0,0,0 without --scene
+    #[clap(long, parse(try_from_str = parse_point), allow_hyphen_values = true)]
+    pub look_at: Option<Point3>,
+}
+
+impl Opts {
+    // Applies any settings given on the command line to `scene`.
+    pub fn apply(&self, scene: &mut Scene) -> Result<(), String> {
+        let render = &mut scene.render;
+        render.width = self.width.unwrap_or(render.width);
+        render.height = self.height.unwrap_or(render.height);
+        render.samples_per_pixel = self.samples.unwrap_or(render.samples_per_pixel);
+        render.max_depth = self.max_depth.unwrap_or(render.max_depth);
+
+        let camera = &mut scene.camera;
+        camera.vfov = self.vfov.unwrap_or(camera.vfov);
+        camera.aperture = self.aperture.unwrap_or(camera.aperture);
+        camera.focus_dist = self.focus_dist.unwrap_or(camera.focus_dist);
+        camera.look_from = self.look_from.unwrap_or(camera.look_from);
+        camera.look_at = self.look_at.unwrap_or(camera.look_at);
+
+        if camera.look_from == camera.look_at {
+            return Err("the camera's look-from and look-at points must differ".to_string());
+        }
+        let view = camera.look_at - camera.look_from;
+        if camera.v_up.cross(view).mag() <= 1e-9 * camera.v_up.mag() * view.mag() {
+            return Err("the camera can't look straight along its up direction".to_string());
+        }
+        Ok(())
+    }
+
+    pub fn integrator(&self, max_depth: u32) -> Box<dyn Integrator> {
+        let ao_distance = self.ao_distance.unwrap_or(f64::INFINITY);
+        integrator::by_name(&self.integrator, max_depth, ao_distance)
+            .expect("checked by parse_integrator")
+    }
+
+    pub fn tone_mapper(&self) -> Box<dyn ToneMapper> {
+        tonemap::by_name(&self.tonemap, self.white_point).expect("checked by parse_tonemap")
+    }
+}
+
+fn parse_positive<T>(s: &str) -> Result<T, String>
+where
+    T: std::str::FromStr + Default + PartialEq,
+{
+    if s.starts_with('-') {
+        return Err("must be positive".to_string());
+    }
+    let v: T = s
+        .parse()
+        .map_err(|_| format!("{:?} isn't a whole number", s))?;
+    if v == T::default() {
+        return Err("must be positive".to_string());
+    }
+    Ok(v)
+}
+
+// The camera maps pixels onto [0, 1] by dividing by `width - 1`, so images
+// need at least two pixels along each axis.
+fn parse_dimension(s: &str) -> Result<u32, String> {
+    let v: u32 = parse_positive(s)?;
+    if v < 2 {
+        return Err("must be at least 2".to_string());
+    }
+    Ok(v)
+}
+
+fn parse_f64(s: &str) -> Result<f64, String> {
+    let v: f64 = s.parse().map_err(|_| format!("{:?} isn't a number", s))?;
+    if !v.is_finite() {
+        return Err("must be finite".to_string());
+    }
+    Ok(v)
+}
+
+fn parse_positive_f64(s: &str) -> Result<f64, String> {
+    let v = parse_f64(s)?;
+    if v <= 0. {
+        return Err("must be positive".to_string());
+    }
+    Ok(v)
+}
+
+fn parse_non_negative(s: &str) -> Result<f64, String> {
+    let v = parse_f64(s)?;
+    if v < 0. {
+        return Err("must not be negative".to_string());
+    }
+    Ok(v)
+}
+
+fn parse_vfov(s: &str) -> Result<f64, String> {
+    let v = parse_f64(s)?;
+    if !(0. < v && v < 180.) {
+        return Err("must be between 0 and 180 degrees".to_string());
+    }
+    Ok(v)
+}
+
+fn parse_tonemap(s: &str) -> Result<String, String> {
+    if !tonemap::NAMES.contains(&s) {
+        return Err(format!("must be one of {}", tonemap::NAMES.join(", ")));
+    }
+    Ok(s.to_string())
+}
+
+fn parse_integrator(s: &str) -> Result<String, String> {
+    if !integrator::NAMES.contains(&s) {
+        return Err(format!("must be one of {}", integrator::NAMES.join(", ")));
+    }
+    Ok(s.to_string())
+}
+
+fn parse_point(s: &str) -> Result<Point3, String> {
+    let coords = s
+        .split(',')
+        .map(|c| parse_f64(c.trim()))
+        .collect::<Result<Vec<_>, _>>()?;
+    match coords[..] {
+        [x, y, z] => Ok(Point3::new(x, y, z)),
+        _ => Err(format!("{:?} isn't of the form x,y,z", s)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use clap::Clap;
+
+    use rtlib::{environment::Gradient, hit::HittableObjects, point3::Point3, scene::Scene};
+
+    use super::Opts;
+
+    fn parse(args: &[&str]) -> Result<Opts, clap::Error> {
+        Opts::try_parse_from(["raytracer", "-f", "out.png"].iter().chain(args))
+    }
+
+    fn empty_scene() -> Scene {
+        Scene {
+            camera: Default::default(),
+            render: Default::default(),
+            world: HittableObjects::new(),
+            environment: Arc::new(Gradient::default()),
+            lights: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn rejects_bad_values() {
+        for args in &[
+            &["--width", "1"][..],
+            &["--samples", "0"],
+            &["--samples", "-3"],
+            &["--threads", "0"],
+            &["--vfov", "180"],
+            &["--aperture", "-1"],
+            &["--focus-dist", "0"],
+            &["--exposure", "inf"],
+            &["--look-from", "1,2"],
+            &["--tonemap", "nope"],
+            &["--integrator", "nope"],
+            &["--ao-distance", "0"],
+        ] {
+            assert!(parse(args).is_err(), "accepted {:?}", args);
+        }
+    }
+
+    #[test]
+    fn overrides_the_scene() {
+        let opts = parse(&["--width", "64", "--look-at", "1, 2, 3", "--exposure", "-1"]).unwrap();
+        let mut scene = empty_scene();
+        scene.render.height = 32;
+        opts.apply(&mut scene).unwrap();
+        assert_eq!(scene.render.width, 64);
+        assert_eq!(scene.render.height, 32);
+        assert_eq!(scene.camera.look_at, Point3::new(1., 2., 3.));
+        assert!((opts.exposure + 1.).abs() < 1e-12);
+
+        // The default up direction is +y, so the camera can't look down.
+        let opts = parse(&["--look-from", "0,5,0", "--look-at", "0,0,0"]).unwrap();
+        assert!(opts.apply(&mut empty_scene()).is_err());
+        let opts = parse(&["--look-from", "1,1,1", "--look-at", "1,1,1"]).unwrap();
+        assert!(opts.apply(&mut empty_scene()).is_err());
+    }
+}